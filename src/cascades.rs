@@ -5,66 +5,457 @@ pub mod sourcenode;
 pub mod operator;
 pub mod util;
 pub mod constants;
+pub mod cost_model;
+pub mod search_config;
+pub mod physical;
 pub mod expression_utils;
+pub mod memo_dump;
+pub mod memo_graph;
+pub mod conflict_detector;
+pub mod pattern;
 pub mod test_utils;
 
-use rulematcher::RuleMatcher;
+use cost_model::{ColumnStats, CostModel, SelectivityFn, StatsProvider};
+use rulematcher::{GroupCostedCallback, RuleMatcher, RuleTrace};
+use search_config::{ExploreOrder, SearchConfig, SearchMode, TieBreak};
+use std::time::Duration;
 use group::Group;
-use mexpr::MExpr;
+use memo_dump::MemoDump;
+use memo_graph::MemoGraph;
+use mexpr::{MExpr, MemoKey};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
 use std::rc::Rc;
 use std::cell::RefCell;
 use ahash::AHashMap; // Using ahash for better performance
+use datafusion::prelude::SessionContext;
+use datafusion_common::{DataFusionError, plan_err};
 use datafusion_expr::LogicalPlan;
+use log::debug;
+use crate::join_graph::JoinGraph;
 
 #[derive(Debug)]
 pub struct Cascades {
     // Using AHashMap (high-performance HashMap) with u32 keys (hash values) and Arc<Group> values
     // Arc provides shared ownership similar to Java's reference semantics
-    memo: AHashMap<u64, Rc<RefCell<Group>>>, // Updated to use u64 for hash keys
+    memo: Memo,
     rulematcher: RuleMatcher,
+    cost_model: CostModel,
+    persisted_subplans: Option<SubplanIndex>, // Set once persist_subplans() has been called
+    strict_no_cross_join: bool,
+    baseline_plan: Option<LogicalPlan>, // The last seeded plan as-is, costed on request by baseline_cost()
+    shares_subtrees: bool,              // Whether the last seeded plan repeats a subtree, see shares_subtrees()
+    reused_groups: usize,               // Groups the last warm start carried over, see reoptimize_with_memo()
 }
 
-impl Cascades {
-    pub fn default() -> Self {
-        let memo = AHashMap::new();
-        let rulematcher = RuleMatcher::default();
+/// Groups keyed on the memo keys of their mexprs, see RuleMatcher::set_structural_keys
+pub type Memo = AHashMap<MemoKey, Rc<RefCell<Group>>>;
+
+/// Explored join groups keyed on the hashes of the non-join groups at the leaves of their join trees, and on the
+/// canonicalized predicates of the joins between them
+type SubplanIndex = AHashMap<(BTreeSet<u64>, BTreeSet<String>), Rc<RefCell<Group>>>;
+
+impl Default for Cascades {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a Cascades optimizer with a non-default cost model or search configuration
+#[derive(Debug, Default)]
+pub struct CascadesBuilder {
+    cost_model: CostModel,
+    config: SearchConfig,
+}
+
+impl CascadesBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cost_model(mut self, cost_model: CostModel) -> Self {
+        self.cost_model = cost_model;
+        self
+    }
+
+    pub fn search_mode(mut self, search_mode: SearchMode) -> Self {
+        self.config.search_mode = search_mode;
+        self
+    }
+
+    pub fn allow_bushy(mut self, allow_bushy: bool) -> Self {
+        self.config.allow_bushy = allow_bushy;
+        self
+    }
+
+    pub fn explore_order(mut self, explore_order: ExploreOrder) -> Self {
+        self.config.explore_order = explore_order;
+        self
+    }
+
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.config.tie_break = tie_break;
+        self
+    }
 
-        Self {
-            memo,
-            rulematcher,
+    pub fn max_assoc_depth(mut self, max_assoc_depth: usize) -> Self {
+        self.config.max_assoc_depth = Some(max_assoc_depth);
+        self
+    }
+
+    pub fn max_groups(mut self, max_groups: usize) -> Self {
+        self.config.max_groups = Some(max_groups);
+        self
+    }
+
+    pub fn max_alternatives_per_group(mut self, max_alternatives_per_group: usize) -> Self {
+        self.config.max_alternatives_per_group = Some(max_alternatives_per_group);
+        self
+    }
+
+    pub fn budget(mut self, budget: Duration) -> Self {
+        self.config.budget = Some(budget);
+        self
+    }
+
+    pub fn build(self) -> Cascades {
+        Cascades {
+            memo: AHashMap::new(),
+            rulematcher: RuleMatcher::new(self.config),
+            cost_model: self.cost_model,
+            persisted_subplans: None,
+            strict_no_cross_join: false,
+            baseline_plan: None,
+            shares_subtrees: false,
+            reused_groups: 0,
         }
     }
+}
+
+impl Cascades {
+    pub fn new() -> Self {
+        CascadesBuilder::new().build()
+    }
+
+    pub fn builder() -> CascadesBuilder {
+        CascadesBuilder::new()
+    }
+
+    /// Make optimize_plan fail when the cheapest plan cross joins relations that the query's predicates connect
+    pub fn with_strict_no_cross_join(mut self, strict_no_cross_join: bool) -> Self {
+        self.strict_no_cross_join = strict_no_cross_join;
+        self
+    }
+
+    /// Break ties between equal cost mexprs by `seed` instead of by exploration order, to study plan stability
+    pub fn with_tiebreak_seed(mut self, seed: u64) -> Self {
+        self.rulematcher.set_tiebreak_seed(seed);
+        self
+    }
+
+    /// Store a single, canonical, orientation of every inner join rather than each join and its commuted form, roughly
+    /// halving the mexprs in the memo. The optimal plan is unchanged, see SearchConfig::implicit_commutativity
+    pub fn with_implicit_commutativity(mut self) -> Self {
+        self.rulematcher.set_implicit_commutativity(true);
+        self
+    }
+
+    /// Key the memo on the exact content of mexprs rather than on their content hash, to tell whether two mexprs
+    /// sharing a group are a hash collision. Slower, call this before seeding the memo
+    pub fn with_structural_keys(mut self) -> Self {
+        self.rulematcher.set_structural_keys(true);
+        self
+    }
+
+    pub fn search_config(&self) -> &SearchConfig {
+        self.rulematcher.config()
+    }
+
+    /// Collect a RuleTrace for every transformation rule firing during optimization
+    pub fn on_rule_fired(&mut self, on_rule_fired: Box<dyn FnMut(RuleTrace)>) {
+        self.rulematcher.set_on_rule_fired(on_rule_fired);
+    }
+
+    /// Report the sources and min cost of every group as soon as it is costed, e.g. to show the best known plan
+    /// tightening while a long optimization runs. The root group is reported last
+    pub fn on_group_costed(&mut self, on_group_costed: GroupCostedCallback) {
+        self.rulematcher.set_on_group_costed(on_group_costed);
+    }
+
+    /// Number of mexprs costed while exploring, over every optimization run so far
+    pub fn costed_mexprs(&self) -> usize {
+        self.rulematcher.costed_mexprs()
+    }
+
+    /// Time spent per phase of the searches run so far, see RuleMatcher::phase_timings for the phases
+    pub fn phase_timings(&self) -> HashMap<&'static str, Duration> {
+        self.rulematcher.phase_timings().clone()
+    }
 
     pub fn optimize(&mut self, root_group: Rc<RefCell<Group>>) {
-        self.rulematcher.explore(root_group, &mut self.memo); 
+        self.rulematcher.begin_search(&self.memo);
+        self.rulematcher
+            .explore(Rc::clone(&root_group), &mut self.memo, &self.cost_model, f64::INFINITY);
+        physical::implement_group(&root_group, &self.cost_model, &mut HashSet::new());
+        self.record_persisted_subplans();
+    }
+
+    /// Like optimize, but pick the plan with the lowest high bound of its cost band, the lowest worst-case cost,
+    /// rather than the lowest mid-point cost. The costs of the explored groups are their high bounds afterwards
+    pub fn optimize_risk_averse(&mut self, root_group: Rc<RefCell<Group>>) {
+        self.cost_model.set_risk_averse(true);
+        self.optimize(root_group);
+        self.cost_model.set_risk_averse(false);
+    }
+
+    /// Like optimize, but hash joins whose build side is estimated to take up more than `max_bytes` in memory are not
+    /// picked for the physical plan, a merge or nested loop join is used instead
+    pub fn optimize_within_memory(&mut self, root_group: Rc<RefCell<Group>>, max_bytes: u64) {
+        self.cost_model.set_memory_budget(Some(max_bytes));
+        self.optimize(root_group);
+        self.cost_model.set_memory_budget(None);
+    }
+
+    /// Optimize `new_plan`, a modified version of the query `prev` optimized, e.g. with a predicate added, starting
+    /// from the memo of `prev`. Groups are keyed on their content, so a group of `new_plan` over the same sources and
+    /// predicates as an explored group of `prev` is that group and is not explored again, only the groups covering the
+    /// modified part of the query are. The groups of `prev` the new query no longer reaches cover the old version of
+    /// the modified part, so their costs no longer apply and they are dropped from the memo. Returns the optimizer,
+    /// see reused_groups(), and the root group of `new_plan`
+    pub fn reoptimize_with_memo(prev: Cascades, new_plan: &LogicalPlan) -> (Cascades, Rc<RefCell<Group>>) {
+        let mut cascades = prev;
+        let prev_groups: HashSet<*const RefCell<Group>> = cascades
+            .memo
+            .values()
+            .filter(|group| group.borrow().is_explored())
+            .map(Rc::as_ptr)
+            .collect();
+
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(new_plan.clone())));
+        cascades.optimize(Rc::clone(&root_group));
+
+        let mut reachable = HashSet::new();
+        Self::collect_reachable_groups(&root_group, &mut reachable);
+        cascades.memo.retain(|_, group| reachable.contains(&Rc::as_ptr(group)));
+        cascades.reused_groups = reachable.intersection(&prev_groups).count();
+        debug!("Warm start reused {} of {} groups", cascades.reused_groups, reachable.len());
+        (cascades, root_group)
+    }
+
+    /// Number of explored groups of the previous memo the last reoptimize_with_memo() kept for the new query
+    pub fn reused_groups(&self) -> usize {
+        self.reused_groups
+    }
+
+    fn collect_reachable_groups(group: &Rc<RefCell<Group>>, reachable: &mut HashSet<*const RefCell<Group>>) {
+        if !reachable.insert(Rc::as_ptr(group)) {
+            return;
+        }
+        let mut mexprs = group.borrow().equivalent_logical_mexprs.borrow().clone();
+        mexprs.extend(group.borrow().start_expression.clone());
+        for mexpr in &mexprs {
+            for operand in mexpr.operands() {
+                Self::collect_reachable_groups(operand, reachable);
+            }
+        }
     }
 
-    fn gen_or_get_from_memo(&mut self, plan_mexpr: MExpr) -> Rc<RefCell<Group>> {
-        let hash = plan_mexpr.hash();
+    /// Seed, optimize and return the cheapest logical plan for `plan`
+    pub fn optimize_plan(&mut self, plan: &LogicalPlan) -> Result<LogicalPlan, DataFusionError> {
+        let root_group = self.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+        self.optimize(Rc::clone(&root_group));
+        let cheapest = util::get_cheapest_logical_plan(&root_group)?;
+
+        if self.strict_no_cross_join
+            && let Some((left, right, predicate)) = util::find_unjustified_cross_join(plan, &cheapest)
+        {
+            return plan_err!(
+                "Cross join between {} and {} although they are connected by {}",
+                util::format_sources(&left),
+                util::format_sources(&right),
+                predicate
+            );
+        }
+        Ok(cheapest)
+    }
+
+    /// Seed, optimize and return the cheapest plan for `plan` that joins the relations named by `order` in that order
+    /// from left to right, however its joins are nested, e.g. to honor a join hint or to compare a given order with
+    /// the free search. The search is run unconstrained, then the mexprs out of order are dropped from the memo and
+    /// the remaining ones are recosted, so the memo only holds plans following the hint afterwards
+    pub fn optimize_with_hint(&mut self, plan: &LogicalPlan, order: Vec<String>) -> Result<LogicalPlan, DataFusionError> {
+        let root_group = self.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+        self.rulematcher.begin_search(&self.memo);
+        self.rulematcher
+            .explore(Rc::clone(&root_group), &mut self.memo, &self.cost_model, f64::INFINITY);
+
+        for group in self.memo.values() {
+            group
+                .borrow()
+                .equivalent_logical_mexprs
+                .borrow_mut()
+                .retain(|mexpr| util::follows_join_order(mexpr, &order));
+        }
+        self.recost();
+        self.record_persisted_subplans();
+
+        if root_group.borrow().cheapest_logical_expression.is_none() {
+            return plan_err!("No plan joins the relations in the order {}", order.join(", "));
+        }
+        util::get_cheapest_logical_plan(&root_group)
+    }
+
+    /// Check that `plan` is in scope for the optimizer, made only of join types the conflict detector knows and node
+    /// types the memo can be seeded with. Otherwise returns the reasons it cannot be fully reordered, so callers can fall back to
+    /// DataFusion's own optimizer
+    pub fn is_reorderable(plan: &LogicalPlan) -> Result<(), Vec<String>> {
+        let reasons = util::get_non_reorderable_reasons(plan);
+        if reasons.is_empty() { Ok(()) } else { Err(reasons) }
+    }
+
+    /// Keep explored join groups around for the plans seeded after this call, so a join over the same inputs as an
+    /// already optimized join reuses its group instead of being explored again, even if the join tree is seeded in a
+    /// different shape. The join trees also have to join the inputs on the same predicates, as written
+    pub fn persist_subplans(&mut self) {
+        self.persisted_subplans.get_or_insert_with(AHashMap::new);
+        self.record_persisted_subplans();
+    }
+
+    fn record_persisted_subplans(&mut self) {
+        let Some(persisted_subplans) = self.persisted_subplans.as_mut() else {
+            return;
+        };
+        for group in self.memo.values() {
+            let is_explored_join = {
+                let group_borrowed = group.borrow();
+                group_borrowed.is_explored()
+                    && group_borrowed
+                        .start_expression
+                        .as_ref()
+                        .is_some_and(|expr| matches!(*expr.op().borrow(), LogicalPlan::Join(_)))
+            };
+            if is_explored_join {
+                persisted_subplans
+                    .entry((util::get_join_leaf_hashes(group), util::get_join_tree_predicates(group)))
+                    .or_insert_with(|| Rc::clone(group));
+            }
+        }
+    }
+
+    /// Optimize with the search primed by a greedy minimum-selectivity spanning tree plan
+    /// The spanning tree plan is costed first and that cost is used as the upper bound for exploration, so that
+    /// join orders costlier than it are never expanded into further join orders
+    pub fn optimize_with_mst_seed(
+        &mut self,
+        root_group: Rc<RefCell<Group>>,
+        plan: &LogicalPlan,
+    ) -> Result<(), DataFusionError> {
+        let upper_bound = match JoinGraph::from_plan(plan)?.build_min_selectivity_spanning_tree()? {
+            Some(mst_plan) => self.cost_of_plan(&mst_plan),
+            None => f64::INFINITY,
+        };
+        debug!("Using the spanning tree plan cost {} as the upper bound", upper_bound);
+
+        self.rulematcher.begin_search(&self.memo);
+        self.rulematcher
+            .explore(Rc::clone(&root_group), &mut self.memo, &self.cost_model, upper_bound);
+        physical::implement_group(&root_group, &self.cost_model, &mut HashSet::new());
+        self.record_persisted_subplans();
+        Ok(())
+    }
+
+    /// Cost a plan with our cost model, as-is and without applying any rules
+    /// The plan is seeded into a throwaway memo, the memo being optimized is left untouched. Useful to compare an
+    /// externally provided plan, such as DataFusion's, with the plan picked by the optimizer
+    pub fn cost_of_plan(&self, plan: &LogicalPlan) -> f64 {
+        let mut scratch_memo = AHashMap::new();
+        let group = Self::gen_group_in_memo(
+            &mut scratch_memo,
+            None,
+            &self.rulematcher,
+            false,
+            Rc::new(RefCell::new(plan.clone())),
+        );
+        self.rulematcher.cost_without_rules(&group, &self.cost_model);
+        group.borrow().get_group_cost()
+    }
+
+    /// Re-cost the already explored memo after a change to the statistics, without applying any transformation rules
+    /// The logical search space is unchanged, only the costs and the cheapest logical and physical expressions of every
+    /// group are updated
+    pub fn recost(&mut self) {
+        self.cost_model.clear_selectivity_cache();
+        let mut recosted = HashSet::new();
+        for group in self.memo.values() {
+            self.rulematcher.recost(group, &self.cost_model, &mut recosted);
+        }
+
+        let mut implemented = HashSet::new();
+        for group in self.memo.values() {
+            if group.borrow().is_explored() {
+                physical::implement_group(group, &self.cost_model, &mut implemented);
+            }
+        }
+    }
+
+    /// Declare the sort order a source table's rows are stored in, so merge joins can skip sorting it
+    pub fn set_table_ordering(&mut self, table: &str, columns: &[&str]) {
+        self.cost_model.set_table_ordering(table, columns);
+    }
+
+    /// Declare the primary key of a source table, so joins on it can be index nested loop joins probing its index
+    pub fn set_table_key(&mut self, table: &str, columns: &[&str]) {
+        self.cost_model.set_table_key(table, columns);
+    }
+
+    /// Pin the estimated output row count of the group covering exactly `sources`, e.g. from an external
+    /// cardinality estimator, instead of deriving it from selectivities
+    pub fn set_cardinality_override(&mut self, sources: BTreeSet<String>, rows: u64) {
+        self.cost_model.set_cardinality_override(sources, rows);
+    }
+
+    /// Consult `stats_provider` for table row counts and column NDVs not set on the cost model
+    pub fn set_stats_provider(&mut self, stats_provider: Box<dyn StatsProvider>) {
+        self.cost_model.set_stats_provider(stats_provider);
+    }
+
+    /// Estimate the selectivity of `column = column` join keys with `selectivity_fn`, from the predicate and the stats
+    /// of both columns, rather than from NDVs or the pre-canned selectivities. Must be deterministic
+    pub fn register_selectivity_fn(&mut self, selectivity_fn: SelectivityFn) {
+        self.cost_model.set_selectivity_fn(selectivity_fn);
+    }
+
+    /// Register statistics for a column of a source table, used by the cost model for row count estimates
+    pub fn set_column_stats(&mut self, table: &str, column: &str, stats: ColumnStats) {
+        self.cost_model.set_column_stats(table, column, stats);
+    }
+
+    fn gen_or_get_from_memo(
+        memo: &mut Memo,
+        plan_mexpr: MExpr,
+        structural_keys: bool,
+    ) -> Rc<RefCell<Group>> {
+        let key = plan_mexpr.memo_key(structural_keys);
 
         // Check if already exists in memo (HashMap lookup)
-        if let Some(group) = self.memo.get(&hash) {
+        if let Some(group) = memo.get(&key) {
             return Rc::clone(group);
         }
 
         // Create new group and add to memo
         let new_group = Group::from_mexpr(plan_mexpr);
-        self.memo.insert(hash, Rc::clone(&new_group));
+        memo.insert(key, Rc::clone(&new_group));
         new_group
     }
 
     pub fn print_memo(&self) {
         println!("Memo :");
         for (key, value) in &self.memo {
-            let sources = if let Some(ref start_expr) = value.borrow().start_expression {
-                format!("{} ", start_expr.op().borrow().display())
-            } else {
-                "Unknown".to_string()
-            };
+            let sources = value.borrow().source_set().into_iter().collect::<Vec<_>>().join(", ");
 
-            println!("{} : [{:p}, {}]",
-                key,
-                Rc::as_ptr(value),
+            println!("{} : [G{}, {{{}}}]",
+                key.digest(),
+                value.borrow().group_id,
                 sources
             );
         }
@@ -82,28 +473,259 @@ impl Cascades {
         println!("Memo capacity: {}", self.memo.capacity());
     }
 
+    /// Check the invariants an explored memo holds, returning every violation found
+    /// Every explored group has no unexplored mexprs left and its cheapest mexpr is one of its explored mexprs, at
+    /// its min cost. Every explored mexpr reads from groups in the memo that together cover the group's sources
+    pub fn validate_memo(&self) -> Result<(), Vec<String>> {
+        let groups: HashSet<*const RefCell<Group>> = self.memo.values().map(Rc::as_ptr).collect();
+        let mut violations = Vec::new();
+        let mut validated = HashSet::new();
+        for group in self.memo.values() {
+            if !validated.insert(Rc::as_ptr(group)) {
+                continue;
+            }
+            let group_borrowed = group.borrow();
+            if !group_borrowed.is_explored() {
+                continue;
+            }
+            let group_id = group_borrowed.group_id;
+            if !group_borrowed.unexplored_equivalent_logical_mexprs.borrow().is_empty() {
+                violations.push(format!("G{} is explored but has unexplored mexprs", group_id));
+            }
+
+            let mexprs = group_borrowed.equivalent_logical_mexprs.borrow();
+            match &group_borrowed.cheapest_logical_expression {
+                Some(cheapest) => {
+                    if !mexprs.iter().any(|mexpr| mexpr.hash() == cheapest.hash()) {
+                        violations.push(format!("G{} has a cheapest mexpr that is not one of its mexprs", group_id));
+                    }
+                    if cheapest.cost().is_nan() || cheapest.cost() != group_borrowed.get_group_cost() {
+                        violations.push(format!(
+                            "G{} has min cost {} but its cheapest mexpr costs {}",
+                            group_id,
+                            group_borrowed.get_group_cost(),
+                            cheapest.cost()
+                        ));
+                    }
+                }
+                None => violations.push(format!("G{} is explored but has no cheapest mexpr", group_id)),
+            }
+
+            let sources = group_borrowed.source_set();
+            for mexpr in mexprs.iter().filter(|mexpr| !mexpr.operands().is_empty()) {
+                if let Some(operand) = mexpr.operands().iter().find(|operand| !groups.contains(&Rc::as_ptr(operand))) {
+                    violations.push(format!(
+                        "Mexpr {} of G{} reads from G{} which is not in the memo",
+                        mexpr.hash(),
+                        group_id,
+                        operand.borrow().group_id
+                    ));
+                }
+                let operand_sources: BTreeSet<String> =
+                    mexpr.operands().iter().flat_map(util::get_group_sources).collect();
+                if operand_sources != sources {
+                    violations.push(format!(
+                        "Mexpr {} of G{} covers {} instead of {}",
+                        mexpr.hash(),
+                        group_id,
+                        util::format_sources(&operand_sources),
+                        util::format_sources(&sources)
+                    ));
+                }
+            }
+        }
+
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+
+    /// Explain how the plan for `root` was picked : for every group reachable from it, the sources it covers,
+    /// its min cost and each of its logical mexprs with their cost, the winner marked with a `*`
+    /// Operands are shown as group ids
+    /// Groups are ordered by their source sets and mexprs by cost, so the output is deterministic
+    pub fn explain(&self, root: Rc<RefCell<Group>>) -> String {
+        MemoDump::from_memo(&self.memo).explain(root.borrow().group_id)
+    }
+
+    /// Write the memo to `path` as JSON, to inspect a run offline with load_memo_dump
+    pub fn dump_memo(&self, path: impl AsRef<Path>) -> Result<(), DataFusionError> {
+        MemoDump::from_memo(&self.memo).write(path)
+    }
+
+    /// Write every logical mexpr in the memo to `path` as text, per group, with its cost, row count and operand group
+    /// ids, to see why a plan that did not win was costed the way it was
+    pub fn dump_all_mexprs(&self, path: impl AsRef<Path>) -> Result<(), DataFusionError> {
+        std::fs::write(path, MemoDump::from_memo(&self.memo).format_all_mexprs())?;
+        Ok(())
+    }
+
+    /// The groups of the memo and the operand references between them, as data rather than text
+    pub fn memo_graph(&self) -> MemoGraph {
+        MemoGraph::from_memo(&self.memo)
+    }
+
+    /// Read back a memo written by dump_memo. Operators are only kept in their displayed form, so the dump can be
+    /// inspected and explained but not optimized further
+    pub fn load_memo_dump(path: impl AsRef<Path>) -> Result<MemoDump, DataFusionError> {
+        MemoDump::read(path)
+    }
+
+    /// The distinct sets of source tables covered by the groups in the memo, smallest sets first
+    pub fn materialized_subsets(&self) -> Vec<BTreeSet<String>> {
+        let subsets: BTreeSet<BTreeSet<String>> =
+            self.memo.values().map(util::get_group_sources).collect();
+        let mut subsets: Vec<BTreeSet<String>> = subsets.into_iter().collect();
+        subsets.sort_by_key(|subset| subset.len());
+        subsets
+    }
+
+    /// The cheapest plan the explored memo holds over exactly the tables in `sources`, without optimizing further
+    /// None if no costed group covers that subset, e.g. because the seeded plan never joins those tables together
+    pub fn optimize_subset(&self, sources: &[String]) -> Option<util::PlanNode> {
+        let sources: BTreeSet<String> = sources.iter().cloned().collect();
+        self.memo
+            .values()
+            .filter(|group| util::get_group_sources(group) == sources)
+            .filter_map(|group| util::get_cheapest_plan(Rc::clone(group)))
+            .min_by(|left, right| cost_model::compare_costs(left.cost, right.cost))
+    }
+
+    /// The number of distinct join trees the explored memo holds for `root`, counting commuted joins as distinct
+    pub fn search_space_size(&self, root: Rc<RefCell<Group>>) -> u128 {
+        util::get_search_space_size(&root, &mut HashMap::new())
+    }
+
+    /// The `k` cheapest distinct plans the explored memo holds for `root` with their costs, cheapest first
+    /// Commuted joins count as distinct plans
+    pub fn top_k_plans(&self, root: Rc<RefCell<Group>>, k: usize) -> Vec<(util::PlanNode, f64)> {
+        util::get_top_k_plans(&root, k, &mut HashMap::new())
+            .iter()
+            .map(|plan| (plan.clone(), plan.cost))
+            .collect()
+    }
+
+    /// Execute the joins of the cheapest plan for `root` with `ctx`, and compare each join's estimated row count with
+    /// the rows it actually produces, to evaluate the quality of the estimates
+    pub async fn join_estimates(
+        &self,
+        root: Rc<RefCell<Group>>,
+        ctx: &SessionContext,
+    ) -> Result<Vec<util::JoinEstimate>, DataFusionError> {
+        util::get_join_estimates(&root, ctx).await
+    }
+
     // Getter for memo (equivalent to @Getter annotation in Java)
-    pub fn get_memo(&self) -> &AHashMap<u64, Rc<RefCell<Group>>> {
+    pub fn get_memo(&self) -> &Memo {
         &self.memo
     }
 
+    /// Seed the memo with a left-deep chain of joins over the tables named by `join_nodes`, e.g. "123" for (t1 ⋈ t2) ⋈ t3
+    pub fn seed_memo(&mut self, join_nodes: &str) -> Rc<RefCell<Group>> {
+        let plan = test_utils::build_join_chain(join_nodes);
+        self.gen_group_logical_plan(Rc::new(RefCell::new(plan)))
+    }
+
     pub fn gen_group_logical_plan(&mut self, plan: Rc<RefCell<LogicalPlan>>) -> Rc<RefCell<Group>> {
+        self.baseline_plan = Some(plan.borrow().clone());
+        self.rulematcher.set_conflict_rules(&plan.borrow());
+        for (alias, table) in util::get_table_aliases(&plan.borrow()) {
+            self.cost_model.set_table_alias(&alias, &table);
+        }
+        let root_group =
+            Self::gen_group_in_memo(&mut self.memo, self.persisted_subplans.as_ref(), &self.rulematcher, true, plan);
+        self.shares_subtrees = util::has_shared_subtree(&root_group, &mut HashSet::new());
+        root_group
+    }
+
+    /// Whether the last seeded plan repeats a subtree, e.g. a self join of the same join result
+    /// Identical subtrees are seeded into the same group, and the cost model charges a group shared by the inputs of
+    /// a join only once
+    pub fn shares_subtrees(&self) -> bool {
+        self.shares_subtrees
+    }
+
+    /// Cost of the last seeded plan in its original join order, None until a plan has been seeded
+    /// Compare with the root group's cost after optimizing to get the improvement from reordering. The plan is only
+    /// costed when this is called, with the statistics of that time
+    pub fn baseline_cost(&self) -> Option<f64> {
+        self.baseline_plan.as_ref().map(|plan| self.cost_of_plan(plan))
+    }
+
+    /// Seed a plan into the memo, with `rewrite_filters` set filters are first moved to where they don't block
+    /// reordering, otherwise the plan is seeded as-is
+    fn gen_group_in_memo(
+        memo: &mut Memo,
+        persisted_subplans: Option<&SubplanIndex>,
+        rulematcher: &RuleMatcher,
+        rewrite_filters: bool,
+        plan: Rc<RefCell<LogicalPlan>>,
+    ) -> Rc<RefCell<Group>> {
+        if rewrite_filters {
+            // A filter over a cross join is seeded as an inner join on the filter's equi predicates, so the search does
+            // not start from a cross join
+            let merged = util::merge_filter_into_cross_join(&plan.borrow());
+            if let Some(merged) = merged {
+                let merged = Rc::new(RefCell::new(merged));
+                return Self::gen_group_in_memo(memo, persisted_subplans, rulematcher, true, merged);
+            }
+
+            // A filter on single relations between two joins is seeded below the joins, so it does not block reordering
+            let pushed_down = util::push_filter_to_leaves(&plan.borrow());
+            if let Some(pushed_down) = pushed_down {
+                let pushed_down = Rc::new(RefCell::new(pushed_down));
+                return Self::gen_group_in_memo(memo, persisted_subplans, rulematcher, true, pushed_down);
+            }
+        }
+
+        let mut gen_input = |input: &LogicalPlan| {
+            let input = Rc::new(RefCell::new(input.clone()));
+            Self::gen_group_in_memo(memo, persisted_subplans, rulematcher, rewrite_filters, input)
+        };
         let operands: Vec<Rc<RefCell<Group>>> = match &*plan.borrow() {
-            LogicalPlan::Projection(proj) => vec![
-                self.gen_group_logical_plan(Rc::new(RefCell::new(proj.input.as_ref().clone())))
-            ],
-            LogicalPlan::Filter(filter) => vec![
-                self.gen_group_logical_plan(Rc::new(RefCell::new(filter.input.as_ref().clone())))
-            ],
-            LogicalPlan::Join(join) => vec![
-                self.gen_group_logical_plan(Rc::new(RefCell::new(join.left.as_ref().clone()))),
-                self.gen_group_logical_plan(Rc::new(RefCell::new(join.right.as_ref().clone()))),
-            ],
-            LogicalPlan::TableScan(_) => vec![],
+            LogicalPlan::Projection(proj) => vec![gen_input(&proj.input)],
+            LogicalPlan::Filter(filter) => vec![gen_input(&filter.input)],
+            // The alias group keeps the alias-qualified schema, so columns above it still resolve against it
+            LogicalPlan::SubqueryAlias(alias) => vec![gen_input(&alias.input)],
+            // The join tree below an aggregate is its own group, and is reordered independently of the aggregate
+            LogicalPlan::Aggregate(agg) => vec![gen_input(&agg.input)],
+            LogicalPlan::Distinct(distinct) => vec![gen_input(distinct.input())],
+            LogicalPlan::Window(window) => vec![gen_input(&window.input)],
+            LogicalPlan::Sort(sort) => {
+                let input = gen_input(&sort.input);
+                if let Some(required_ordering) = physical::get_sort_ordering(sort) {
+                    physical::require_ordering(&input, required_ordering);
+                }
+                vec![input]
+            }
+            LogicalPlan::Join(join) => vec![gen_input(&join.left), gen_input(&join.right)],
+            LogicalPlan::TableScan(_) | LogicalPlan::EmptyRelation(_) | LogicalPlan::Values(_) => vec![],
             _ => unimplemented!("Support for this LogicalPlan variant is not yet implemented"),
         };
 
-        let mexpr = MExpr::build_with_node(plan, operands);
-        self.gen_or_get_from_memo(mexpr)
+        // With implicit commutativity a join is seeded with its inputs in the order the rules would store it in
+        let mexpr = rulematcher.canonicalize_join_inputs(MExpr::build_with_node(plan, operands));
+        let structural_keys = rulematcher.config().structural_keys;
+
+        // A join over the same inputs and predicates as a persisted join group is that group, whatever the shape of
+        // its join tree
+        let key = mexpr.memo_key(structural_keys);
+        if let Some(persisted_subplans) = persisted_subplans
+            && !memo.contains_key(&key)
+            && let LogicalPlan::Join(join) = &*mexpr.op().borrow()
+        {
+            let leaf_hashes: BTreeSet<u64> = mexpr
+                .operands()
+                .iter()
+                .flat_map(util::get_join_leaf_hashes)
+                .collect();
+            let mut predicates: BTreeSet<String> = MExpr::canonicalize_join_predicates(join).into_iter().collect();
+            predicates.extend(mexpr.operands().iter().flat_map(util::get_join_tree_predicates));
+            if let Some(group) = persisted_subplans.get(&(leaf_hashes, predicates)) {
+                debug!("Reusing the persisted group for join mexpr {}", mexpr.hash());
+                memo.insert(key, Rc::clone(group));
+                return Rc::clone(group);
+            }
+        }
+
+        Self::gen_or_get_from_memo(memo, mexpr, structural_keys)
     }
 }
@@ -0,0 +1,381 @@
+use super::expression_utils::flip_equality;
+use datafusion_common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion_common::{DFSchemaRef, JoinType, NullEquality};
+use datafusion_expr::utils::split_conjunction;
+use datafusion_expr::{BinaryExpr, Expr, Join, LogicalPlan, Operator};
+use std::collections::BTreeSet;
+
+/// Conflict rule `T1 → T2` : a placement of the operator whose inputs hold a table of T1 has to hold all of T2
+pub type ConflictRule = (BTreeSet<String>, BTreeSet<String>);
+
+/// A join of a seeded plan, with the sets its placements are checked against
+/// See Moerkotte, Fender and Eich, "On the correct and complete enumeration of the core search space" (CD-C)
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinOperator {
+    pub join_type: JoinType,
+    /// Tables below the operator's left and right inputs in the seeded plan
+    pub left_tables: BTreeSet<String>,
+    pub right_tables: BTreeSet<String>,
+    /// Syntactic eligibility set, the tables the operator's predicate references
+    pub ses: BTreeSet<String>,
+    /// Total eligibility set, the SES plus the tables the conflict rules force in whatever the placement
+    pub tes: BTreeSet<String>,
+    pub conflict_rules: Vec<ConflictRule>,
+    /// Equi keys as `left = right` plus the conjuncts of the filter
+    predicates: Vec<Expr>,
+    /// Whether the predicate is false on nulls, see rejects_nulls()
+    rejects_nulls: bool,
+}
+
+/// The join operators of the seeded plan, deciding which reorderings of non-inner joins are valid
+/// Plans of inner joins only record nothing, and every placement is valid
+#[derive(Debug, Clone, Default)]
+pub struct ConflictDetector {
+    operators: Vec<JoinOperator>,
+}
+
+/// A non-join input of a join tree, with the tables below it
+struct Leaf {
+    tables: BTreeSet<String>,
+    schema: DFSchemaRef,
+}
+
+/// Join types the conflict rules are known for, joins of other types are not moved relative to their neighbours
+pub fn is_reorderable_join_type(join_type: JoinType) -> bool {
+    matches!(
+        join_type,
+        JoinType::Inner | JoinType::Left | JoinType::LeftSemi | JoinType::LeftAnti | JoinType::Full
+    )
+}
+
+fn is_commutative(join_type: JoinType) -> bool {
+    matches!(join_type, JoinType::Inner | JoinType::Full)
+}
+
+// The conditional entries of the paper's tables need a predicate rejecting nulls on one side, we check that both
+// predicates reject nulls instead
+
+/// (A ◦a B) ◦b C ≡ A ◦a (B ◦b C)
+fn is_associative(a: &JoinOperator, b: &JoinOperator) -> bool {
+    use JoinType::*;
+    match (a.join_type, b.join_type) {
+        (Inner, Inner | LeftSemi | LeftAnti | Left) => true,
+        (Left | Full, Left) | (Full, Full) => a.rejects_nulls && b.rejects_nulls,
+        _ => false,
+    }
+}
+
+/// (A ◦a B) ◦b C ≡ (A ◦b C) ◦a B
+fn is_left_asscom(a: &JoinOperator, b: &JoinOperator) -> bool {
+    use JoinType::*;
+    match (a.join_type, b.join_type) {
+        (Inner | LeftSemi | LeftAnti | Left, Inner | LeftSemi | LeftAnti | Left) => true,
+        (Left, Full) | (Full, Left) | (Full, Full) => a.rejects_nulls && b.rejects_nulls,
+        _ => false,
+    }
+}
+
+/// A ◦a (B ◦b C) ≡ B ◦b (A ◦a C)
+fn is_right_asscom(a: &JoinOperator, b: &JoinOperator) -> bool {
+    use JoinType::*;
+    match (a.join_type, b.join_type) {
+        (Inner, Inner) => true,
+        (Full, Full) => a.rejects_nulls && b.rejects_nulls,
+        _ => false,
+    }
+}
+
+/// `T1 → T2` where T2 is the part of `tables` the operator's predicate references, or all of `tables` if it
+/// references none of them
+fn get_conflict_rule(from: &BTreeSet<String>, tables: &BTreeSet<String>, ses: &BTreeSet<String>) -> ConflictRule {
+    let referenced: BTreeSet<String> = tables.intersection(ses).cloned().collect();
+    let to = if referenced.is_empty() { tables.clone() } else { referenced };
+    (from.clone(), to)
+}
+
+fn get_join_predicates(join: &Join) -> Vec<Expr> {
+    let mut predicates: Vec<Expr> = join
+        .on
+        .iter()
+        .map(|(left, right)| {
+            Expr::BinaryExpr(BinaryExpr::new(Box::new(left.clone()), Operator::Eq, Box::new(right.clone())))
+        })
+        .collect();
+    if let Some(filter) = &join.filter {
+        predicates.extend(split_conjunction(filter).into_iter().cloned());
+    }
+    predicates
+}
+
+/// Whether a join predicate is never true on nulls, taken to hold when each of its conjuncts compares two columns and
+/// its equi keys do not match nulls to each other
+fn rejects_nulls(join: &Join) -> bool {
+    let is_column_comparison = |predicate: &Expr| match predicate {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            matches!(
+                op,
+                Operator::Eq | Operator::NotEq | Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq
+            ) && matches!(**left, Expr::Column(_))
+                && matches!(**right, Expr::Column(_))
+        }
+        _ => false,
+    };
+    let predicates = get_join_predicates(join);
+    !predicates.is_empty()
+        && (join.on.is_empty() || join.null_equality == NullEquality::NullEqualsNothing)
+        && predicates.iter().all(is_column_comparison)
+}
+
+fn get_table_names(plan: &LogicalPlan) -> BTreeSet<String> {
+    let mut tables = BTreeSet::new();
+    plan.apply(|node| {
+        if let LogicalPlan::TableScan(scan) = node {
+            tables.insert(scan.table_name.to_string());
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })
+    .expect("visiting a logical plan does not fail");
+    tables
+}
+
+impl JoinOperator {
+    pub fn tables(&self) -> BTreeSet<String> {
+        self.left_tables.union(&self.right_tables).cloned().collect()
+    }
+
+    /// Whether `join` evaluates this operator's predicate, whichever way round its equi keys are
+    fn has_predicates_of(&self, join: &Join) -> bool {
+        let predicates = get_join_predicates(join);
+        predicates.len() == self.predicates.len()
+            && predicates
+                .iter()
+                .all(|predicate| {
+                    self.predicates.contains(predicate) || self.predicates.contains(&flip_equality(predicate))
+                })
+    }
+
+    /// Whether the inputs straddle the operator's predicate
+    fn is_straddled_by(&self, left: &BTreeSet<String>, right: &BTreeSet<String>) -> bool {
+        !self.ses.is_disjoint(left)
+            && !self.ses.is_disjoint(right)
+            && self.ses.iter().all(|table| left.contains(table) || right.contains(table))
+    }
+
+    fn satisfies_conflict_rules(&self, tables: &BTreeSet<String>) -> bool {
+        self.conflict_rules.iter().all(|(from, to)| from.is_disjoint(tables) || to.is_subset(tables))
+    }
+
+    /// Whether the operator can be applied between inputs over `left` and `right`
+    fn can_place(&self, left: &BTreeSet<String>, right: &BTreeSet<String>) -> bool {
+        let tables: BTreeSet<String> = left.union(right).cloned().collect();
+        let tes_left: BTreeSet<String> = self.tes.intersection(&self.left_tables).cloned().collect();
+        let tes_right: BTreeSet<String> = self.tes.intersection(&self.right_tables).cloned().collect();
+        let is_ordered = |l: &BTreeSet<String>, r: &BTreeSet<String>| tes_left.is_subset(l) && tes_right.is_subset(r);
+        self.satisfies_conflict_rules(&tables)
+            && (is_ordered(left, right) || (is_commutative(self.join_type) && is_ordered(right, left)))
+    }
+}
+
+impl ConflictDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the join operators of a seeded plan, nothing if all of its joins are inner joins
+    pub fn add_plan(&mut self, plan: &LogicalPlan) {
+        let has_non_inner_join = plan
+            .exists(|node| Ok(matches!(node, LogicalPlan::Join(join) if join.join_type != JoinType::Inner)))
+            .expect("visiting a logical plan does not fail");
+        if has_non_inner_join {
+            self.add_join_trees(plan);
+        }
+    }
+
+    pub fn operators(&self) -> &[JoinOperator] {
+        &self.operators
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operators.is_empty()
+    }
+
+    /// Whether `join` can be applied between inputs over the `left` and `right` tables
+    /// The join is matched to the seeded operator evaluating the same predicate, else to the operators of its type
+    /// whose predicate it straddles. A join matching neither, e.g. a cross product, only has to satisfy the conflict
+    /// rules of the operators above it
+    pub fn is_applicable(&self, join: &Join, left: &BTreeSet<String>, right: &BTreeSet<String>) -> bool {
+        let same_type = || self.operators.iter().filter(|op| op.join_type == join.join_type);
+        let exact: Vec<&JoinOperator> = same_type().filter(|op| op.has_predicates_of(join)).collect();
+        if !exact.is_empty() {
+            return exact.iter().all(|op| op.can_place(left, right));
+        }
+
+        let straddled: Vec<&JoinOperator> = same_type().filter(|op| op.is_straddled_by(left, right)).collect();
+        if !straddled.is_empty() {
+            return straddled.iter().all(|op| op.can_place(left, right));
+        }
+
+        let tables: BTreeSet<String> = left.union(right).cloned().collect();
+        self.operators
+            .iter()
+            .filter(|op| tables.is_subset(&op.tables()))
+            .all(|op| op.satisfies_conflict_rules(&tables))
+    }
+
+    fn add_join_trees(&mut self, plan: &LogicalPlan) {
+        if matches!(plan, LogicalPlan::Join(_)) {
+            self.add_join_tree(plan);
+        } else {
+            plan.inputs().into_iter().for_each(|input| self.add_join_trees(input));
+        }
+    }
+
+    /// Add the operators of the join tree rooted at `plan`, returning their indexes and the leaves of the tree
+    fn add_join_tree(&mut self, plan: &LogicalPlan) -> (Vec<usize>, Vec<Leaf>) {
+        let LogicalPlan::Join(join) = plan else {
+            self.add_join_trees(plan);
+            let leaf = Leaf {
+                tables: get_table_names(plan),
+                schema: plan.schema().clone(),
+            };
+            return (Vec::new(), vec![leaf]);
+        };
+
+        let (left_ops, left_leaves) = self.add_join_tree(&join.left);
+        let (right_ops, right_leaves) = self.add_join_tree(&join.right);
+        let collect_tables = |leaves: &[Leaf]| leaves.iter().flat_map(|leaf| leaf.tables.iter().cloned()).collect();
+        let predicates = get_join_predicates(join);
+
+        let mut ses = BTreeSet::new();
+        for column in predicates.iter().flat_map(|predicate| predicate.column_refs()) {
+            for leaf in left_leaves.iter().chain(&right_leaves).filter(|leaf| leaf.schema.has_column(column)) {
+                ses.extend(leaf.tables.iter().cloned());
+            }
+        }
+
+        let mut op = JoinOperator {
+            join_type: join.join_type,
+            left_tables: collect_tables(&left_leaves),
+            right_tables: collect_tables(&right_leaves),
+            tes: ses.clone(),
+            ses,
+            conflict_rules: Vec::new(),
+            rejects_nulls: rejects_nulls(join),
+            predicates,
+        };
+
+        for child in left_ops.iter().map(|i| &self.operators[*i]) {
+            if !is_associative(child, &op) {
+                op.conflict_rules.push(get_conflict_rule(&child.right_tables, &child.left_tables, &child.ses));
+            }
+            if !is_left_asscom(child, &op) {
+                op.conflict_rules.push(get_conflict_rule(&child.left_tables, &child.right_tables, &child.ses));
+            }
+        }
+        for child in right_ops.iter().map(|i| &self.operators[*i]) {
+            if !is_associative(&op, child) {
+                op.conflict_rules.push(get_conflict_rule(&child.left_tables, &child.right_tables, &child.ses));
+            }
+            if !is_right_asscom(&op, child) {
+                op.conflict_rules.push(get_conflict_rule(&child.right_tables, &child.left_tables, &child.ses));
+            }
+        }
+
+        // Any placement holds the TES, so the rules it triggers hold their right hand side too
+        loop {
+            let forced: BTreeSet<String> = op
+                .conflict_rules
+                .iter()
+                .filter(|(from, _)| !from.is_disjoint(&op.tes))
+                .flat_map(|(_, to)| to.iter().cloned())
+                .collect();
+            if forced.is_subset(&op.tes) {
+                break;
+            }
+            op.tes.extend(forced);
+        }
+
+        let mut indexes: Vec<usize> = left_ops.into_iter().chain(right_ops).collect();
+        match self.operators.iter().position(|existing| *existing == op) {
+            Some(index) => indexes.push(index),
+            None => {
+                indexes.push(self.operators.len());
+                self.operators.push(op);
+            }
+        }
+        (indexes, left_leaves.into_iter().chain(right_leaves).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cascades::test_utils::build_table_scan;
+    use datafusion_expr::{LogicalPlanBuilder, col};
+
+    fn tables(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// (a ◦ab b) ◦bc c
+    fn build_plan(ab: JoinType, bc: JoinType) -> LogicalPlan {
+        let scan = |name: &str| build_table_scan(name, &[&format!("{}1", name)], Some(1000));
+        LogicalPlanBuilder::from(scan("a"))
+            .join_on(scan("b"), ab, vec![col("a.a1").eq(col("b.b1"))])
+            .unwrap()
+            .join_on(scan("c"), bc, vec![col("b.b1").eq(col("c.c1"))])
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    fn get_joins(plan: &LogicalPlan) -> (Join, Join) {
+        let LogicalPlan::Join(top) = plan else {
+            panic!("not a join");
+        };
+        let LogicalPlan::Join(nested) = &*top.left else {
+            panic!("not a join");
+        };
+        (nested.clone(), top.clone())
+    }
+
+    #[test]
+    fn test_outer_join_associativity() {
+        // (A ⟕ab B) ⟕bc C ≡ A ⟕ab (B ⟕bc C), p_bc rejects nulls on B
+        let plan = build_plan(JoinType::Left, JoinType::Left);
+        let mut detector = ConflictDetector::new();
+        detector.add_plan(&plan);
+        assert_eq!(detector.operators().len(), 2);
+
+        let (ab, bc) = get_joins(&plan);
+        assert!(detector.is_applicable(&bc, &tables(&["b"]), &tables(&["c"])));
+        assert!(detector.is_applicable(&ab, &tables(&["a"]), &tables(&["b", "c"])));
+        // An outer join does not commute
+        assert!(!detector.is_applicable(&bc, &tables(&["c"]), &tables(&["b"])));
+    }
+
+    #[test]
+    fn test_outer_join_is_not_associative_with_inner() {
+        // (A ⟕ab B) ⋈bc C is not A ⟕ab (B ⋈bc C), the inner join filters out the rows padded for A
+        let plan = build_plan(JoinType::Left, JoinType::Inner);
+        let mut detector = ConflictDetector::new();
+        detector.add_plan(&plan);
+
+        let (ab, bc) = get_joins(&plan);
+        assert_eq!(detector.operators()[1].conflict_rules, vec![(tables(&["b"]), tables(&["a"]))]);
+        assert_eq!(detector.operators()[1].tes, tables(&["a", "b", "c"]));
+        assert!(!detector.is_applicable(&bc, &tables(&["b"]), &tables(&["c"])));
+        assert!(detector.is_applicable(&bc, &tables(&["a", "b"]), &tables(&["c"])));
+        assert!(detector.is_applicable(&ab, &tables(&["a"]), &tables(&["b"])));
+        // Nor is C joined to A first and B put back on top as a cross product
+        let cross = Join { on: Vec::new(), ..bc };
+        assert!(!detector.is_applicable(&cross, &tables(&["c"]), &tables(&["b"])));
+    }
+
+    #[test]
+    fn test_inner_joins_record_nothing() {
+        let mut detector = ConflictDetector::new();
+        detector.add_plan(&build_plan(JoinType::Inner, JoinType::Inner));
+        assert!(detector.is_empty());
+    }
+}
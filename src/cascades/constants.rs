@@ -1,4 +1,27 @@
 pub const DEFAULT_ROW_COUNT: u64 = 42;
+pub const SCAN_COST_PER_EXTRA_COLUMN: f64 = 0.1; // Fraction of a row's scan cost added by each column past the first
 pub const JOIN_COST_PER_ROW: f64 = 0.01;
 pub const FILTER_COST_PER_ROW: f64 = 0.005;
 pub const PROJECT_COST_PER_ROW: f64 = 0.0009;
+pub const AGGREGATE_COST_PER_ROW: f64 = 0.002;
+pub const CROSS_JOIN_PENALTY: f64 = 1000.0; // Multiplies the per-row cost of joins without any join condition
+pub const DEFAULT_JOIN_SELECTIVITY: f64 = 0.1; // Used for an equi join key between tables without a pre-canned selectivity
+// Exponent applied to the selectivity of a key computed from columns, e.g. UPPER(t1.a) = t2.b, trusting it only partly
+pub const COMPUTED_JOIN_KEY_CONFIDENCE: f64 = 0.5;
+// Pre-canned selectivities for non-equi join predicates carried in a join's residual filter
+pub const RANGE_JOIN_SELECTIVITY: f64 = 1.0 / 3.0; // <, <=, >, >=
+pub const BETWEEN_JOIN_SELECTIVITY: f64 = 0.25;
+pub const DEFAULT_FILTER_SELECTIVITY: f64 = 0.10; // Used when we cannot estimate a filter predicate
+// Factor a selectivity estimate may be off by either way, widening the cost band of the mexpr it is used in
+pub const STATS_SELECTIVITY_UNCERTAINTY: f64 = 1.5; // Estimated from column stats
+pub const CANNED_SELECTIVITY_UNCERTAINTY: f64 = 3.0; // Pre-canned for the pair of tables
+pub const DEFAULT_SELECTIVITY_UNCERTAINTY: f64 = 10.0; // A default or a guess
+// Physical join costs, charged on top of the logical JOIN_COST_PER_ROW for the output rows
+pub const HASH_BUILD_COST_PER_ROW: f64 = 0.02;
+pub const HASH_PROBE_COST_PER_ROW: f64 = 0.01;
+pub const HASH_MEMORY_COST_PER_ROW: f64 = 0.005; // Keeping a build row in the hash table
+pub const NESTED_LOOP_COST_PER_PAIR: f64 = 0.001;
+pub const INDEX_PROBE_COST_PER_ROW: f64 = 0.03; // An index lookup on the inner side per outer row
+pub const SORT_COST_PER_ROW: f64 = 0.002; // Scaled by log2 of the input rows
+pub const MERGE_COST_PER_ROW: f64 = 0.005;
+pub const VARIABLE_WIDTH_FIELD_BYTES: u64 = 32; // Assumed size of a string or other variable width value in memory
@@ -0,0 +1,414 @@
+use super::constants::{CROSS_JOIN_PENALTY, DEFAULT_FILTER_SELECTIVITY, DEFAULT_JOIN_SELECTIVITY};
+use datafusion::arrow::datatypes::DataType;
+use datafusion_common::tree_node::{Transformed, TreeNode};
+use datafusion_common::{Column, ScalarValue};
+use datafusion_expr::{Expr, Operator};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Statistics for a single column of a source table
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnStats {
+    /// Number of distinct values
+    pub ndv: Option<u64>,
+    /// Number of NULL values
+    pub null_count: Option<u64>,
+    /// Smallest and largest values, for numeric columns
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+}
+
+impl ColumnStats {
+    pub fn with_ndv(ndv: u64) -> Self {
+        Self {
+            ndv: Some(ndv),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_range(min_value: f64, max_value: f64) -> Self {
+        Self {
+            min_value: Some(min_value),
+            max_value: Some(max_value),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_null_count(null_count: u64) -> Self {
+        Self {
+            null_count: Some(null_count),
+            ..Default::default()
+        }
+    }
+}
+
+/// The range the cost of a plan is estimated to fall in, given how far off its selectivity estimates may be
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostBand {
+    pub low: f64,
+    pub mid: f64,
+    pub high: f64,
+}
+
+impl CostBand {
+    /// A cost known exactly
+    pub fn point(cost: f64) -> Self {
+        Self {
+            low: cost,
+            mid: cost,
+            high: cost,
+        }
+    }
+}
+
+/// A source of table and column statistics, consulted for what was not set on the cost model directly
+pub trait StatsProvider: std::fmt::Debug {
+    fn row_count(&self, table: &str) -> Option<u64>;
+    fn ndv(&self, table: &str, column: &str) -> Option<u64>;
+}
+
+/// Estimates the selectivity of a join predicate from the stats of its left and right columns
+/// Has to be deterministic, a group's cost is assumed to be the same every time its mexprs are costed
+pub type SelectivityFn = Box<dyn Fn(&Expr, &ColumnStats, &ColumnStats) -> f64>;
+
+/// Holds the statistics the cost model consults when estimating row counts
+#[derive(Default)]
+pub struct CostModel {
+    // Keyed on (table name, column name)
+    column_stats: HashMap<(String, String), ColumnStats>,
+    // Sort order a source table is stored in, keyed on table name
+    table_orderings: HashMap<String, Vec<Column>>,
+    // Primary key columns of a source table, keyed on table name
+    table_keys: HashMap<String, Vec<Column>>,
+    // Externally estimated output row counts, keyed on the set of source tables joined
+    cardinality_overrides: HashMap<BTreeSet<String>, u64>,
+    // Source table read through a SubqueryAlias, keyed on the alias
+    table_aliases: HashMap<String, String>,
+    stats_provider: Option<Box<dyn StatsProvider>>,
+    cross_join_penalty: Option<f64>,
+    default_join_selectivity: Option<f64>,
+    // Cost mexprs by the high bound of their cost band rather than its mid point
+    risk_averse: bool,
+    // Most bytes a hash join's build side may take up in memory
+    memory_budget: Option<u64>,
+    selectivity_fn: Option<SelectivityFn>,
+    // Selectivities of the join key sets costed so far, keyed on get_join_keys_hash, cleared whenever stats change
+    selectivity_cache: RefCell<HashMap<u64, f64>>,
+    selectivity_cache_hits: Cell<usize>,
+}
+
+impl std::fmt::Debug for CostModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CostModel")
+            .field("column_stats", &self.column_stats)
+            .field("table_orderings", &self.table_orderings)
+            .field("table_keys", &self.table_keys)
+            .field("cardinality_overrides", &self.cardinality_overrides)
+            .field("table_aliases", &self.table_aliases)
+            .field("stats_provider", &self.stats_provider)
+            .field("cross_join_penalty", &self.cross_join_penalty)
+            .field("default_join_selectivity", &self.default_join_selectivity)
+            .field("risk_averse", &self.risk_averse)
+            .field("memory_budget", &self.memory_budget)
+            .field("selectivity_cache_hits", &self.selectivity_cache_hits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CostModel {
+    pub fn set_column_stats(&mut self, table: &str, column: &str, stats: ColumnStats) {
+        self.column_stats
+            .insert((table.to_string(), column.to_string()), stats);
+        self.clear_selectivity_cache();
+    }
+
+    /// Declare that the rows of a source table are sorted on `columns`, outermost first
+    pub fn set_table_ordering(&mut self, table: &str, columns: &[&str]) {
+        let ordering = columns
+            .iter()
+            .map(|column| Column::new(Some(table), *column))
+            .collect();
+        self.table_orderings.insert(table.to_string(), ordering);
+    }
+
+    pub fn get_table_ordering(&self, table: &str) -> Vec<Column> {
+        self.table_orderings.get(table).cloned().unwrap_or_default()
+    }
+
+    /// Declare `columns` the primary key of a source table, with an index a join can probe
+    pub fn set_table_key(&mut self, table: &str, columns: &[&str]) {
+        let key = columns
+            .iter()
+            .map(|column| Column::new(Some(table), *column))
+            .collect();
+        self.table_keys.insert(table.to_string(), key);
+    }
+
+    /// Whether `sources` is a single table and `columns` include every column of its declared key
+    /// The index is on the table itself, so the rows of a join over the table can't be probed through it
+    pub fn is_key(&self, sources: &BTreeSet<String>, columns: &[Column]) -> bool {
+        let mut tables = sources.iter();
+        let (Some(table), None) = (tables.next(), tables.next()) else {
+            return false;
+        };
+        self.table_keys
+            .get(table)
+            .is_some_and(|key| !key.is_empty() && key.iter().all(|column| columns.contains(column)))
+    }
+
+    /// Pin the row count estimate for the join (or scan) over exactly `sources`
+    pub fn set_cardinality_override(&mut self, sources: BTreeSet<String>, rows: u64) {
+        self.cardinality_overrides.insert(sources, rows);
+    }
+
+    pub fn get_cardinality_override(&self, sources: &BTreeSet<String>) -> Option<u64> {
+        self.cardinality_overrides.get(sources).copied()
+    }
+
+    pub fn has_cardinality_overrides(&self) -> bool {
+        !self.cardinality_overrides.is_empty()
+    }
+
+    /// Declare `alias` a SubqueryAlias of the source table `table`, so the stats of the table apply to its columns
+    pub fn set_table_alias(&mut self, alias: &str, table: &str) {
+        self.table_aliases.insert(alias.to_string(), table.to_string());
+        self.clear_selectivity_cache();
+    }
+
+    /// The source table of a column, looking through table aliases
+    fn get_source_table(&self, column: &Column) -> Option<String> {
+        let table = column.relation.as_ref()?.to_string();
+        Some(self.table_aliases.get(&table).cloned().unwrap_or(table))
+    }
+
+    /// `expr` with the columns read through a table alias qualified with the source table instead
+    pub fn resolve_aliases(&self, expr: &Expr) -> Expr {
+        if self.table_aliases.is_empty() {
+            return expr.clone();
+        }
+        expr.clone()
+            .transform(|expr| match expr {
+                Expr::Column(column) => {
+                    let table =
+                        column.relation.as_ref().and_then(|relation| self.table_aliases.get(&relation.to_string()));
+                    match table {
+                        Some(table) => Ok(Transformed::yes(Expr::Column(Column::new(Some(table), column.name)))),
+                        None => Ok(Transformed::no(Expr::Column(column))),
+                    }
+                }
+                _ => Ok(Transformed::no(expr)),
+            })
+            .expect("requalifying columns does not fail")
+            .data
+    }
+
+    pub fn get_column_stats(&self, column: &Column) -> Option<&ColumnStats> {
+        let table = self.get_source_table(column)?;
+        self.column_stats.get(&(table, column.name.clone()))
+    }
+
+    /// Override CROSS_JOIN_PENALTY, the multiplier on the per-row cost of joins without any join condition
+    pub fn set_cross_join_penalty(&mut self, penalty: f64) {
+        self.cross_join_penalty = Some(penalty);
+    }
+
+    pub fn get_cross_join_penalty(&self) -> f64 {
+        self.cross_join_penalty.unwrap_or(CROSS_JOIN_PENALTY)
+    }
+
+    /// Override DEFAULT_JOIN_SELECTIVITY, the selectivity of an equi join key between tables without a pre-canned one
+    pub fn set_default_join_selectivity(&mut self, selectivity: f64) {
+        self.default_join_selectivity = Some(selectivity);
+        self.clear_selectivity_cache();
+    }
+
+    pub fn get_default_join_selectivity(&self) -> f64 {
+        self.default_join_selectivity.unwrap_or(DEFAULT_JOIN_SELECTIVITY)
+    }
+
+    /// Make the cost of every mexpr the high bound of its cost band, so the plan with the lowest worst case wins
+    pub fn set_risk_averse(&mut self, risk_averse: bool) {
+        self.risk_averse = risk_averse;
+    }
+
+    pub fn is_risk_averse(&self) -> bool {
+        self.risk_averse
+    }
+
+    /// Reject hash join manifestations whose build side would take up more than `max_bytes` in memory
+    pub fn set_memory_budget(&mut self, max_bytes: Option<u64>) {
+        self.memory_budget = max_bytes;
+    }
+
+    pub fn get_memory_budget(&self) -> Option<u64> {
+        self.memory_budget
+    }
+
+    /// Estimate `column = column` join keys with `selectivity_fn` ahead of column NDVs and pre-canned selectivities
+    pub fn set_selectivity_fn(&mut self, selectivity_fn: SelectivityFn) {
+        self.selectivity_fn = Some(selectivity_fn);
+        self.clear_selectivity_cache();
+    }
+
+    pub fn has_selectivity_fn(&self) -> bool {
+        self.selectivity_fn.is_some()
+    }
+
+    /// Selectivity of a `left = right` join key from the registered selectivity function, None if there is none
+    /// The function is passed the known stats of both columns, with NDVs from the stats provider filled in
+    pub fn get_custom_join_selectivity(&self, left: &Column, right: &Column) -> Option<f64> {
+        let selectivity_fn = self.selectivity_fn.as_ref()?;
+        let predicate = Expr::Column(left.clone()).eq(Expr::Column(right.clone()));
+        Some(selectivity_fn(&predicate, &self.get_known_stats(left), &self.get_known_stats(right)))
+    }
+
+    fn get_known_stats(&self, column: &Column) -> ColumnStats {
+        let mut stats = self.get_column_stats(column).cloned().unwrap_or_default();
+        stats.ndv = self.get_ndv(column);
+        stats
+    }
+
+    pub fn set_stats_provider(&mut self, stats_provider: Box<dyn StatsProvider>) {
+        self.stats_provider = Some(stats_provider);
+        self.clear_selectivity_cache();
+    }
+
+    /// Selectivity of the join keys `join_on`, estimated with `estimate` only the first time the same set of keys is
+    /// costed, whichever way round and in whatever order the keys are
+    pub fn get_cached_join_selectivity(&self, join_on: &[(Expr, Expr)], estimate: impl FnOnce() -> f64) -> f64 {
+        let key = get_join_keys_hash(join_on);
+        if let Some(selectivity) = self.selectivity_cache.borrow().get(&key) {
+            self.selectivity_cache_hits.set(self.selectivity_cache_hits.get() + 1);
+            return *selectivity;
+        }
+        let selectivity = estimate();
+        self.selectivity_cache.borrow_mut().insert(key, selectivity);
+        selectivity
+    }
+
+    /// Number of join selectivities served from the cache, see get_cached_join_selectivity
+    pub fn selectivity_cache_hits(&self) -> usize {
+        self.selectivity_cache_hits.get()
+    }
+
+    /// Forget the cached join selectivities, so they are estimated again from the current stats
+    pub fn clear_selectivity_cache(&mut self) {
+        self.selectivity_cache.get_mut().clear();
+    }
+
+    /// Row count of a source table, if the stats provider knows it
+    pub fn get_table_row_count(&self, table: &str) -> Option<u64> {
+        self.stats_provider.as_ref()?.row_count(table)
+    }
+
+    /// NDV of a column, from its column stats or else from the stats provider
+    pub fn get_ndv(&self, column: &Column) -> Option<u64> {
+        if let Some(ndv) = self.get_column_stats(column).and_then(|stats| stats.ndv) {
+            return Some(ndv);
+        }
+        let table = self.get_source_table(column)?;
+        self.stats_provider.as_ref()?.ndv(&table, &column.name)
+    }
+
+    /// Selectivity of a `left = right` join key, 1/max(NDV) when the NDVs of both columns are known
+    pub fn get_equi_join_selectivity(&self, left: &Column, right: &Column) -> Option<f64> {
+        let ndv = self.get_ndv(left)?.max(self.get_ndv(right)?);
+        (ndv > 0).then(|| 1.0 / ndv as f64)
+    }
+
+    /// Selectivity of a `column = literal` point predicate, 1/NDV when the column's NDV is known
+    pub fn get_point_selectivity(&self, column: &Column) -> f64 {
+        match self.get_ndv(column) {
+            Some(ndv) if ndv > 0 => 1.0 / ndv as f64,
+            _ => DEFAULT_FILTER_SELECTIVITY,
+        }
+    }
+
+    /// Selectivity of a `column <op> value` range predicate, the fraction of the column's [min, max] range it covers
+    /// Returns None when the column's range is unknown or the value isn't numeric
+    pub fn get_range_selectivity(&self, column: &Column, op: Operator, value: &ScalarValue) -> Option<f64> {
+        let (min_value, max_value) = self.get_value_range(column)?;
+        let value = scalar_to_f64(value)?;
+        let fraction_below = (value - min_value) / (max_value - min_value);
+        let selectivity = match op {
+            Operator::Lt | Operator::LtEq => fraction_below,
+            Operator::Gt | Operator::GtEq => 1.0 - fraction_below,
+            _ => return None,
+        };
+        Some(selectivity.clamp(0.0, 1.0))
+    }
+
+    /// Selectivity of a `column BETWEEN low AND high` predicate, as the fraction of the column's range covered
+    pub fn get_between_selectivity(&self, column: &Column, low: &ScalarValue, high: &ScalarValue) -> Option<f64> {
+        let (min_value, max_value) = self.get_value_range(column)?;
+        let low = scalar_to_f64(low)?.max(min_value);
+        let high = scalar_to_f64(high)?.min(max_value);
+        Some(((high - low) / (max_value - min_value)).clamp(0.0, 1.0))
+    }
+
+    fn get_value_range(&self, column: &Column) -> Option<(f64, f64)> {
+        let stats = self.get_column_stats(column)?;
+        match (stats.min_value, stats.max_value) {
+            (Some(min_value), Some(max_value)) if max_value > min_value => Some((min_value, max_value)),
+            _ => None,
+        }
+    }
+}
+
+/// Hash of a set of join keys, the same for a key written either way round and for the keys in any order
+fn get_join_keys_hash(join_on: &[(Expr, Expr)]) -> u64 {
+    let hash_expr = |expr: &Expr| {
+        let mut hasher = DefaultHasher::new();
+        expr.hash(&mut hasher);
+        hasher.finish()
+    };
+    let keys: BTreeSet<(u64, u64)> = join_on
+        .iter()
+        .map(|(left, right)| {
+            let (left, right) = (hash_expr(left), hash_expr(right));
+            (left.min(right), left.max(right))
+        })
+        .collect();
+    let mut hasher = DefaultHasher::new();
+    keys.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Number of distinct values a column of the given type can hold at most, None for types without a useful bound
+/// A join key can't have more distinct values than its type's domain, e.g. a Boolean key matches every row with half
+/// of the other side's rows
+pub fn get_domain_ndv(data_type: &DataType) -> Option<u64> {
+    match data_type {
+        DataType::Boolean => Some(2),
+        DataType::Int8 | DataType::UInt8 => Some(1 << 8),
+        DataType::Int16 | DataType::UInt16 => Some(1 << 16),
+        DataType::Int32 | DataType::UInt32 | DataType::Date32 => Some(1 << 32),
+        DataType::Int64 | DataType::UInt64 => Some(u64::MAX),
+        _ => None,
+    }
+}
+
+/// Whether cost `a` is strictly cheaper than cost `b`
+/// A NaN cost, e.g. from a broken selectivity or row count estimate, is worse than any other cost, infinite included
+pub fn better_cost(a: f64, b: f64) -> bool {
+    compare_costs(a, b) == Ordering::Less
+}
+
+/// Order costs cheapest first, with NaN costs last, see better_cost
+pub fn compare_costs(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.partial_cmp(&b).expect("costs that are not NaN are ordered"),
+        (false, true) => Ordering::Less,
+        (true, false) => Ordering::Greater,
+        (true, true) => Ordering::Equal,
+    }
+}
+
+fn scalar_to_f64(value: &ScalarValue) -> Option<f64> {
+    match value.cast_to(&DataType::Float64).ok()? {
+        ScalarValue::Float64(value) => value,
+        _ => None,
+    }
+}
@@ -1,3 +1,4 @@
+use datafusion_common::{Column, ScalarValue};
 use datafusion_expr::{BinaryExpr, Expr};
 use datafusion_expr_common::operator::Operator;
 use std::collections::{HashMap, HashSet};
@@ -31,6 +32,98 @@ pub fn flip_equality(expr: &Expr) -> Expr {
     }
 }
 
+/// Flips the left and right sides of a BinaryExpr with a comparison operator,
+/// swapping the operator so the expression keeps its meaning.
+/// `<` and `>`, `<=` and `>=` are swapped, while `=` and `!=` are kept as is.
+/// Returns the original expression if it's not a BinaryExpr with a comparison operator.
+///
+/// # Arguments
+/// * `expr` - The expression to flip
+///
+/// # Returns
+/// A new expression with left and right sides flipped if applicable, otherwise the original expression
+///
+/// # Example
+/// ```ignore
+/// // Input: a < b
+/// // Output: b > a
+/// ```
+pub fn flip_comparison(expr: &Expr) -> Expr {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            let flipped_op = match op {
+                Operator::Eq => Operator::Eq,
+                Operator::NotEq => Operator::NotEq,
+                Operator::Lt => Operator::Gt,
+                Operator::LtEq => Operator::GtEq,
+                Operator::Gt => Operator::Lt,
+                Operator::GtEq => Operator::LtEq,
+                _ => return expr.clone(),
+            };
+            Expr::BinaryExpr(BinaryExpr {
+                left: right.clone(),
+                op: flipped_op,
+                right: left.clone(),
+            })
+        }
+        _ => expr.clone(),
+    }
+}
+
+/// Returns the column of a `column = literal` (or `literal = column`) point predicate.
+/// Returns None for any other expression.
+///
+/// # Example
+/// ```ignore
+/// // Input: t1.a = 5
+/// // Output: Some(t1.a)
+/// ```
+pub fn get_column_literal_equality(expr: &Expr) -> Option<&Column> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::Eq,
+            right,
+        }) => match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(column), Expr::Literal(..)) | (Expr::Literal(..), Expr::Column(column)) => {
+                Some(column)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the column, operator and literal of a `column <op> literal` range predicate, with `<`, `<=`, `>` or `>=`.
+/// A `literal <op> column` predicate is flipped so the column is on the left.
+/// Returns None for any other expression.
+///
+/// # Example
+/// ```ignore
+/// // Input: 50 > t1.a
+/// // Output: Some((t1.a, Lt, 50))
+/// ```
+pub fn get_column_literal_range(expr: &Expr) -> Option<(Column, Operator, ScalarValue)> {
+    let Expr::BinaryExpr(BinaryExpr { left, op, right }) = expr else {
+        return None;
+    };
+    if !matches!(op, Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq) {
+        return None;
+    }
+
+    let oriented = match (left.as_ref(), right.as_ref()) {
+        (Expr::Literal(..), Expr::Column(_)) => flip_comparison(expr),
+        _ => expr.clone(),
+    };
+    let Expr::BinaryExpr(BinaryExpr { left, op, right }) = oriented else {
+        return None;
+    };
+    match (*left, *right) {
+        (Expr::Column(column), Expr::Literal(value, _)) => Some((column, op, value)),
+        _ => None,
+    }
+}
+
 /// Union-Find (Disjoint Set Union) data structure for tracking equivalence classes.
 ///
 /// This implementation uses path compression and union-by-rank for optimal performance.
@@ -120,8 +213,7 @@ pub fn get_unique_equalities(equalities: &[(Expr, Expr)]) -> HashSet<(Expr, Expr
     let mut unique_equalities = HashSet::new();
     for group in groups.values() {
         if group.len() > 1 {
-            // Add the first equality in the group as a representative
-            unique_equalities.insert((group[0].clone(), group[1].clone()));
+            unique_equalities.insert(pick_representative_pair(group));
         }
     }
 
@@ -129,6 +221,67 @@ pub fn get_unique_equalities(equalities: &[(Expr, Expr)]) -> HashSet<(Expr, Expr
     unique_equalities
 }
 
+/// The equivalence classes of the expressions equated by `equalities`
+/// Members are ordered by their display form, and classes by their first member, so the result is deterministic
+pub fn get_equivalence_classes(equalities: &[(Expr, Expr)]) -> Vec<Vec<Expr>> {
+    let mut uf = UnionFind::new();
+    for (left, right) in equalities {
+        uf.union(left, right);
+    }
+
+    let mut classes: Vec<Vec<Expr>> = uf
+        .get_equivalence_classes()
+        .into_values()
+        .map(|mut class| {
+            class.sort_by_cached_key(|expr| expr.to_string());
+            class
+        })
+        .collect();
+    classes.sort_by_cached_key(|class| class[0].to_string());
+    classes
+}
+
+/// The `equalities` not implied by `known` and the equalities before them, in order
+/// Applying just these on top of `known` yields the same equivalence classes as applying all of them
+pub fn get_new_equalities(known: &[(Expr, Expr)], equalities: &[(Expr, Expr)]) -> Vec<(Expr, Expr)> {
+    let mut uf = UnionFind::new();
+    for (left, right) in known {
+        uf.union(left, right);
+    }
+
+    let mut new_equalities = Vec::new();
+    for (left, right) in equalities {
+        if uf.find(left) != uf.find(right) {
+            uf.union(left, right);
+            new_equalities.push((left.clone(), right.clone()));
+        }
+    }
+    new_equalities
+}
+
+/// Picks a representative equality from an equivalence class.
+/// Prefers a pair whose sides are columns from different relations, since only such a pair is usable as a join key,
+/// falls back to the first two members of the class otherwise.
+/// Members are ordered by their display form first so the pick doesn't depend on HashMap iteration order
+fn pick_representative_pair(group: &[Expr]) -> (Expr, Expr) {
+    let mut group = group.to_vec();
+    group.sort_by_cached_key(|expr| expr.to_string());
+
+    for i in 0..group.len() {
+        for j in i + 1..group.len() {
+            if let (Expr::Column(left), Expr::Column(right)) = (&group[i], &group[j])
+                && left.relation.is_some()
+                && right.relation.is_some()
+                && left.relation != right.relation
+            {
+                return (group[i].clone(), group[j].clone());
+            }
+        }
+    }
+
+    (group[0].clone(), group[1].clone())
+}
+
 /// Infers transitive equalities from a list of equality expressions.
 ///
 /// Given a set of equality expressions (e.g., a = b, b = c, c = d),
@@ -164,12 +317,16 @@ pub fn infer_equalities(equalities: &Vec<Expr>) -> Vec<Expr> {
         }
     }
 
-    // Get all equivalence classes
-    let groups = uf.get_equivalence_classes();
+    // Get all equivalence classes, in a deterministic order so the inferred equalities are too
+    let mut groups: Vec<Vec<Expr>> = uf.get_equivalence_classes().into_values().collect();
+    for group in &mut groups {
+        group.sort_by_cached_key(|expr| expr.to_string());
+    }
+    groups.sort_by_cached_key(|group| group[0].to_string());
 
     // Generate all pairwise equalities within each equivalence class
     let mut all_equalities = Vec::new();
-    for group in groups.values() {
+    for group in &groups {
         // Skip singleton groups (no equalities to infer)
         if group.len() < 2 {
             continue;
@@ -184,8 +341,8 @@ pub fn infer_equalities(equalities: &Vec<Expr>) -> Vec<Expr> {
                     right: Box::new(group[j].clone()),
                 });
 
-                // Only include if it's not in the original input
-                if !original_equalities.contains(&equality) {
+                // Only include if it's not in the original input, and not a trivial a = a
+                if !original_equalities.contains(&equality) && !is_self_equality(&group[i], &group[j]) {
                     all_equalities.push(equality);
                 }
             }
@@ -194,3 +351,11 @@ pub fn infer_equalities(equalities: &Vec<Expr>) -> Vec<Expr> {
 
     all_equalities
 }
+
+/// Whether both sides of an equality are the same column of the same relation
+fn is_self_equality(left: &Expr, right: &Expr) -> bool {
+    matches!(
+        (left, right),
+        (Expr::Column(left), Expr::Column(right)) if left.relation == right.relation && left.name == right.name
+    )
+}
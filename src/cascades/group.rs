@@ -1,11 +1,21 @@
+use super::cost_model::{better_cost, compare_costs};
 use super::mexpr::MExpr;
 use super::sourcenode::SourceNode;
-use std::cell::RefCell;
-use std::collections::{HashSet, VecDeque};
+use datafusion_common::Column;
+use datafusion_expr::LogicalPlan;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::{BTreeSet, HashSet, VecDeque};
 use std::rc::Rc;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+thread_local! {
+    // Groups are Rc'd and never leave the thread that created them, so ids only need to be unique per thread
+    static NEXT_GROUP_ID: Cell<usize> = const { Cell::new(0) };
+}
 
 #[derive(Debug)]
 pub struct Group {
+    pub group_id: usize, // Sequential id for display, the memo is still keyed on content hashes
     explored: bool,
     pub min_cost: f64, // For now, assuming that 0.0 => UNKNOWN cost
     pub start_expression: Option<MExpr>,
@@ -23,11 +33,19 @@ pub struct Group {
 
     // Using Option for Java's Optional
     pub source_node: Option<SourceNode>,
+
+    // Ordering a sort above the group requires of its rows, for the physical plan to provide or pay a sort for
+    pub required_ordering: Option<Vec<Column>>,
+
+    // Leaf tables covered by the group, derived from the start expression on first use
+    source_set: OnceCell<BTreeSet<String>>,
 }
 
 impl Group {
     pub fn new(start_expression: MExpr) -> Self {
+        let group_id = NEXT_GROUP_ID.with(|next_group_id| next_group_id.replace(next_group_id.get() + 1));
         Self {
+            group_id,
             explored: false,
             min_cost: 0.0,
             start_expression: Some(start_expression),
@@ -37,6 +55,8 @@ impl Group {
             equivalent_logical_mexprs: RefCell::new(Vec::new()),                 // Empty vector
             physical_manifestations: RefCell::new(HashSet::new()),               // Empty hash set
             source_node: None,
+            required_ordering: None,
+            source_set: OnceCell::new(),
         }
     }
 
@@ -85,15 +105,29 @@ impl Group {
         self.min_cost
     }
 
+    /// Cost of the cheapest physical manifestation, 0.0 if the group has not been implemented
+    pub fn get_group_physical_cost(&self) -> f64 {
+        self.cheapest_physical_expression
+            .as_ref()
+            .map(|expr| expr.cost())
+            .unwrap_or(0.0)
+    }
+
     pub fn set_explored(&mut self, explored: bool) {
         self.explored = explored;
+        self.update_cheapest();
+    }
+
+    /// Re-derive the cheapest logical expression and min cost from the current costs of the equivalent mexprs
+    pub fn update_cheapest(&mut self) {
+        self.cheapest_logical_expression = None;
         // Find the cheapest logical expression from equivalent_logical_mexprs
         self.equivalent_logical_mexprs
             .borrow()
             .iter()
             .for_each(|mexpr| {
                 if let Some(ref cheapest) = self.cheapest_logical_expression {
-                    if mexpr.cost() < cheapest.cost() {
+                    if better_cost(mexpr.cost(), cheapest.cost()) {
                         self.cheapest_logical_expression = Some(mexpr.clone());
                     }
                 } else {
@@ -108,9 +142,115 @@ impl Group {
             .unwrap_or(0.0);
     }
 
+    /// Drop the costliest explored mexprs until at most `max_alternatives` are left, keeping the exploration order of
+    /// the rest. Among mexprs of equal cost the latest explored is dropped first
+    pub fn retain_cheapest(&mut self, max_alternatives: usize) {
+        let mut mexprs = self.equivalent_logical_mexprs.borrow_mut();
+        while mexprs.len() > max_alternatives {
+            let Some((costliest, _)) = mexprs
+                .iter()
+                .enumerate()
+                .max_by(|(_, left), (_, right)| compare_costs(left.cost(), right.cost()))
+            else {
+                break;
+            };
+            mexprs.remove(costliest);
+        }
+    }
+
+    /// Among the mexprs as cheap as the cheapest one, pick which one is the cheapest
+    /// With `prefer_simplest`, the mexprs whose cheapest tree has the fewest cross joins, then the shallowest tree, are
+    /// kept. With a `seed`, the one ranked first by a seeded hash is picked among those, the pick being the same for a
+    /// given seed whatever the order the mexprs were explored in. Otherwise the first explored is
+    pub fn break_cost_ties(&mut self, prefer_simplest: bool, seed: Option<u64>) {
+        let Some(min_cost) = self.cheapest_logical_expression.as_ref().map(|expr| expr.cost()) else {
+            return;
+        };
+        let mut tied: Vec<MExpr> = self
+            .equivalent_logical_mexprs
+            .borrow()
+            .iter()
+            .filter(|mexpr| mexpr.cost() == min_cost)
+            .cloned()
+            .collect();
+        if prefer_simplest && tied.len() > 1 {
+            let shapes: Vec<(usize, usize)> = tied.iter().map(get_tree_shape).collect();
+            let simplest = shapes.iter().min().copied();
+            tied = tied
+                .into_iter()
+                .zip(shapes)
+                .filter(|(_, shape)| Some(*shape) == simplest)
+                .map(|(mexpr, _)| mexpr)
+                .collect();
+        }
+
+        let winner = match seed {
+            Some(seed) => tied
+                .into_iter()
+                .min_by_key(|mexpr| xxh3_64_with_seed(&mexpr.hash().to_le_bytes(), seed)),
+            None => tied.into_iter().next(),
+        };
+        if winner.is_some() {
+            self.cheapest_logical_expression = winner;
+        }
+    }
+
+    /// The names of the leaf tables the group covers, its identity whatever the join order of its mexprs
+    /// Walks the start expression's operands down to their source nodes or table scans, the set is cached after that
+    pub fn source_set(&self) -> BTreeSet<String> {
+        self.source_set
+            .get_or_init(|| {
+                if let Some(source_node) = &self.source_node {
+                    return BTreeSet::from([source_node.node_id.clone()]);
+                }
+                let Some(start_expression) = self.start_expression.as_ref() else {
+                    return BTreeSet::new();
+                };
+                if let LogicalPlan::TableScan(table_scan) = &*start_expression.op().borrow() {
+                    return BTreeSet::from([table_scan.table_name.to_string()]);
+                }
+                start_expression
+                    .operands()
+                    .iter()
+                    .flat_map(|operand| operand.borrow().source_set())
+                    .collect()
+            })
+            .clone()
+    }
+
     pub fn is_explored(&self) -> bool {
         self.explored
     }
+
+    /// The display, cost and row count of each explored logical mexpr of this group
+    pub fn equivalent_plans(&self) -> Vec<(String, f64, u64)> {
+        self.equivalent_logical_mexprs
+            .borrow()
+            .iter()
+            .map(|mexpr| (mexpr.op().borrow().display().to_string(), mexpr.cost(), mexpr.row_count()))
+            .collect()
+    }
+}
+
+/// The number of cross joins and the depth of the tree of a mexpr and the cheapest mexprs of its operand groups
+/// An operand group already borrowed, the group whose cheapest mexpr is being picked, is not descended into
+fn get_tree_shape(mexpr: &MExpr) -> (usize, usize) {
+    let is_cross_join = matches!(
+        &*mexpr.op().borrow(),
+        LogicalPlan::Join(join) if join.on.is_empty() && join.filter.is_none()
+    );
+    let (mut cross_joins, mut depth) = (usize::from(is_cross_join), 0);
+    for operand in mexpr.operands() {
+        let Ok(operand) = operand.try_borrow() else {
+            continue;
+        };
+        if let Some(cheapest) = &operand.cheapest_logical_expression {
+            let (operand_cross_joins, operand_depth) = get_tree_shape(cheapest);
+            cross_joins += operand_cross_joins;
+            depth = depth.max(operand_depth);
+        }
+    }
+    (cross_joins, depth + 1)
 }
 
 #[cfg(test)]
@@ -145,9 +285,80 @@ mod tests {
         verify_row_count(logical_plan, 0, 0.0);
     }
 
+    #[test]
+    fn test_sequential_group_ids() {
+        let first = Group::from_mexpr(MExpr::build_with_node(Rc::new(RefCell::new(LogicalPlan::default())), vec![]));
+        let second = Group::from_mexpr(MExpr::build_with_node(Rc::new(RefCell::new(LogicalPlan::default())), vec![]));
+        assert_ne!(first.borrow().group_id, second.borrow().group_id);
+        assert_eq!(second.borrow().group_id, first.borrow().group_id + 1);
+    }
+
+    #[test]
+    fn test_equivalent_plans() {
+        let mut cascades = crate::cascades::Cascades::default();
+        let root = cascades.seed_memo("123");
+        cascades.optimize(root.clone());
+
+        let root = root.borrow();
+        let plans = root.equivalent_plans();
+        assert_eq!(plans.len(), root.equivalent_logical_mexprs.borrow().len());
+        assert!(plans.len() > 1);
+        assert!(plans.iter().all(|(display, cost, _)| display.starts_with("Inner Join") && cost.is_finite()));
+        let min_cost = plans.iter().map(|(_, cost, _)| *cost).fold(f64::INFINITY, f64::min);
+        assert_eq!(min_cost, root.get_group_cost());
+    }
+
+    #[test]
+    fn test_source_set() {
+        let mut cascades = crate::cascades::Cascades::default();
+        let root = cascades.seed_memo("123");
+        let expected = BTreeSet::from(["t1".to_string(), "t2".to_string(), "t3".to_string()]);
+        assert_eq!(root.borrow().source_set(), expected);
+
+        // Exploring adds other join orders, the group still covers the same tables
+        cascades.optimize(root.clone());
+        assert_eq!(root.borrow().source_set(), expected);
+        let start_expression = root.borrow().start_expression.clone().unwrap();
+        let left = start_expression.operands()[0].borrow().source_set();
+        assert_eq!(left, BTreeSet::from(["t1".to_string(), "t2".to_string()]));
+    }
+
+    #[test]
+    fn test_nan_cost_is_never_cheapest() {
+        use crate::cascades::Cascades;
+        use crate::cascades::cost_model::CostModel;
+        use datafusion_expr::{JoinType, LogicalPlanBuilder, col};
+
+        // A NaN cross join penalty makes every plan through the cross join t1 x t2 cost NaN, but not (t1 ⋈ t3) ⋈ t2
+        let plan = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(100)))
+            .cross_join(test_utils::build_table_scan("t2", &["a2"], Some(100)))
+            .unwrap()
+            .join_with_expr_keys(
+                test_utils::build_table_scan("t3", &["a3", "b3"], Some(100)),
+                JoinType::Inner,
+                (vec![col("t1.a1"), col("t2.a2")], vec![col("t3.a3"), col("t3.b3")]),
+                None,
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut cost_model = CostModel::default();
+        cost_model.set_cross_join_penalty(f64::NAN);
+        let mut cascades = Cascades::builder().cost_model(cost_model).build();
+        let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+        cascades.optimize(root.clone());
+
+        let root = root.borrow();
+        let costs: Vec<f64> = root.equivalent_logical_mexprs.borrow().iter().map(|mexpr| mexpr.cost()).collect();
+        assert!(costs.iter().any(|cost| cost.is_nan()));
+        assert!(costs.iter().any(|cost| cost.is_finite()));
+        assert!(root.get_group_cost().is_finite());
+        assert!(root.get_group_physical_cost().is_finite());
+    }
+
     fn verify_row_count(logical_plan: LogicalPlan, expected_row_count: u64, expected_cost: f64) {
         let mexpr = MExpr::build_with_node(Rc::new(RefCell::new(logical_plan)), vec![]);
-        let mut group = Group::new(mexpr.clone());
+        let group = Group::new(mexpr.clone());
         let row_count = group.get_group_row_count();
         println!("Group row count: {}", row_count);
         assert_eq!(row_count, expected_row_count);
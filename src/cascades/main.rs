@@ -1,5 +1,5 @@
-use optimizer::cascades::Cascades;
-use optimizer::util::{get_all_possible_trees, get_all_possible_trees_count};
+use disagg_optimizer::cascades::Cascades;
+use disagg_optimizer::cascades::util::get_all_possible_trees;
 use std::time::Instant;
 
 fn main() {
@@ -15,7 +15,7 @@ fn driver() {
 
     // read join_node string from command line
     let join_nodes = std::env::args().nth(1).unwrap_or_else(|| {
-        eprintln!("Usage: {} <join_nodes>", std::env::args().nth(0).unwrap());
+        eprintln!("Usage: {} <join_nodes>", std::env::args().next().unwrap());
         std::process::exit(1);
     });
 
@@ -27,7 +27,7 @@ fn driver() {
             .start_expression
             .as_ref()
             .unwrap()
-            .get_full_string()
+            .canonicalized()
     );
     
     println!("Created root group for join nodes '{join_nodes}'");
@@ -0,0 +1,234 @@
+use super::cost_model::compare_costs;
+use super::mexpr::MExpr;
+use super::Memo;
+use super::util::{format_sources, get_group_sources};
+use datafusion_common::DataFusionError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::Path;
+
+/// A logical mexpr as written to a memo dump, its operator kept only in its displayed form
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MExprDump {
+    pub hash: u64,
+    pub display: String,
+    #[serde(with = "non_finite_as_null")]
+    pub cost: f64,
+    pub row_count: u64,
+    /// Group ids of the operands
+    pub operands: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupDump {
+    pub group_id: usize,
+    pub hash: u64,
+    pub sources: BTreeSet<String>,
+    pub explored: bool,
+    #[serde(with = "non_finite_as_null")]
+    pub min_cost: f64,
+    pub row_count: u64,
+    pub cheapest_logical_expression: Option<MExprDump>,
+    pub equivalent_logical_mexprs: Vec<MExprDump>,
+}
+
+/// Snapshot of a memo, with groups referring to each other by group id, for inspecting a run offline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoDump {
+    /// Groups keyed on group id
+    pub groups: BTreeMap<usize, GroupDump>,
+    /// Memo entries, from the digest of the mexpr's memo key to the id of its group
+    pub memo: BTreeMap<u64, usize>,
+}
+
+impl MemoDump {
+    pub fn from_memo(memo: &Memo) -> Self {
+        let mut groups = BTreeMap::new();
+        for group in memo.values() {
+            let group_borrowed = group.borrow();
+            if groups.contains_key(&group_borrowed.group_id) {
+                continue;
+            }
+            groups.insert(
+                group_borrowed.group_id,
+                GroupDump {
+                    group_id: group_borrowed.group_id,
+                    hash: group_borrowed.get_group_hash(),
+                    sources: get_group_sources(group),
+                    explored: group_borrowed.is_explored(),
+                    min_cost: group_borrowed.get_group_cost(),
+                    row_count: group_borrowed.get_group_row_count(),
+                    cheapest_logical_expression: group_borrowed
+                        .cheapest_logical_expression
+                        .as_ref()
+                        .map(dump_mexpr),
+                    equivalent_logical_mexprs: group_borrowed
+                        .equivalent_logical_mexprs
+                        .borrow()
+                        .iter()
+                        .map(dump_mexpr)
+                        .collect(),
+                },
+            );
+        }
+
+        let memo = memo
+            .iter()
+            .map(|(key, group)| (key.digest(), group.borrow().group_id))
+            .collect();
+        Self { groups, memo }
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), DataFusionError> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| DataFusionError::External(Box::new(e)))?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, DataFusionError> {
+        let json = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| DataFusionError::External(Box::new(e)))
+    }
+
+    pub fn get_group(&self, group_id: usize) -> Option<&GroupDump> {
+        self.groups.get(&group_id)
+    }
+
+    /// Same as util::get_cheapest_tree on the memo this was dumped from
+    pub fn get_cheapest_tree(&self, group_id: usize) -> String {
+        let Some(cheapest_expr) = self
+            .get_group(group_id)
+            .and_then(|group| group.cheapest_logical_expression.as_ref())
+        else {
+            return "None".to_string();
+        };
+
+        let mut result = format!(
+            "{}, Cost {}, RowCount {}\n",
+            cheapest_expr.display, cheapest_expr.cost, cheapest_expr.row_count
+        );
+        for operand in &cheapest_expr.operands {
+            for line in self.get_cheapest_tree(*operand).lines() {
+                result.push_str(&format!("    -> {}\n", line));
+            }
+        }
+        result.trim_end().to_string()
+    }
+
+    /// Same as Cascades::explain on the memo this was dumped from
+    pub fn explain(&self, root_group_id: usize) -> String {
+        let mut groups = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![root_group_id];
+        while let Some(group_id) = pending.pop() {
+            if !visited.insert(group_id) {
+                continue;
+            }
+            let Some(group) = self.get_group(group_id) else {
+                continue;
+            };
+            for mexpr in &group.equivalent_logical_mexprs {
+                pending.extend(mexpr.operands.iter().copied());
+            }
+            groups.push(group);
+        }
+        groups.sort_by(|left, right| {
+            (left.sources.len(), &left.sources, left.group_id)
+                .cmp(&(right.sources.len(), &right.sources, right.group_id))
+        });
+
+        let mut explanation = String::new();
+        for group in groups {
+            let winner = group.cheapest_logical_expression.as_ref().map(|mexpr| mexpr.hash);
+            let mut mexprs: Vec<&MExprDump> = group.equivalent_logical_mexprs.iter().collect();
+            mexprs.sort_by(|left, right| compare_costs(left.cost, right.cost).then(left.hash.cmp(&right.hash)));
+
+            explanation.push_str(&format!(
+                "Group G{} {} : min_cost {}, {} logical mexprs\n",
+                group.group_id,
+                format_sources(&group.sources),
+                group.min_cost,
+                mexprs.len()
+            ));
+            for mexpr in mexprs {
+                explanation.push_str(&format!(
+                    "  {} {}\n",
+                    if winner == Some(mexpr.hash) { "*" } else { "-" },
+                    format_mexpr(mexpr)
+                ));
+            }
+        }
+        explanation
+    }
+
+    /// Every logical mexpr of every group, winner or not, groups by group id and mexprs in the order they were
+    /// generated in. Unlike explain, groups not reachable from the root are listed too
+    pub fn format_all_mexprs(&self) -> String {
+        let mut formatted = String::new();
+        for group in self.groups.values() {
+            formatted.push_str(&format!(
+                "Group G{} {} : explored {}, min_cost {}, row_count {}\n",
+                group.group_id,
+                format_sources(&group.sources),
+                group.explored,
+                group.min_cost,
+                group.row_count
+            ));
+            let winner = group.cheapest_logical_expression.as_ref().map(|mexpr| mexpr.hash);
+            for mexpr in &group.equivalent_logical_mexprs {
+                formatted.push_str(&format!(
+                    "  {} {}\n",
+                    if winner == Some(mexpr.hash) { "*" } else { "-" },
+                    format_mexpr(mexpr)
+                ));
+            }
+        }
+        formatted
+    }
+}
+
+fn format_mexpr(mexpr: &MExprDump) -> String {
+    let operands = mexpr
+        .operands
+        .iter()
+        .map(|operand| format!("G{}", operand))
+        .collect::<Vec<_>>();
+    format!(
+        "{} [{}] cost {}, row_count {}",
+        mexpr.display,
+        operands.join(", "),
+        mexpr.cost,
+        mexpr.row_count
+    )
+}
+
+fn dump_mexpr(mexpr: &MExpr) -> MExprDump {
+    MExprDump {
+        hash: mexpr.hash(),
+        display: mexpr.op().borrow().display().to_string(),
+        cost: mexpr.cost(),
+        row_count: mexpr.row_count(),
+        operands: mexpr
+            .operands()
+            .iter()
+            .map(|operand| operand.borrow().group_id)
+            .collect(),
+    }
+}
+
+// JSON has no infinity, uncosted mexprs are written with a null cost
+mod non_finite_as_null {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_some(value)
+        } else {
+            serializer.serialize_none()
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::INFINITY))
+    }
+}
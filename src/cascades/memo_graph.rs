@@ -0,0 +1,72 @@
+use super::Memo;
+use super::util::get_group_sources;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A group of the memo, without its mexprs
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupInfo {
+    pub id: usize,
+    pub source_set: BTreeSet<String>,
+    pub min_cost: f64,
+    /// Number of explored logical mexprs
+    pub n_logical: usize,
+    /// Number of physical manifestations
+    pub n_physical: usize,
+}
+
+/// The groups of a memo and how they reference each other, for analyzing a memo programmatically
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoGraph {
+    /// Ordered by group id
+    pub groups: Vec<GroupInfo>,
+    /// From a group to a group one of its logical mexprs has as operand, by group id. Every edge is listed once,
+    /// however many mexprs share it
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl MemoGraph {
+    pub fn from_memo(memo: &Memo) -> Self {
+        let mut groups = BTreeMap::new();
+        let mut edges = BTreeSet::new();
+        for group in memo.values() {
+            let group_borrowed = group.borrow();
+            if groups.contains_key(&group_borrowed.group_id) {
+                continue;
+            }
+            let logical_mexprs = group_borrowed.equivalent_logical_mexprs.borrow();
+            for mexpr in logical_mexprs.iter() {
+                for operand in mexpr.operands() {
+                    edges.insert((group_borrowed.group_id, operand.borrow().group_id));
+                }
+            }
+            groups.insert(
+                group_borrowed.group_id,
+                GroupInfo {
+                    id: group_borrowed.group_id,
+                    source_set: get_group_sources(group),
+                    min_cost: group_borrowed.get_group_cost(),
+                    n_logical: logical_mexprs.len(),
+                    n_physical: group_borrowed.physical_manifestations.borrow().len(),
+                },
+            );
+        }
+
+        Self {
+            groups: groups.into_values().collect(),
+            edges: edges.into_iter().collect(),
+        }
+    }
+
+    pub fn get_group(&self, id: usize) -> Option<&GroupInfo> {
+        self.groups.iter().find(|group| group.id == id)
+    }
+
+    /// The ids of the groups the mexprs of group `id` have as operands
+    pub fn get_children(&self, id: usize) -> Vec<usize> {
+        self.edges
+            .iter()
+            .filter(|(parent, _)| *parent == id)
+            .map(|(_, child)| *child)
+            .collect()
+    }
+}
@@ -1,29 +1,77 @@
 use super::constants::{
-    DEFAULT_ROW_COUNT, FILTER_COST_PER_ROW, JOIN_COST_PER_ROW, PROJECT_COST_PER_ROW,
+    AGGREGATE_COST_PER_ROW, BETWEEN_JOIN_SELECTIVITY, CANNED_SELECTIVITY_UNCERTAINTY, COMPUTED_JOIN_KEY_CONFIDENCE,
+    DEFAULT_FILTER_SELECTIVITY, DEFAULT_JOIN_SELECTIVITY, DEFAULT_ROW_COUNT, DEFAULT_SELECTIVITY_UNCERTAINTY,
+    FILTER_COST_PER_ROW, JOIN_COST_PER_ROW, PROJECT_COST_PER_ROW, RANGE_JOIN_SELECTIVITY, SCAN_COST_PER_EXTRA_COLUMN,
+    STATS_SELECTIVITY_UNCERTAINTY,
 };
+use super::cost_model::{CostBand, CostModel, get_domain_ndv};
 
 use super::group::Group;
+use super::util::{format_sources, get_group_sources, get_operator_name};
+use super::physical::{JoinAlgorithm, get_sort_cost, get_sort_ordering};
 use core::f64;
-use datafusion_common::{DFSchema};
-use datafusion_expr::{Expr, LogicalPlan};
+use datafusion_common::{Column, DFSchema, JoinType, NullEquality};
+use datafusion_expr::utils::{conjunction, split_conjunction};
+use datafusion_expr::{Between, BinaryExpr, Distinct, Expr, Join, LogicalPlan, Operator, TableScan};
 use lazy_static::lazy_static;
 use log::debug;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 use std::sync::Arc;
-use xxhash_rust::xxh3::Xxh3;
-use super::expression_utils::get_unique_equalities;
+use xxhash_rust::xxh3::{Xxh3, xxh3_64};
+use super::expression_utils::{
+    get_column_literal_equality, get_column_literal_range, get_new_equalities, get_unique_equalities,
+};
+
+/// Key of a mexpr in the memo
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MemoKey {
+    /// The mexpr's Xxh3 content hash, distinct mexprs may collide
+    Hash(u64),
+    /// Everything the content hash is computed over and the join keys in any order, with the operands identified by
+    /// group id instead of by group hash. Exact, but slower to build and compare
+    Structural(Vec<u8>),
+}
+
+impl MemoKey {
+    /// The content hash for a hash key, a hash of the key for a structural one
+    pub fn digest(&self) -> u64 {
+        match self {
+            MemoKey::Hash(hash) => *hash,
+            MemoKey::Structural(bytes) => xxh3_64(bytes),
+        }
+    }
+}
+
+/// Collects the bytes a value is hashed from instead of hashing them
+#[derive(Default)]
+struct ByteRecorder(Vec<u8>);
+
+impl Hasher for ByteRecorder {
+    fn finish(&self) -> u64 {
+        xxh3_64(&self.0)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct MExpr {
     hash: u64,
     cost: f64,
+    cost_band: CostBand, // Range of the cost given the uncertainty of the selectivities used to estimate it
     row_count: u64,
     op: Rc<RefCell<LogicalPlan>>,      // Store LogicalPlan node directly
     operands: Vec<Rc<RefCell<Group>>>, // Using Rc and RefCell for shared ownership and mutability
     canonicalized: String,
+    join_algorithm: Option<JoinAlgorithm>, // Set on physical join manifestations only
+    provided_ordering: Vec<Column>,        // Ordering of the rows a physical manifestation produces
+    known_equalities: Vec<(Expr, Expr)>,   // Column equalities applied by the joins of the cheapest tree below
+    table_rows: Option<u64>,               // Rows of the scanned table before the scan's filters, scans only
 }
 
 impl MExpr {
@@ -38,54 +86,278 @@ impl MExpr {
             // All nodes, including the TableScan node will be a group
             hasher.update(operand.borrow().get_group_hash().to_le_bytes().as_ref());
         }
+        Self::hash_operator(&node.borrow(), &mut hasher);
+
+        let hash = hasher.digest();
+        let canonicalized = Self::canonicalize(&node.borrow(), &operands);
+
+        Self {
+            hash,
+            cost: f64::INFINITY,
+            cost_band: CostBand::point(f64::INFINITY),
+            row_count: u64::MAX,
+            op: node,
+            operands,
+            canonicalized,
+            join_algorithm: None,
+            provided_ordering: Vec::new(),
+            known_equalities: Vec::new(),
+            table_rows: None,
+        }
+    }
+
+    /// Cost of the groups that the cheapest trees of several operands have in common, so that a common subexpression
+    /// is only charged once. A group shared with an earlier operand is discounted whole, with everything below it
+    fn get_shared_operand_cost(operands: &[Rc<RefCell<Group>>]) -> f64 {
+        if operands.len() < 2 {
+            return 0.0;
+        }
+
+        let mut earlier = HashSet::new();
+        let mut shared_cost = 0.0;
+        for operand in operands {
+            let mut visited = HashSet::new();
+            shared_cost += Self::get_cost_shared_with(operand, &earlier, &mut visited);
+            earlier.extend(visited);
+        }
+        shared_cost
+    }
+
+    /// Cost of the topmost groups of the cheapest tree of `group` that are in `earlier`
+    /// A group reached twice within the tree is already charged once by the tree's own cost, so it is only looked at once
+    fn get_cost_shared_with(group: &Rc<RefCell<Group>>, earlier: &HashSet<usize>, visited: &mut HashSet<usize>) -> f64 {
+        let group_id = group.borrow().group_id;
+        if !visited.insert(group_id) {
+            return 0.0;
+        }
+        if earlier.contains(&group_id) {
+            return group.borrow().get_group_cost();
+        }
+
+        let Some(cheapest) = group.borrow().cheapest_logical_expression.clone() else {
+            return 0.0;
+        };
+        cheapest
+            .operands()
+            .iter()
+            .map(|operand| Self::get_cost_shared_with(operand, earlier, visited))
+            .sum()
+    }
+
+    /// A readable identity for logs and snapshots : the operator, the sources of its operands and its predicates
+    /// The inputs of inner and full joins are listed in sorted order, the predicates are sorted and the sides of each
+    /// equality ordered, so a join and its commuted form share it, e.g. `Inner Join({t1}, {t2}) [t1.a1 = t2.a2]`
+    fn canonicalize(node: &LogicalPlan, operands: &[Rc<RefCell<Group>>]) -> String {
+        let mut operand_sources: Vec<String> =
+            operands.iter().map(|operand| format_sources(&get_group_sources(operand))).collect();
+        let mut predicates: Vec<String> = Vec::new();
+        let operator = match node {
+            LogicalPlan::Join(join) => {
+                if matches!(join.join_type, JoinType::Inner | JoinType::Full) {
+                    operand_sources.sort();
+                }
+                predicates.extend(Self::canonicalize_join_predicates(join));
+                format!("{} Join", join.join_type)
+            }
+            LogicalPlan::Filter(filter) => {
+                predicates.extend(split_conjunction(&filter.predicate).into_iter().map(Self::canonicalize_predicate));
+                get_operator_name(node)
+            }
+            LogicalPlan::TableScan(scan) => {
+                operand_sources.push(format_sources(&BTreeSet::from([scan.table_name.to_string()])));
+                predicates.extend(scan.filters.iter().map(Self::canonicalize_predicate));
+                get_operator_name(node)
+            }
+            _ => get_operator_name(node),
+        };
+        predicates.sort();
 
-        // Hash the operator type and its specific properties, excluding children
-        match node.borrow().clone() {
+        let mut canonicalized = format!("{}({})", operator, operand_sources.join(", "));
+        if !predicates.is_empty() {
+            canonicalized.push_str(&format!(" [{}]", predicates.join(", ")));
+        }
+        canonicalized
+    }
+
+    /// The join keys and filter conjuncts of a join, canonicalized as in canonicalize()
+    pub fn canonicalize_join_predicates(join: &Join) -> Vec<String> {
+        let mut predicates: Vec<String> =
+            join.on.iter().map(|(left, right)| Self::canonicalize_equality(left, right)).collect();
+        if let Some(filter) = &join.filter {
+            predicates.extend(split_conjunction(filter).into_iter().map(Self::canonicalize_predicate));
+        }
+        predicates
+    }
+
+    fn canonicalize_predicate(predicate: &Expr) -> String {
+        match predicate {
+            Expr::BinaryExpr(BinaryExpr { left, op: Operator::Eq, right }) => Self::canonicalize_equality(left, right),
+            _ => predicate.to_string(),
+        }
+    }
+
+    fn canonicalize_equality(left: &Expr, right: &Expr) -> String {
+        let (left, right) = (left.to_string(), right.to_string());
+        if left <= right {
+            format!("{} = {}", left, right)
+        } else {
+            format!("{} = {}", right, left)
+        }
+    }
+
+    /// Hash the operator type and its specific properties, excluding children
+    fn hash_operator<H: Hasher>(node: &LogicalPlan, hasher: &mut H) {
+        std::mem::discriminant(node).hash(hasher);
+        match node {
             LogicalPlan::Projection(proj) => {
-                proj.schema.hash(&mut hasher);
-                proj.expr.hash(&mut hasher);
+                proj.schema.hash(hasher);
+                proj.expr.hash(hasher);
             }
             LogicalPlan::Filter(filter) => {
-                filter.predicate.hash(&mut hasher);
+                filter.predicate.hash(hasher);
             }
             LogicalPlan::Join(join) => {
-                join.join_type.hash(&mut hasher);
+                join.join_type.hash(hasher);
                 // TODO : We need to fix the hashing for the ON clauses, so that a join node with [a = b] and [b = a] hash the same
                 // TODO : Because rulematcher.split_eq_and_noneq_join_predicate is not correctly generating equality inferences
                 // TODO : We are seeing CROSS JOINs while these would have been correctly generated as Inner Joins with ON clauses
-                // join.on.hash(&mut hasher);
-                join.filter.hash(&mut hasher);
-                join.join_constraint.hash(&mut hasher);
+                // join.on.hash(hasher);
+                // Whether there are join keys at all is still hashed, so a cross join and an equi join differ
+                join.on.is_empty().hash(hasher);
+                join.filter.hash(hasher);
+                join.join_constraint.hash(hasher);
             }
             LogicalPlan::TableScan(ts) => {
-                ts.hash(&mut hasher);
+                ts.hash(hasher);
+            }
+            LogicalPlan::SubqueryAlias(alias) => {
+                alias.alias.hash(hasher);
+            }
+            LogicalPlan::Aggregate(agg) => {
+                agg.group_expr.hash(hasher);
+                agg.aggr_expr.hash(hasher);
+            }
+            LogicalPlan::Sort(sort) => {
+                sort.expr.hash(hasher);
+                sort.fetch.hash(hasher);
+            }
+            LogicalPlan::Limit(limit) => {
+                limit.skip.hash(hasher);
+                limit.fetch.hash(hasher);
+            }
+            LogicalPlan::Union(union) => {
+                union.inputs.len().hash(hasher);
+            }
+            LogicalPlan::Window(window) => {
+                window.window_expr.hash(hasher);
+            }
+            LogicalPlan::Distinct(Distinct::On(distinct_on)) => {
+                distinct_on.on_expr.hash(hasher);
+                distinct_on.select_expr.hash(hasher);
+                distinct_on.sort_expr.hash(hasher);
+            }
+            LogicalPlan::EmptyRelation(empty) => {
+                empty.produce_one_row.hash(hasher);
+                empty.schema.hash(hasher);
+            }
+            LogicalPlan::Values(values) => {
+                values.schema.hash(hasher);
+                values.values.hash(hasher);
             }
             _ => { /* Fix the other nodes similarly*/ }
-        };
+        }
+    }
 
+    /// The key of this mexpr in a memo, its content hash unless `structural` is set
+    pub fn memo_key(&self, structural: bool) -> MemoKey {
+        if !structural {
+            return MemoKey::Hash(self.hash);
+        }
+
+        let mut recorder = ByteRecorder::default();
+        for operand in &self.operands {
+            recorder.write_usize(operand.borrow().group_id);
+        }
+        recorder.write(&Self::record_operator(&self.op.borrow()));
+        MemoKey::Structural(recorder.0)
+    }
+
+    /// The bytes the operator is hashed from, and for a join its keys, which the content hash leaves out. Each key is
+    /// recorded with its sides in byte order and the keys are sorted, so the same keys written either way round and in
+    /// any order record the same
+    fn record_operator(node: &LogicalPlan) -> Vec<u8> {
+        let mut recorder = ByteRecorder::default();
+        Self::hash_operator(node, &mut recorder);
+        if let LogicalPlan::Join(join) = node {
+            let record_expr = |expr: &Expr| {
+                let mut recorder = ByteRecorder::default();
+                expr.hash(&mut recorder);
+                recorder.0
+            };
+            let keys: BTreeSet<(Vec<u8>, Vec<u8>)> = join
+                .on
+                .iter()
+                .map(|(left, right)| {
+                    let (left, right) = (record_expr(left), record_expr(right));
+                    if left <= right { (left, right) } else { (right, left) }
+                })
+                .collect();
+            keys.hash(&mut recorder);
+        }
+        recorder.0
+    }
+
+    /// A physical manifestation of an explored logical mexpr, sharing its operator, operands and row count
+    /// The algorithm is mixed into the hash so the manifestations of a join are told apart
+    pub fn build_physical(logical: &MExpr, join_algorithm: Option<JoinAlgorithm>) -> Self {
+        let mut hasher = Xxh3::new();
+        hasher.update(logical.hash.to_le_bytes().as_ref());
+        join_algorithm.hash(&mut hasher);
         let hash = hasher.digest();
 
+        let canonicalized = match join_algorithm {
+            Some(join_algorithm) => format!("{} {}", logical.canonicalized, join_algorithm),
+            None => logical.canonicalized.clone(),
+        };
+
         Self {
             hash,
-            cost: f64::INFINITY,
-            row_count: u64::MAX,
-            op: node,
-            operands,
-            canonicalized: hash.to_string(),
+            canonicalized,
+            join_algorithm,
+            ..logical.clone()
         }
     }
 
     // This will be called after the children groups have been explored and have accurate cost/rowcount
-    pub fn update_cost_and_rowcount(&mut self) {
+    pub fn update_cost_and_rowcount(&mut self, cost_model: &CostModel) {
         let mut row_count = DEFAULT_ROW_COUNT; // Default row count, need to improve this
         let mut cost = 0.0;
         let mut operand_row_counts: Vec<u64> = Vec::new();
         let mut operand_costs: f64 = 0.0;
 
+        let mut operand_bands = CostBand::point(0.0);
+        // Factor the selectivity this mexpr is estimated with may be off by, 1.0 for an exact estimate
+        let mut uncertainty = 1.0;
+
+        let mut known_equalities = Vec::new();
+        let mut table_rows = None;
+
         for operand in &self.operands {
             operand_row_counts.push(operand.borrow().get_group_row_count());
             operand_costs += operand.borrow().get_group_cost();
+            let operand_band = match &operand.borrow().cheapest_logical_expression {
+                Some(cheapest) => {
+                    known_equalities.extend(cheapest.known_equalities.iter().cloned());
+                    cheapest.cost_band
+                }
+                None => CostBand::point(operand.borrow().get_group_cost()),
+            };
+            operand_bands.low += operand_band.low;
+            operand_bands.mid += operand_band.mid;
+            operand_bands.high += operand_band.high;
         }
+        let shared_operand_cost = Self::get_shared_operand_cost(&self.operands);
+        operand_costs -= shared_operand_cost;
 
         match self.op.borrow().clone() {
             LogicalPlan::Projection(_proj) => {
@@ -95,41 +367,325 @@ impl MExpr {
                     .unwrap_or(DEFAULT_ROW_COUNT);
                 cost = PROJECT_COST_PER_ROW * row_count as f64 + operand_costs; // Assume projection has a small cost
             }
-            LogicalPlan::Filter(_filter) => {
-                row_count = (0.10
+            LogicalPlan::Filter(filter) => {
+                let selectivity = Self::get_filter_selectivity(&filter.predicate, cost_model);
+                uncertainty = Self::get_filter_uncertainty(&filter.predicate, cost_model);
+                row_count = (selectivity
                     * operand_row_counts
                         .first()
                         .cloned()
-                        .unwrap_or(DEFAULT_ROW_COUNT) as f64) as u64; // Assume filter reduces rows by 90%
+                        .unwrap_or(DEFAULT_ROW_COUNT) as f64) as u64;
                 cost = FILTER_COST_PER_ROW * row_count as f64 + operand_costs;
             }
             LogicalPlan::Join(join) => {
                 // Simplistic cost model for now , we use pre canned selectivities
                 // We will later add NDV stats based estimation
-                let selectivity = Self::get_join_selectivity(&join.on);
+                // Equalities already applied below, e.g. repeated in this join's filter, don't filter any further
+                // The keys are resolved to the source tables of aliased inputs, whose selectivities they share
+                let join_equalities: Vec<(Expr, Expr)> = Self::get_join_equalities(&join)
+                    .iter()
+                    .map(|(left, right)| (cost_model.resolve_aliases(left), cost_model.resolve_aliases(right)))
+                    .collect();
+                let new_equalities = get_new_equalities(&known_equalities, &join_equalities);
+                known_equalities.extend(join_equalities);
+                let key_selectivity = cost_model.get_cached_join_selectivity(&new_equalities, || {
+                    Self::get_join_selectivity_with_domains(&new_equalities, &join.schema, cost_model)
+                });
+                let selectivity = key_selectivity * Self::get_join_filter_selectivity(join.filter.as_ref());
+                uncertainty = Self::get_join_uncertainty(&new_equalities, join.filter.as_ref(), cost_model);
+                let null_adjusted_selectivity = self.get_null_adjusted_selectivity(
+                    &join,
+                    selectivity,
+                    &operand_row_counts,
+                    cost_model,
+                );
                 debug!(
-                    "Estimated selectivity for join {:?} is {}",
-                    join.on, selectivity
+                    "Estimated selectivity for join {:?} with filter {:?} is {}, {} after adjusting for nulls",
+                    join.on, join.filter, selectivity, null_adjusted_selectivity
                 );
-                if selectivity != 1.0 {
-                    row_count =
-                        (selectivity * operand_row_counts.iter().product::<u64>() as f64) as u64;
+                if selectivity != 1.0 || null_adjusted_selectivity != 1.0 {
+                    row_count = (null_adjusted_selectivity
+                        * operand_row_counts.iter().map(|rows| *rows as f64).product::<f64>())
+                        as u64;
+                    // Tiny selectivities would truncate to 0 rows and make the joins above look free, a join of
+                    // non-empty inputs is estimated to produce at least one row
+                    if operand_row_counts.iter().all(|rows| *rows > 0) {
+                        row_count = row_count.max(1);
+                    }
                 } else {
                     // Cross join
                     log::info!("Cross join detected, using default row count");
-                    row_count = operand_row_counts.iter().product();
+                    // Cross joins of large inputs can produce more rows than a u64 holds
+                    row_count = operand_row_counts.iter().fold(1, |product, rows| product.saturating_mul(*rows));
                 }
-                cost = JOIN_COST_PER_ROW * row_count as f64 + operand_costs;
+                // Every row of a preserved side shows up in the output, matched or not
+                if let [left_rows, right_rows] = operand_row_counts[..] {
+                    row_count = match join.join_type {
+                        JoinType::Left => row_count.max(left_rows),
+                        JoinType::Right => row_count.max(right_rows),
+                        JoinType::Full => row_count.max(left_rows).max(right_rows),
+                        _ => row_count,
+                    };
+                }
+                // Penalize cross joins so that among otherwise equal plans the ones joining on a condition win
+                let cost_per_row = if selectivity == 1.0 && join.on.is_empty() {
+                    JOIN_COST_PER_ROW * cost_model.get_cross_join_penalty()
+                } else {
+                    JOIN_COST_PER_ROW
+                };
+                cost = cost_per_row * row_count as f64 + operand_costs;
+            }
+            LogicalPlan::SubqueryAlias(_) => {
+                // Renaming the input is free
+                row_count = operand_row_counts
+                    .first()
+                    .cloned()
+                    .unwrap_or(DEFAULT_ROW_COUNT);
+                cost = operand_costs;
+            }
+            LogicalPlan::Aggregate(agg) => {
+                let input_row_count = operand_row_counts
+                    .first()
+                    .cloned()
+                    .unwrap_or(DEFAULT_ROW_COUNT);
+                // There are at most as many groups as combinations of the grouping columns' values, without NDV stats
+                // for them we assume every input row is its own group
+                let grouping_columns = agg.group_expr.iter().flat_map(|expr| expr.column_refs());
+                row_count = if agg.group_expr.is_empty() {
+                    1
+                } else {
+                    match get_value_combinations(grouping_columns, cost_model) {
+                        Some(combinations) => input_row_count.min(combinations),
+                        None => input_row_count,
+                    }
+                };
+                cost = AGGREGATE_COST_PER_ROW * input_row_count as f64 + operand_costs;
+            }
+            LogicalPlan::Distinct(distinct) => {
+                let input_row_count = operand_row_counts
+                    .first()
+                    .cloned()
+                    .unwrap_or(DEFAULT_ROW_COUNT);
+                // There are at most as many distinct rows as combinations of the distinct columns' values
+                row_count = match self.get_distinct_value_combinations(&distinct, cost_model) {
+                    Some(combinations) => input_row_count.min(combinations),
+                    None => input_row_count,
+                };
+                cost = AGGREGATE_COST_PER_ROW * input_row_count as f64 + operand_costs;
+            }
+            LogicalPlan::Window(_) => {
+                // Every input row gets its window values, after sorting the input on the partition and order keys
+                row_count = operand_row_counts
+                    .first()
+                    .cloned()
+                    .unwrap_or(DEFAULT_ROW_COUNT);
+                cost = get_sort_cost(row_count as f64) + operand_costs;
+            }
+            LogicalPlan::Sort(sort) => {
+                // Logically the input is always sorted, an ordering provided by the physical plan can spare the sort
+                let input_row_count = operand_row_counts
+                    .first()
+                    .cloned()
+                    .unwrap_or(DEFAULT_ROW_COUNT);
+                row_count = match sort.fetch {
+                    Some(fetch) => input_row_count.min(fetch as u64),
+                    None => input_row_count,
+                };
+                cost = get_sort_cost(input_row_count as f64) + operand_costs;
             }
             LogicalPlan::TableScan(ts) => {
-                row_count = ts.fetch.unwrap_or(DEFAULT_ROW_COUNT.try_into().unwrap()) as u64;
-                cost = row_count as f64;
+                // A known table row count is still capped by the scan's fetch
+                let table_row_count = cost_model.get_table_row_count(&ts.table_name.to_string());
+                row_count = match (table_row_count, ts.fetch) {
+                    (Some(table_rows), Some(fetch)) => table_rows.min(fetch as u64),
+                    (Some(table_rows), None) => table_rows,
+                    (None, fetch) => fetch.unwrap_or(DEFAULT_ROW_COUNT.try_into().unwrap()) as u64,
+                };
+                // Without stats the fetch, or the default, stands in for the table's size
+                table_rows = Some(table_row_count.unwrap_or(row_count));
+                // Filters pushed into the scan are assumed to prune at the source, so filtered out rows cost nothing
+                if let Some(predicate) = conjunction(ts.filters.iter().cloned()) {
+                    row_count = (Self::get_filter_selectivity(&predicate, cost_model) * row_count as f64) as u64;
+                    uncertainty = Self::get_filter_uncertainty(&predicate, cost_model);
+                }
+                cost = Self::get_scan_cost_per_row(&ts) * row_count as f64;
+            }
+            LogicalPlan::EmptyRelation(empty) => {
+                row_count = if empty.produce_one_row { 1 } else { 0 };
+                cost = 0.0;
+            }
+            LogicalPlan::Values(values) => {
+                // Producing literal rows is about as cheap as projecting them
+                row_count = values.values.len() as u64;
+                cost = PROJECT_COST_PER_ROW * row_count as f64;
             }
             _ => { /* Fix the other nodes similarly*/ }
         };
 
+        if let Some(rows) = self.get_cardinality_override(cost_model) {
+            debug!("Using the cardinality override {} instead of the estimate {}", rows, row_count);
+            cost += (rows as f64 - row_count as f64) * self.get_cost_per_row();
+            row_count = rows;
+            uncertainty = 1.0;
+        }
+
+        // Only the cost of this node scales with its own uncertainty, the operands bring their own bands
+        let self_cost = cost - operand_costs;
+        let mut cost_band = CostBand {
+            low: self_cost / uncertainty + operand_bands.low - shared_operand_cost,
+            mid: self_cost + operand_bands.mid - shared_operand_cost,
+            high: self_cost * uncertainty + operand_bands.high - shared_operand_cost,
+        };
+        if cost_model.is_risk_averse() {
+            cost = cost_band.high;
+        } else {
+            cost_band.mid = cost;
+        }
+
         self.cost = cost;
+        self.cost_band = cost_band;
         self.row_count = row_count;
+        self.known_equalities = known_equalities;
+        self.table_rows = table_rows;
+    }
+
+    /// Product of the NDVs of the columns a DISTINCT is over, None unless all of their NDVs are known
+    fn get_distinct_value_combinations(&self, distinct: &Distinct, cost_model: &CostModel) -> Option<u64> {
+        let columns: Vec<Column> = match distinct {
+            Distinct::All(_) => self.get_schema()?.columns(),
+            Distinct::On(distinct_on) => distinct_on
+                .on_expr
+                .iter()
+                .flat_map(|expr| expr.column_refs())
+                .cloned()
+                .collect(),
+        };
+        get_value_combinations(&columns, cost_model)
+    }
+
+    /// The externally estimated row count pinned for the relation set this join or scan covers, if any
+    fn get_cardinality_override(&self, cost_model: &CostModel) -> Option<u64> {
+        if !cost_model.has_cardinality_overrides() {
+            return None;
+        }
+
+        let sources: BTreeSet<String> = match &*self.op.borrow() {
+            LogicalPlan::TableScan(ts) => BTreeSet::from([ts.table_name.to_string()]),
+            LogicalPlan::Join(_) => self.operands.iter().flat_map(get_group_sources).collect(),
+            _ => return None,
+        };
+        cost_model.get_cardinality_override(&sources)
+    }
+
+    /// Scanning a row costs 1.0 for its first projected column, wider rows cost more
+    fn get_scan_cost_per_row(scan: &TableScan) -> f64 {
+        let extra_columns = scan.projected_schema.fields().len().saturating_sub(1);
+        1.0 + SCAN_COST_PER_EXTRA_COLUMN * extra_columns as f64
+    }
+
+    /// What an output row adds to the cost of this mexpr's operator
+    fn get_cost_per_row(&self) -> f64 {
+        match &*self.op.borrow() {
+            LogicalPlan::Join(_) => JOIN_COST_PER_ROW,
+            LogicalPlan::TableScan(scan) => Self::get_scan_cost_per_row(scan),
+            _ => 0.0,
+        }
+    }
+
+    // Called on a physical manifestation after its operand groups have been implemented
+    // The operator's own logical cost is swapped for its physical cost, on top of the operands' physical costs
+    pub fn update_physical_cost(&mut self) {
+        let mut operand_row_counts: Vec<u64> = Vec::new();
+        let mut operand_logical_costs = 0.0;
+        let mut operand_physical_costs = 0.0;
+
+        for operand in &self.operands {
+            let operand = operand.borrow();
+            operand_row_counts.push(operand.get_group_row_count());
+            operand_logical_costs += operand.get_group_cost();
+            operand_physical_costs += operand.get_group_physical_cost();
+        }
+
+        let self_cost = match (self.join_algorithm, operand_row_counts.as_slice()) {
+            (Some(algorithm), [left_rows, right_rows]) => {
+                JOIN_COST_PER_ROW * self.row_count as f64 + algorithm.cost(*left_rows, *right_rows)
+            }
+            // The input already comes out in the required order, so there is nothing to sort
+            _ if self.is_sort_provided_by_input() => 0.0,
+            _ => self.cost - operand_logical_costs,
+        };
+        self.cost = self_cost + operand_physical_costs;
+    }
+
+    /// Whether this is a sort whose input's cheapest physical expression already provides the sort's ordering
+    pub fn is_sort_provided_by_input(&self) -> bool {
+        let LogicalPlan::Sort(sort) = &*self.op.borrow() else {
+            return false;
+        };
+        let Some(required_ordering) = get_sort_ordering(sort) else {
+            return false;
+        };
+        self.operands[0]
+            .borrow()
+            .cheapest_physical_expression
+            .as_ref()
+            .is_some_and(|input| input.provided_ordering().starts_with(&required_ordering))
+    }
+
+    /// Adjust a join's selectivity for NULLs in its equi-join keys, using the keys' null_count stats
+    /// The null fraction of a side is taken against that join input's row count, assuming nulls survive the joins
+    /// below it. Null keys match nothing, unless the join treats nulls as equal in which case they match every null
+    /// key on the other side
+    fn get_null_adjusted_selectivity(
+        &self,
+        join: &Join,
+        selectivity: f64,
+        operand_row_counts: &[u64],
+        cost_model: &CostModel,
+    ) -> f64 {
+        let mut null_fractions = [0.0_f64; 2];
+        for column in join
+            .on
+            .iter()
+            .flat_map(|(left, right)| [left, right])
+            .filter_map(|key| match key {
+                Expr::Column(column) => Some(column),
+                _ => None,
+            })
+        {
+            let Some(null_count) = cost_model
+                .get_column_stats(column)
+                .and_then(|stats| stats.null_count)
+            else {
+                continue;
+            };
+            let Some(side) = self.operands.iter().position(|operand| {
+                operand
+                    .borrow()
+                    .start_expression
+                    .as_ref()
+                    .and_then(|expr| expr.get_schema())
+                    .is_some_and(|schema| schema.has_column(column))
+            }) else {
+                continue;
+            };
+            let Some(&rows) = operand_row_counts.get(side).filter(|rows| **rows > 0) else {
+                continue;
+            };
+            let null_fraction = (null_count as f64 / rows as f64).min(1.0);
+            null_fractions[side] = null_fractions[side].max(null_fraction);
+        }
+
+        let [left_null_fraction, right_null_fraction] = null_fractions;
+        if left_null_fraction == 0.0 && right_null_fraction == 0.0 {
+            return selectivity;
+        }
+
+        let non_null_selectivity = (1.0 - left_null_fraction) * (1.0 - right_null_fraction) * selectivity;
+        match join.null_equality {
+            NullEquality::NullEqualsNothing => non_null_selectivity,
+            NullEquality::NullEqualsNull => non_null_selectivity + left_null_fraction * right_null_fraction,
+        }
     }
 
     pub fn get_schema(&self) -> Option<Arc<DFSchema>> {
@@ -140,6 +696,10 @@ impl MExpr {
                 LogicalPlan::Projection(proj) => return Some(proj.schema),
                 LogicalPlan::Filter(filter) => current_node = (*filter.input).clone(),
                 LogicalPlan::Aggregate(agg) => return Some(agg.schema),
+                LogicalPlan::SubqueryAlias(alias) => return Some(alias.schema),
+                LogicalPlan::Window(window) => return Some(window.schema),
+                LogicalPlan::Distinct(Distinct::All(input)) => current_node = (*input).clone(),
+                LogicalPlan::Distinct(Distinct::On(distinct_on)) => return Some(distinct_on.schema),
                 LogicalPlan::Join(join) => return Some(join.schema),
                 LogicalPlan::Sort(sort) => current_node = (*sort.input).clone(),
                 LogicalPlan::TableScan(scan) => return Some(scan.projected_schema.clone()),
@@ -152,6 +712,7 @@ impl MExpr {
                     }
                 }
                 LogicalPlan::EmptyRelation(empty) => return Some(empty.schema.clone()),
+                LogicalPlan::Values(values) => return Some(values.schema.clone()),
                 _ => return None, // Handle other cases or stop if schema is not found
             }
         }
@@ -164,6 +725,9 @@ impl MExpr {
     pub fn cost(&self) -> f64 {
         self.cost
     }
+    pub fn cost_band(&self) -> CostBand {
+        self.cost_band
+    }
     pub fn op(&self) -> Rc<RefCell<LogicalPlan>> {
         Rc::clone(&self.op)
     }
@@ -173,57 +737,295 @@ impl MExpr {
     pub fn canonicalized(&self) -> &str {
         &self.canonicalized
     }
+    pub fn join_algorithm(&self) -> Option<JoinAlgorithm> {
+        self.join_algorithm
+    }
+    pub fn provided_ordering(&self) -> &[Column] {
+        &self.provided_ordering
+    }
+    pub fn set_provided_ordering(&mut self, provided_ordering: Vec<Column>) {
+        self.provided_ordering = provided_ordering;
+    }
     pub fn row_count(&self) -> u64 {
         self.row_count
     }
+    pub fn table_rows(&self) -> Option<u64> {
+        self.table_rows
+    }
 
     pub fn get_join_selectivity(join_on: &[(Expr, Expr)]) -> f64 {
-        let mut total_selectivity = 1.0;
+        get_unique_equalities(join_on)
+            .iter()
+            .map(|(left_expr, right_expr)| {
+                Self::get_equality_selectivity(left_expr, right_expr, DEFAULT_JOIN_SELECTIVITY)
+            })
+            .product()
+    }
 
-        for (left_expr, right_expr) in get_unique_equalities(join_on) {
-            let mut left_table = None;
-            let mut right_table = None;
+    /// Like get_join_selectivity, but join keys between two columns with known NDVs are estimated from those
+    pub fn get_join_selectivity_with_stats(join_on: &[(Expr, Expr)], cost_model: &CostModel) -> f64 {
+        get_unique_equalities(join_on)
+            .iter()
+            .map(|(left_expr, right_expr)| Self::get_key_selectivity_with_stats(left_expr, right_expr, cost_model))
+            .product()
+    }
 
-            // Parse the left expression to determine the table used
-            if let Expr::Column(column) = &left_expr {
-                if let Some(table_ref) = &column.relation {
-                    left_table = Some(table_ref.to_string());
-                } else {
-                    debug!("Left Table reference is not available");
+    /// Like get_join_selectivity_with_stats, but a join key between columns without known NDVs is no more selective
+    /// than the domains of their data types in `schema` allow, e.g. a Boolean key has at most 2 distinct values
+    pub fn get_join_selectivity_with_domains(join_on: &[(Expr, Expr)], schema: &DFSchema, cost_model: &CostModel) -> f64 {
+        get_unique_equalities(join_on)
+            .iter()
+            .map(|(left_expr, right_expr)| {
+                let selectivity = Self::get_key_selectivity_with_stats(left_expr, right_expr, cost_model);
+                let (Expr::Column(left), Expr::Column(right)) = (left_expr, right_expr) else {
+                    return selectivity;
+                };
+                if cost_model.has_selectivity_fn() || cost_model.get_equi_join_selectivity(left, right).is_some() {
+                    return selectivity;
+                }
+                let domain_ndv = |column: &Column| {
+                    let (_, field) = schema.qualified_field_from_column(column).ok()?;
+                    get_domain_ndv(field.data_type())
+                };
+                match (domain_ndv(left), domain_ndv(right)) {
+                    (Some(left_ndv), Some(right_ndv)) => selectivity.max(1.0 / left_ndv.max(right_ndv) as f64),
+                    _ => selectivity,
+                }
+            })
+            .product()
+    }
+
+    fn get_key_selectivity_with_stats(left_expr: &Expr, right_expr: &Expr, cost_model: &CostModel) -> f64 {
+        let default_selectivity = cost_model.get_default_join_selectivity();
+        match (left_expr, right_expr) {
+            (Expr::Column(left), Expr::Column(right)) => cost_model
+                .get_custom_join_selectivity(left, right)
+                .or_else(|| cost_model.get_equi_join_selectivity(left, right))
+                .unwrap_or_else(|| Self::get_equality_selectivity(left_expr, right_expr, default_selectivity)),
+            _ => Self::get_equality_selectivity(left_expr, right_expr, default_selectivity),
+        }
+    }
+
+    /// Factor the selectivity of a join's keys and residual filter may be off by, compounded over the keys
+    fn get_join_uncertainty(join_on: &[(Expr, Expr)], filter: Option<&Expr>, cost_model: &CostModel) -> f64 {
+        let mut uncertainty: f64 = get_unique_equalities(join_on)
+            .iter()
+            .map(|(left_expr, right_expr)| match (left_expr, right_expr) {
+                (Expr::Column(left), Expr::Column(right)) if cost_model.get_equi_join_selectivity(left, right).is_some() => {
+                    STATS_SELECTIVITY_UNCERTAINTY
                 }
+                (Expr::Column(_), Expr::Column(_)) if Self::has_canned_selectivity(left_expr, right_expr) => {
+                    CANNED_SELECTIVITY_UNCERTAINTY
+                }
+                _ => DEFAULT_SELECTIVITY_UNCERTAINTY,
+            })
+            .product();
+        if Self::get_join_filter_selectivity(filter) != 1.0 {
+            uncertainty *= DEFAULT_SELECTIVITY_UNCERTAINTY;
+        }
+        uncertainty
+    }
+
+    fn has_canned_selectivity(left_expr: &Expr, right_expr: &Expr) -> bool {
+        let (Some(left), Some(right)) = (Self::get_key_table(left_expr), Self::get_key_table(right_expr)) else {
+            return false;
+        };
+        SELECTIVITY_MAP.contains_key(&(left.as_str(), right.as_str()))
+            || SELECTIVITY_MAP.contains_key(&(right.as_str(), left.as_str()))
+    }
+
+    /// Pre-canned selectivity of a single join key between the tables of its two sides
+    /// A key between tables without a pre-canned selectivity still joins them, it gets `default_selectivity`
+    /// Sides computed from columns, e.g. `UPPER(t1.a)` or `t1.a + 1`, are resolved to the table of their columns, but
+    /// the selectivity is only trusted with COMPUTED_JOIN_KEY_CONFIDENCE since the expression may not keep the NDV
+    fn get_equality_selectivity(left_expr: &Expr, right_expr: &Expr, default_selectivity: f64) -> f64 {
+        let (Some(left), Some(right)) = (Self::get_key_table(left_expr), Self::get_key_table(right_expr)) else {
+            return 1.0;
+        };
+
+        let selectivity = match SELECTIVITY_MAP
+            .get(&(left.as_str(), right.as_str()))
+            .or_else(|| SELECTIVITY_MAP.get(&(right.as_str(), left.as_str())))
+        {
+            Some(&selectivity) => selectivity,
+            None => {
+                debug!(
+                    "Selectivity not found for tables: ({}, {}), using the default {}",
+                    left, right, default_selectivity
+                );
+                default_selectivity
+            }
+        };
+        if matches!((left_expr, right_expr), (Expr::Column(_), Expr::Column(_))) {
+            selectivity
+        } else {
+            selectivity.powf(COMPUTED_JOIN_KEY_CONFIDENCE)
+        }
+    }
+
+    /// The table a join key reads from, None if it references no columns, columns of more than one table or
+    /// unqualified columns
+    fn get_key_table(key: &Expr) -> Option<String> {
+        let mut tables = key
+            .column_refs()
+            .into_iter()
+            .map(|column| column.relation.as_ref().map(|table| table.to_string()));
+        let table = tables.next()??;
+        if !tables.all(|other| other.as_ref() == Some(&table)) {
+            debug!("Join key {} does not read from a single table", key);
+            return None;
+        }
+        Some(table)
+    }
+
+    /// Estimate the selectivity of a Filter predicate
+    /// `column = literal` conjuncts use a 1/NDV point selectivity, range conjuncts the fraction of the column's
+    /// min/max range they cover, all remaining conjuncts together are assumed to reduce rows by 90%
+    pub fn get_filter_selectivity(predicate: &Expr, cost_model: &CostModel) -> f64 {
+        let mut selectivity = 1.0;
+        let mut has_unestimated_conjuncts = false;
+
+        for expr in split_conjunction(predicate) {
+            if let Some(column) = get_column_literal_equality(expr) {
+                selectivity *= cost_model.get_point_selectivity(column);
+            } else if let Some(range_selectivity) = Self::get_filter_range_selectivity(expr, cost_model) {
+                selectivity *= range_selectivity;
             } else {
-                debug!("Left expression is not a column");
+                has_unestimated_conjuncts = true;
             }
+        }
 
-            // Parse the right expression to determine the table used
-            if let Expr::Column(column) = &right_expr {
-                if let Some(table_ref) = &column.relation {
-                    right_table = Some(table_ref.to_string());
-                } else {
-                    debug!("Right Table reference is not available");
-                }
+        if has_unestimated_conjuncts {
+            selectivity *= DEFAULT_FILTER_SELECTIVITY;
+        }
+        selectivity
+    }
+
+    /// Factor the selectivity get_filter_selectivity estimates for a predicate may be off by
+    fn get_filter_uncertainty(predicate: &Expr, cost_model: &CostModel) -> f64 {
+        let mut uncertainty = 1.0;
+        let mut has_unestimated_conjuncts = false;
+
+        for expr in split_conjunction(predicate) {
+            if let Some(column) = get_column_literal_equality(expr) {
+                uncertainty *= match cost_model.get_ndv(column) {
+                    Some(ndv) if ndv > 0 => STATS_SELECTIVITY_UNCERTAINTY,
+                    _ => DEFAULT_SELECTIVITY_UNCERTAINTY,
+                };
+            } else if Self::get_filter_range_selectivity(expr, cost_model).is_some() {
+                uncertainty *= STATS_SELECTIVITY_UNCERTAINTY;
             } else {
-                debug!("Right expression is not a column");
+                has_unestimated_conjuncts = true;
             }
+        }
+
+        if has_unestimated_conjuncts {
+            uncertainty *= DEFAULT_SELECTIVITY_UNCERTAINTY;
+        }
+        uncertainty
+    }
+
+    /// Selectivity of a `column <op> literal` or `column [NOT] BETWEEN literal AND literal` filter conjunct,
+    /// None if it is neither or the column has no min/max stats
+    fn get_filter_range_selectivity(expr: &Expr, cost_model: &CostModel) -> Option<f64> {
+        if let Some((column, op, value)) = get_column_literal_range(expr) {
+            return cost_model.get_range_selectivity(&column, op, &value);
+        }
 
-            // Lookup selectivity if both tables are resolved
-            if let (Some(left), Some(right)) = (left_table, right_table) {
-                if let Some(&selectivity) = SELECTIVITY_MAP.get(&(left.as_str(), right.as_str())) {
-                    total_selectivity *= selectivity;
-                } else if let Some(&selectivity) =
-                    SELECTIVITY_MAP.get(&(right.as_str(), left.as_str()))
+        let Expr::Between(Between { expr, negated, low, high }) = expr else {
+            return None;
+        };
+        let (Expr::Column(column), Expr::Literal(low, _), Expr::Literal(high, _)) =
+            (expr.as_ref(), low.as_ref(), high.as_ref())
+        else {
+            return None;
+        };
+        let selectivity = cost_model.get_between_selectivity(column, low, high)?;
+        Some(if *negated { 1.0 - selectivity } else { selectivity })
+    }
+
+    /// The join's equi keys, plus the `column = column` conjuncts of its residual filter
+    /// Joins built with the condition in the filter rather than in `on` would otherwise be estimated as cross joins
+    fn get_join_equalities(join: &Join) -> Vec<(Expr, Expr)> {
+        let mut equalities = join.on.clone();
+        if let Some(filter) = &join.filter {
+            for expr in split_conjunction(filter) {
+                if let Expr::BinaryExpr(BinaryExpr { left, op: Operator::Eq, right }) = expr
+                    && matches!((left.as_ref(), right.as_ref()), (Expr::Column(_), Expr::Column(_)))
                 {
-                    total_selectivity *= selectivity;
+                    equalities.push((left.as_ref().clone(), right.as_ref().clone()));
+                }
+            }
+        }
+        equalities
+    }
+
+    /// Estimate the selectivity of the non-equi predicates in a join's residual filter
+    /// Range comparisons and BETWEEN use pre-canned selectivities, ORs combine their disjuncts as independent events,
+    /// anything else is assumed to not filter
+    /// Equalities in the filter are estimated along with the equi keys, see get_join_equalities
+    pub fn get_join_filter_selectivity(join_filter: Option<&Expr>) -> f64 {
+        let Some(filter) = join_filter else {
+            return 1.0;
+        };
+
+        split_conjunction(filter)
+            .into_iter()
+            .map(Self::get_join_predicate_selectivity)
+            .product()
+    }
+
+    fn get_join_predicate_selectivity(expr: &Expr) -> f64 {
+        match expr {
+            Expr::BinaryExpr(BinaryExpr {
+                op: Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq,
+                ..
+            }) => RANGE_JOIN_SELECTIVITY,
+            Expr::Between(Between { negated, .. }) => {
+                if *negated {
+                    1.0 - BETWEEN_JOIN_SELECTIVITY
                 } else {
-                    debug!("Selectivity not found for tables: ({}, {})", left, right);
+                    BETWEEN_JOIN_SELECTIVITY
                 }
             }
+            // P(A or B) = P(A) + P(B) - P(A)P(B)
+            Expr::BinaryExpr(BinaryExpr { left, op: Operator::Or, right }) => {
+                let left = Self::get_join_disjunct_selectivity(left);
+                let right = Self::get_join_disjunct_selectivity(right);
+                left + right - left * right
+            }
+            _ => {
+                debug!("No selectivity estimate for join filter predicate {}", expr);
+                1.0
+            }
         }
+    }
 
-        total_selectivity
+    /// Selectivity of one side of an OR in a join filter
+    /// Unlike top-level conjuncts, its `column = column` equalities are not join keys, so they are estimated here
+    fn get_join_disjunct_selectivity(disjunct: &Expr) -> f64 {
+        split_conjunction(disjunct)
+            .into_iter()
+            .map(|expr| match expr {
+                Expr::BinaryExpr(BinaryExpr { left, op: Operator::Eq, right })
+                    if matches!((left.as_ref(), right.as_ref()), (Expr::Column(_), Expr::Column(_))) =>
+                {
+                    Self::get_equality_selectivity(left, right, DEFAULT_JOIN_SELECTIVITY)
+                }
+                _ => Self::get_join_predicate_selectivity(expr),
+            })
+            .product()
     }
 }
 
+/// Number of combinations of the values of `columns`, the product of their NDVs, None if an NDV is unknown
+fn get_value_combinations<'a>(columns: impl IntoIterator<Item = &'a Column>, cost_model: &CostModel) -> Option<u64> {
+    columns
+        .into_iter()
+        .map(|column| cost_model.get_ndv(column))
+        .try_fold(1_u64, |combinations, ndv| Some(combinations.saturating_mul(ndv?)))
+}
+
 impl Hash for MExpr {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.hash.hash(state);
@@ -231,8 +1033,26 @@ impl Hash for MExpr {
 }
 
 impl PartialEq for MExpr {
+    /// Mexprs with equal content hashes are only equal if they also agree on what the hash was computed over, so a
+    /// genuine Xxh3 collision does not merge two different expressions
     fn eq(&self, other: &Self) -> bool {
-        self.hash == other.hash
+        if self.hash != other.hash {
+            return false;
+        }
+        if self.join_algorithm != other.join_algorithm || self.operands.len() != other.operands.len() {
+            return false;
+        }
+        let same_operands = self.operands.iter().zip(&other.operands).all(|(left, right)| {
+            Rc::ptr_eq(left, right) || left.borrow().get_group_hash() == right.borrow().get_group_hash()
+        });
+        if !same_operands {
+            return false;
+        }
+        if Rc::ptr_eq(&self.op, &other.op) {
+            return true;
+        }
+
+        Self::record_operator(&self.op.borrow()) == Self::record_operator(&other.op.borrow())
     }
 }
 
@@ -255,3 +1075,548 @@ lazy_static! {
         map
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cascades::Cascades;
+    use crate::cascades::cost_model::ColumnStats;
+    use crate::cascades::test_utils::{build_table_scan, build_typed_table_scan};
+    use datafusion::arrow::datatypes::DataType;
+    use datafusion_expr::{LogicalPlanBuilder, col, lit};
+
+    #[test]
+    fn test_range_join_selectivity() {
+        let t1 = build_table_scan("t1", &["a1"], None);
+        let t2 = build_table_scan("t2", &["lo", "hi"], None);
+
+        // t1.a1 BETWEEN t2.lo AND t2.hi is carried as the join filter, with no equi keys
+        let band_join = LogicalPlanBuilder::from(t1)
+            .join_on(
+                t2,
+                JoinType::Inner,
+                vec![col("t1.a1").between(col("t2.lo"), col("t2.hi"))],
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let LogicalPlan::Join(join) = &band_join else {
+            panic!("Expected a Join node");
+        };
+        assert!(join.on.is_empty());
+        let selectivity = MExpr::get_join_filter_selectivity(join.filter.as_ref());
+        assert!(selectivity < 1.0);
+
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(band_join)));
+        cascades.optimize(root_group.clone());
+
+        let cross_join_rows = DEFAULT_ROW_COUNT * DEFAULT_ROW_COUNT;
+        assert!(root_group.borrow().get_group_row_count() < cross_join_rows);
+    }
+
+    #[test]
+    fn test_or_join_filter_selectivity() {
+        let t1 = build_table_scan("t1", &["a1", "c1"], None);
+        let t2 = build_table_scan("t2", &["a2", "c2"], None);
+
+        // Neither side of the OR can be used as an equi key, the whole OR is carried as the join filter
+        let a_equality = col("t1.a1").eq(col("t2.a2"));
+        let c_equality = col("t1.c1").eq(col("t2.c2"));
+        let or_join = LogicalPlanBuilder::from(t1)
+            .join_on(t2, JoinType::Inner, vec![a_equality.clone().or(c_equality.clone())])
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let LogicalPlan::Join(join) = &or_join else {
+            panic!("Expected a Join node");
+        };
+        assert!(join.on.is_empty());
+        let selectivity = MExpr::get_join_filter_selectivity(join.filter.as_ref());
+        let a_selectivity = MExpr::get_join_disjunct_selectivity(&a_equality);
+        let c_selectivity = MExpr::get_join_disjunct_selectivity(&c_equality);
+        println!("OR selectivity {} of disjuncts {} and {}", selectivity, a_selectivity, c_selectivity);
+        assert!(selectivity < 1.0);
+        assert!(selectivity > a_selectivity && selectivity > c_selectivity);
+        assert!((selectivity - (a_selectivity + c_selectivity - a_selectivity * c_selectivity)).abs() < 1e-12);
+
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(or_join)));
+        cascades.optimize(root_group.clone());
+
+        let cross_join_rows = DEFAULT_ROW_COUNT * DEFAULT_ROW_COUNT;
+        assert!(root_group.borrow().get_group_row_count() < cross_join_rows);
+    }
+
+    #[test]
+    fn test_aggregate_group_count_from_ndv() {
+        let group_count = |input_rows: usize, group_by: Vec<Expr>| {
+            let plan = LogicalPlanBuilder::from(build_table_scan("t1", &["a1", "b1"], Some(input_rows)))
+                .aggregate(group_by, Vec::<Expr>::new())
+                .unwrap()
+                .build()
+                .unwrap();
+            let mut cascades = Cascades::default();
+            cascades.set_column_stats("t1", "a1", ColumnStats::with_ndv(3));
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+            cascades.optimize(root_group.clone());
+            root_group.borrow().get_group_row_count()
+        };
+
+        for input_rows in [10, 1000, 10_000_000] {
+            assert_eq!(group_count(input_rows, vec![col("t1.a1")]), 3);
+        }
+        // Fewer input rows than value combinations, and a grouping column without stats
+        assert_eq!(group_count(2, vec![col("t1.a1")]), 2);
+        assert_eq!(group_count(1000, vec![col("t1.a1"), col("t1.b1")]), 1000);
+    }
+
+    #[test]
+    fn test_join_selectivity_cache() {
+        let mut cascades = Cascades::default();
+        let root_group = cascades.seed_memo("12");
+        cascades.optimize(root_group.clone());
+        let mut join = root_group.borrow().start_expression.clone().unwrap();
+        let mut swapped = MExpr::build_with_node(join.op(), join.operands().iter().rev().cloned().collect());
+
+        let mut cost_model = CostModel::default();
+        join.update_cost_and_rowcount(&cost_model);
+        let row_count = join.row_count();
+        assert_eq!(cost_model.selectivity_cache_hits(), 0);
+
+        // The same join keys are not estimated again, whichever way round the inputs are
+        join.update_cost_and_rowcount(&cost_model);
+        swapped.update_cost_and_rowcount(&cost_model);
+        assert_eq!(cost_model.selectivity_cache_hits(), 2);
+        assert_eq!(swapped.row_count(), row_count);
+
+        // New stats invalidate the cache
+        cost_model.set_column_stats("t1", "a1", ColumnStats::with_ndv(1));
+        cost_model.set_column_stats("t2", "a2", ColumnStats::with_ndv(1));
+        join.update_cost_and_rowcount(&cost_model);
+        assert_eq!(cost_model.selectivity_cache_hits(), 2);
+        assert_ne!(join.row_count(), row_count);
+    }
+
+    #[test]
+    fn test_constant_equality_filter() {
+        let filtered_scan = || {
+            LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], Some(1000)))
+                .filter(col("t1.a1").eq(lit(5)))
+                .unwrap()
+                .build()
+                .unwrap()
+        };
+
+        // Without stats we fall back to the flat filter selectivity
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(filtered_scan())));
+        cascades.optimize(root_group.clone());
+        assert_eq!(root_group.borrow().get_group_row_count(), 100);
+
+        // With an NDV of 50, a point lookup keeps 1/50th of the rows
+        let mut cascades = Cascades::default();
+        cascades.set_column_stats("t1", "a1", ColumnStats::with_ndv(50));
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(filtered_scan())));
+        cascades.optimize(root_group.clone());
+        assert_eq!(root_group.borrow().get_group_row_count(), 20);
+    }
+
+    #[test]
+    fn test_null_aware_join_cardinality() {
+        let join_rows = |null_equality: Option<NullEquality>| {
+            let mut plan = LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], Some(1000)))
+                .join_with_expr_keys(
+                    build_table_scan("t2", &["a2"], Some(1000)),
+                    JoinType::Inner,
+                    (vec![col("t1.a1")], vec![col("t2.a2")]),
+                    None,
+                )
+                .unwrap()
+                .build()
+                .unwrap();
+            if let (LogicalPlan::Join(join), Some(null_equality)) = (&mut plan, null_equality) {
+                join.null_equality = null_equality;
+            }
+
+            let mut cascades = Cascades::default();
+            cascades.set_column_stats("t1", "a1", ColumnStats::with_null_count(500));
+            cascades.set_column_stats("t2", "a2", ColumnStats::with_null_count(100));
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+            cascades.optimize(root_group.clone());
+            root_group.borrow().get_group_row_count()
+        };
+
+        // 1000 * 1000 * 0.001 = 1000 rows without nulls, half of t1 and a tenth of t2 can't match
+        let nulls_unequal = join_rows(None);
+        assert_eq!(nulls_unequal, 450);
+
+        // Treating nulls as equal, the 500 * 100 null pairs match as well
+        let nulls_equal = join_rows(Some(NullEquality::NullEqualsNull));
+        assert_eq!(nulls_equal, 450 + 50000);
+        assert_ne!(nulls_equal, nulls_unequal);
+    }
+
+    #[test]
+    fn test_range_filter_selectivity() {
+        let filtered_scan = |predicate: Expr| {
+            LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], Some(1000)))
+                .filter(predicate)
+                .unwrap()
+                .build()
+                .unwrap()
+        };
+        let filtered_rows = |predicate: Expr, stats: Option<ColumnStats>| {
+            let mut cascades = Cascades::default();
+            if let Some(stats) = stats {
+                cascades.set_column_stats("t1", "a1", stats);
+            }
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(filtered_scan(predicate))));
+            cascades.optimize(root_group.clone());
+            root_group.borrow().get_group_row_count()
+        };
+
+        // Without a range we fall back to the flat filter selectivity
+        assert_eq!(filtered_rows(col("t1.a1").lt(lit(50)), None), 100);
+
+        // Half of [0, 100] is below 50, and the flipped form is estimated the same way
+        let stats = ColumnStats::with_range(0.0, 100.0);
+        assert_eq!(filtered_rows(col("t1.a1").lt(lit(50)), Some(stats.clone())), 500);
+        assert_eq!(filtered_rows(lit(50).gt(col("t1.a1")), Some(stats.clone())), 500);
+        assert_eq!(filtered_rows(col("t1.a1").gt_eq(lit(75)), Some(stats.clone())), 250);
+        assert_eq!(filtered_rows(col("t1.a1").between(lit(10), lit(30)), Some(stats.clone())), 200);
+
+        // Values outside of the range clamp the selectivity
+        assert_eq!(filtered_rows(col("t1.a1").lt(lit(500)), Some(stats)), 1000);
+    }
+
+    #[test]
+    fn test_join_condition_in_filter() {
+        let t1 = build_table_scan("t1", &["a1"], Some(1000));
+        let t2 = build_table_scan("t2", &["a2"], Some(1000));
+
+        // join_on keeps the whole condition in the join filter
+        let filter_join = LogicalPlanBuilder::from(t1)
+            .join_on(t2, JoinType::Inner, vec![col("t1.a1").eq(col("t2.a2"))])
+            .unwrap()
+            .build()
+            .unwrap();
+        let LogicalPlan::Join(join) = &filter_join else {
+            panic!("Expected a Join node");
+        };
+        assert!(join.on.is_empty());
+        assert!(join.filter.is_some());
+
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(filter_join)));
+        cascades.optimize(root_group.clone());
+
+        // Scanning both inputs costs their row counts, the rest is the join itself
+        let join_cost = root_group.borrow().get_group_cost() - 2000.0;
+        let cross_join_cost = JOIN_COST_PER_ROW * 1000.0 * 1000.0;
+        assert_eq!(root_group.borrow().get_group_row_count(), 1000);
+        assert!(join_cost < cross_join_cost / 100.0);
+    }
+
+    #[test]
+    fn test_limit_hash_includes_fetch() {
+        let scan = Group::from_mexpr(MExpr::build_with_node(
+            Rc::new(RefCell::new(build_table_scan("t1", &["a1"], None))),
+            vec![],
+        ));
+        let build_limit = |fetch| {
+            let limit = LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], None))
+                .limit(0, Some(fetch))
+                .unwrap()
+                .build()
+                .unwrap();
+            MExpr::build_with_node(Rc::new(RefCell::new(limit)), vec![Rc::clone(&scan)])
+        };
+
+        assert_eq!(build_limit(10).hash(), build_limit(10).hash());
+        assert_ne!(build_limit(10).hash(), build_limit(20).hash());
+    }
+
+    #[test]
+    fn test_outer_join_keeps_preserved_rows() {
+        let estimate_rows = |join_type| {
+            let t1 = build_table_scan("t1", &["a1"], Some(1000));
+            let t2 = build_table_scan("t2", &["a2"], Some(100));
+            let join = LogicalPlanBuilder::from(t1)
+                .join_with_expr_keys(t2, join_type, (vec![col("a1")], vec![col("a2")]), None)
+                .unwrap()
+                .build()
+                .unwrap();
+
+            let mut cascades = Cascades::default();
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(join)));
+            cascades.optimize(root_group.clone());
+            root_group.borrow().get_group_row_count()
+        };
+
+        let inner_rows = estimate_rows(JoinType::Inner);
+        let left_rows = estimate_rows(JoinType::Left);
+        assert!(inner_rows < 1000);
+        assert!(left_rows >= 1000);
+        assert!(left_rows >= inner_rows);
+    }
+
+    #[test]
+    fn test_default_join_selectivity() {
+        // t6 and t7 have no pre-canned selectivity, but are still joined on a key
+        let join_on = [(col("t6.a6"), col("t7.a7"))];
+        assert_eq!(MExpr::get_join_selectivity(&join_on), DEFAULT_JOIN_SELECTIVITY);
+        assert_eq!(MExpr::get_join_selectivity(&[]), 1.0);
+
+        let mut cost_model = CostModel::default();
+        cost_model.set_default_join_selectivity(0.05);
+        assert_eq!(MExpr::get_join_selectivity_with_stats(&join_on, &cost_model), 0.05);
+        assert_eq!(MExpr::get_join_selectivity_with_stats(&[(col("t1.a1"), col("t2.a2"))], &cost_model), 0.001);
+    }
+
+    #[test]
+    fn test_join_key_domain_selectivity() {
+        let join_rows = |data_type: DataType| {
+            let plan = LogicalPlanBuilder::from(build_typed_table_scan("t1", &[("a1", data_type.clone())], Some(1000)))
+                .join_with_expr_keys(
+                    build_typed_table_scan("t2", &[("a2", data_type)], Some(1000)),
+                    JoinType::Inner,
+                    (vec![col("t1.a1")], vec![col("t2.a2")]),
+                    None,
+                )
+                .unwrap()
+                .build()
+                .unwrap();
+
+            let mut cascades = Cascades::default();
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+            cascades.optimize(root_group.clone());
+            root_group.borrow().get_group_row_count()
+        };
+
+        // An Int32 key's domain doesn't bound the pre-canned 0.001, a Boolean key matches half of the other side
+        assert_eq!(join_rows(DataType::Int32), 1000);
+        assert_eq!(join_rows(DataType::Boolean), 500_000);
+        assert_eq!(join_rows(DataType::Int8), 1000 * 1000 / 256);
+    }
+
+    #[test]
+    fn test_computed_join_key_selectivity() {
+        // Computed keys still resolve to the t1, t2 pair, at a less selective estimate than the plain columns
+        let plain = MExpr::get_join_selectivity(&[(col("t1.a1"), col("t2.a2"))]);
+        let computed = MExpr::get_join_selectivity(&[(col("t1.a1") + lit(1), col("t2.a2"))]);
+        assert!(computed < 1.0);
+        assert!(computed > plain);
+        assert_eq!(computed, plain.powf(COMPUTED_JOIN_KEY_CONFIDENCE));
+
+        // A key reading from both tables cannot be attributed to either side
+        assert_eq!(MExpr::get_join_selectivity(&[(col("t1.a1") + col("t2.a2"), col("t3.a3"))]), 1.0);
+
+        let join = LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], Some(1000)))
+            .join_with_expr_keys(
+                build_table_scan("t2", &["a2"], Some(1000)),
+                JoinType::Inner,
+                (vec![col("t1.a1") + lit(1)], vec![col("t2.a2")]),
+                None,
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(join)));
+        cascades.optimize(root_group.clone());
+        assert!(root_group.borrow().get_group_row_count() < 1000 * 1000);
+    }
+
+    #[test]
+    fn test_cross_join_penalty() {
+        let join_rows_and_self_cost = |plan: LogicalPlan| {
+            let mut cascades = Cascades::default();
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+            cascades.optimize(root_group.clone());
+            let plan = crate::cascades::util::get_cheapest_plan(root_group).unwrap();
+            (plan.row_count, plan.self_cost())
+        };
+
+        // 1000 x 100 rows at the t1, t2 selectivity of 0.001, and a 10 x 10 cross join, both produce 100 rows
+        let equi_join = LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], Some(1000)))
+            .join_with_expr_keys(
+                build_table_scan("t2", &["a2"], Some(100)),
+                JoinType::Inner,
+                (vec![col("a1")], vec![col("a2")]),
+                None,
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        let cross_join = LogicalPlanBuilder::from(build_table_scan("t3", &["a3"], Some(10)))
+            .cross_join(build_table_scan("t4", &["a4"], Some(10)))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let (equi_join_rows, equi_join_cost) = join_rows_and_self_cost(equi_join);
+        let (cross_join_rows, cross_join_cost) = join_rows_and_self_cost(cross_join);
+        assert_eq!(equi_join_rows, cross_join_rows);
+        assert!(cross_join_cost > equi_join_cost * 100.0);
+    }
+
+    #[test]
+    fn test_scan_cost() {
+        let scan_cost_and_rows = |plan: LogicalPlan| {
+            let mut mexpr = MExpr::build_with_node(Rc::new(RefCell::new(plan)), vec![]);
+            mexpr.update_cost_and_rowcount(&CostModel::default());
+            (mexpr.cost(), mexpr.row_count())
+        };
+
+        let columns: Vec<String> = (0..10).map(|i| format!("c{i}")).collect();
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        let (narrow_cost, narrow_rows) = scan_cost_and_rows(build_table_scan("t1", &columns[..1], Some(1000)));
+        let (wide_cost, wide_rows) = scan_cost_and_rows(build_table_scan("t1", &columns, Some(1000)));
+        assert_eq!(narrow_rows, wide_rows);
+        assert!(narrow_cost < wide_cost);
+
+        let LogicalPlan::TableScan(mut filtered_scan) = build_table_scan("t1", &columns[..1], Some(1000)) else {
+            panic!("Expected a TableScan node");
+        };
+        filtered_scan.filters = vec![col("c0").gt(lit(5))];
+        let mut filtered = MExpr::build_with_node(Rc::new(RefCell::new(LogicalPlan::TableScan(filtered_scan))), vec![]);
+        filtered.update_cost_and_rowcount(&CostModel::default());
+        assert!(filtered.row_count() < narrow_rows);
+        assert!(filtered.cost() < narrow_cost);
+        // The table's size is kept apart from the rows left after the filters
+        assert_eq!(filtered.table_rows(), Some(narrow_rows));
+    }
+
+    #[test]
+    fn test_repeated_equality_is_not_applied_twice() {
+        let chain_rows = |condition: Vec<Expr>| {
+            let plan = LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], Some(1000)))
+                .join_with_expr_keys(
+                    build_table_scan("t2", &["a2", "b2"], Some(1000)),
+                    JoinType::Inner,
+                    (vec![col("a1")], vec![col("a2")]),
+                    None,
+                )
+                .unwrap()
+                .join_on(build_table_scan("t3", &["b3"], Some(1000)), JoinType::Inner, condition)
+                .unwrap()
+                .build()
+                .unwrap();
+
+            let mut cascades = Cascades::default();
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+            cascades.optimize(root_group.clone());
+            root_group.borrow().get_group_row_count()
+        };
+
+        // t1.a1 = t2.a2 is already applied by the join below, so the top join only adds t2.b2 = t3.b3
+        let rows = chain_rows(vec![col("t2.b2").eq(col("t3.b3"))]);
+        let rows_with_repeated_equality =
+            chain_rows(vec![col("t1.a1").eq(col("t2.a2")), col("t2.b2").eq(col("t3.b3"))]);
+        assert_eq!(rows, 1000);
+        assert_eq!(rows_with_repeated_equality, rows);
+    }
+
+    #[test]
+    fn test_join_with_empty_relation() {
+        let plan = LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], Some(1000)))
+            .cross_join(LogicalPlanBuilder::empty(false).build().unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+        cascades.optimize(root_group.clone());
+        assert_eq!(root_group.borrow().get_group_row_count(), 0);
+    }
+
+    #[test]
+    fn test_joins_on_different_keys() {
+        let mut cascades = Cascades::default();
+        let t1_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_table_scan("t1", &["a1", "b1"], None))));
+        let t2_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_table_scan("t2", &["a2", "b2"], None))));
+        let join = |left_keys: Vec<&str>, right_keys: Vec<&str>| {
+            let plan = LogicalPlanBuilder::from(build_table_scan("t1", &["a1", "b1"], None))
+                .join(build_table_scan("t2", &["a2", "b2"], None), JoinType::Inner, (left_keys, right_keys), None)
+                .unwrap();
+            let plan = Rc::new(RefCell::new(plan.build().unwrap()));
+            MExpr::build_with_node(plan, vec![Rc::clone(&t1_group), Rc::clone(&t2_group)])
+        };
+
+        // The content hash leaves out the join keys, the structural key and equality do not
+        let on_a = join(vec!["t1.a1"], vec!["t2.a2"]);
+        let on_b = join(vec!["t1.b1"], vec!["t2.b2"]);
+        assert_eq!(on_a.memo_key(false), on_b.memo_key(false));
+        assert_ne!(on_a.memo_key(true), on_b.memo_key(true));
+        assert_ne!(on_a, on_b);
+
+        let on_a_b = join(vec!["t1.a1", "t1.b1"], vec!["t2.a2", "t2.b2"]);
+        let on_b_a = join(vec!["t1.b1", "t1.a1"], vec!["t2.b2", "t2.a2"]);
+        assert_eq!(on_a_b.memo_key(true), on_b_a.memo_key(true));
+        assert_eq!(on_a_b, on_b_a);
+    }
+
+    #[test]
+    fn test_values_row_count() {
+        let values = LogicalPlanBuilder::values(vec![vec![lit(1)], vec![lit(2)], vec![lit(3)]])
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut mexpr = MExpr::build_with_node(Rc::new(RefCell::new(values)), vec![]);
+        mexpr.update_cost_and_rowcount(&CostModel::default());
+        assert_eq!(mexpr.row_count(), 3);
+        assert!(mexpr.cost() > 0.0);
+    }
+
+    #[test]
+    fn test_hash_collision_is_not_equal() {
+        let mut cascades = Cascades::default();
+        let t1_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_table_scan("t1", &["a1"], None))));
+        let t2_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_table_scan("t2", &["a2"], None))));
+        let limit = || {
+            let plan = LogicalPlanBuilder::from(build_table_scan("t1", &["a1"], None)).limit(0, Some(10)).unwrap();
+            Rc::new(RefCell::new(plan.build().unwrap()))
+        };
+
+        let over_t1 = MExpr::build_with_node(limit(), vec![t1_group]);
+        let mut over_t2 = MExpr::build_with_node(limit(), vec![t2_group]);
+        assert_ne!(over_t1.hash(), over_t2.hash());
+
+        // Force a collision, the differing operands still tell the mexprs apart
+        over_t2.hash = over_t1.hash;
+        assert_ne!(over_t1, over_t2);
+        assert_eq!(over_t1, over_t1.clone());
+    }
+
+    #[test]
+    fn test_canonicalized_commuted_join() {
+        let mut cascades = Cascades::default();
+        let root = cascades.seed_memo("12");
+        let join = root.borrow().start_expression.clone().unwrap();
+        let operands = join.operands().clone();
+        let commuted = MExpr::build_with_node(join.op(), vec![Rc::clone(&operands[1]), Rc::clone(&operands[0])]);
+
+        assert_ne!(join.hash(), commuted.hash());
+        assert_eq!(join.canonicalized(), "Inner Join({t1}, {t2}) [t1.a1 = t2.a2]");
+        assert_eq!(join.canonicalized(), commuted.canonicalized());
+        assert_eq!(operands[0].borrow().start_expression.as_ref().unwrap().canonicalized(), "TableScan({t1})");
+    }
+
+    #[test]
+    fn test_join_row_count_is_never_truncated_to_zero() {
+        let mut cascades = Cascades::default();
+        let root = cascades.seed_memo("12345");
+        cascades.optimize(root);
+
+        for group in cascades.get_unique_groups_in_memo() {
+            let group = group.borrow();
+            for mexpr in group.equivalent_logical_mexprs.borrow().iter() {
+                assert!(mexpr.row_count() > 0, "{} estimated to produce no rows", mexpr.canonicalized());
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use super::group::Group;
+use super::mexpr::MExpr;
+use datafusion_expr::{Join, LogicalPlan};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The operator shape a transformation rule applies to
+/// Only the mexpr a rule fires on is matched as-is, nested patterns are matched against the explored mexprs of the
+/// operand groups
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// Matches any group, binding it
+    Any,
+    /// Matches a join whose left and right inputs match the given patterns
+    Join(Box<Pattern>, Box<Pattern>),
+}
+
+/// What a pattern bound against a mexpr
+#[derive(Debug, Clone, Default)]
+pub struct Bindings {
+    /// The joins matched by `Pattern::Join`, in pre-order, so the join of the bound mexpr comes first
+    pub joins: Vec<Join>,
+    /// The groups matched by `Pattern::Any`, left to right
+    pub groups: Vec<Rc<RefCell<Group>>>,
+}
+
+impl Pattern {
+    pub fn join(left: Pattern, right: Pattern) -> Self {
+        Pattern::Join(Box::new(left), Box::new(right))
+    }
+
+    /// The first binding of this pattern against a mexpr, None if the mexpr does not have its shape
+    pub fn bind(&self, mexpr: &MExpr) -> Option<Bindings> {
+        self.bind_all(mexpr).into_iter().next()
+    }
+
+    /// Every binding of this pattern against a mexpr, one per combination of operand mexprs matching the nested
+    /// patterns
+    pub fn bind_all(&self, mexpr: &MExpr) -> Vec<Bindings> {
+        match self {
+            Pattern::Any => vec![Bindings::default()],
+            Pattern::Join(left, right) => {
+                let op = mexpr.op();
+                let LogicalPlan::Join(join) = &*op.borrow() else {
+                    return Vec::new();
+                };
+
+                let left_bindings = left.bind_group(&mexpr.operands()[0]);
+                let right_bindings = right.bind_group(&mexpr.operands()[1]);
+                let mut result = Vec::new();
+                for left_binding in &left_bindings {
+                    for right_binding in &right_bindings {
+                        let mut joins = vec![join.clone()];
+                        joins.extend(left_binding.joins.iter().cloned());
+                        joins.extend(right_binding.joins.iter().cloned());
+                        let mut groups = left_binding.groups.clone();
+                        groups.extend(right_binding.groups.iter().cloned());
+                        result.push(Bindings { joins, groups });
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    fn bind_group(&self, group: &Rc<RefCell<Group>>) -> Vec<Bindings> {
+        if *self == Pattern::Any {
+            return vec![Bindings {
+                joins: Vec::new(),
+                groups: vec![Rc::clone(group)],
+            }];
+        }
+
+        // Clone the mexprs out, binding them borrows their operand groups which may be this same group
+        let mexprs = group.borrow().equivalent_logical_mexprs.borrow().clone();
+        mexprs.iter().flat_map(|mexpr| self.bind_all(mexpr)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cascades::Cascades;
+
+    #[test]
+    fn test_bind_join_patterns() {
+        let any = || Pattern::Any;
+        let left_deep = Pattern::join(Pattern::join(any(), any()), any());
+        let right_deep = Pattern::join(any(), Pattern::join(any(), any()));
+
+        let mut cascades = Cascades::new();
+        let root = cascades.seed_memo("123");
+        cascades.optimize(root.clone());
+
+        // The seeded ((1 ⋈ 2) ⋈ 3) only has the left-deep shape
+        let seeded = root.borrow().start_expression.clone().unwrap();
+        let bindings = left_deep.bind(&seeded).expect("left-deep pattern did not bind");
+        assert_eq!(bindings.joins.len(), 2);
+        assert_eq!(bindings.groups.len(), 3);
+        assert!(right_deep.bind(&seeded).is_none());
+
+        // Commutativity explored 3 ⋈ (1 ⋈ 2), binding the right-deep pattern
+        let root_mexprs = root.borrow().equivalent_logical_mexprs.borrow().clone();
+        assert!(root_mexprs.iter().any(|mexpr| right_deep.bind(mexpr).is_some()));
+
+        // A leaf is not a join
+        let leaf = bindings.groups[0].borrow().start_expression.clone().unwrap();
+        assert!(right_deep.bind(&leaf).is_none());
+        assert!(any().bind(&leaf).is_some());
+    }
+}
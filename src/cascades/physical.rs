@@ -0,0 +1,554 @@
+use super::constants::{
+    HASH_BUILD_COST_PER_ROW, HASH_MEMORY_COST_PER_ROW, HASH_PROBE_COST_PER_ROW, INDEX_PROBE_COST_PER_ROW,
+    MERGE_COST_PER_ROW, NESTED_LOOP_COST_PER_PAIR, SORT_COST_PER_ROW, VARIABLE_WIDTH_FIELD_BYTES,
+};
+use super::group::Group;
+use super::mexpr::MExpr;
+use super::cost_model::{CostModel, better_cost};
+use datafusion_common::{Column, DFSchema};
+use datafusion_expr::{Expr, Join, LogicalPlan, Sort};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+/// The join input a hash join builds its hash table from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuildSide {
+    Left,
+    Right,
+}
+
+impl fmt::Display for BuildSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildSide::Left => write!(f, "Left"),
+            BuildSide::Right => write!(f, "Right"),
+        }
+    }
+}
+
+/// Physical algorithms a logical join can be implemented with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JoinAlgorithm {
+    Hash { build_side: BuildSide },
+    NestedLoop,
+    /// Inputs already sorted on the join keys are not sorted again
+    SortMerge { left_sorted: bool, right_sorted: bool },
+    /// Probes the index on the join key of the `index_side` input once per row of the other input
+    IndexNestedLoop { index_side: BuildSide },
+}
+
+impl fmt::Display for JoinAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinAlgorithm::Hash { .. } => write!(f, "Hash"),
+            JoinAlgorithm::NestedLoop => write!(f, "NestedLoop"),
+            JoinAlgorithm::SortMerge { .. } => write!(f, "SortMerge"),
+            JoinAlgorithm::IndexNestedLoop { .. } => write!(f, "IndexNestedLoop"),
+        }
+    }
+}
+
+/// The DataFusion join ExecutionPlan a join algorithm maps to, for a physical planner to consult as a hint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PhysicalJoinType {
+    /// HashJoinExec
+    HashJoin,
+    /// SortMergeJoinExec
+    SortMergeJoin,
+    /// NestedLoopJoinExec
+    NestedLoopJoin,
+}
+
+impl From<JoinAlgorithm> for PhysicalJoinType {
+    fn from(algorithm: JoinAlgorithm) -> Self {
+        match algorithm {
+            JoinAlgorithm::Hash { .. } => PhysicalJoinType::HashJoin,
+            JoinAlgorithm::SortMerge { .. } => PhysicalJoinType::SortMergeJoin,
+            // DataFusion has no index join, a nested loop join is the closest
+            JoinAlgorithm::NestedLoop | JoinAlgorithm::IndexNestedLoop { .. } => PhysicalJoinType::NestedLoopJoin,
+        }
+    }
+}
+
+impl JoinAlgorithm {
+    /// Cost of running the algorithm over its inputs, excluding the cost of producing the output rows
+    /// Hash joins pay to build and hold the build side in memory, and probe it with every row of the other side
+    pub fn cost(&self, left_rows: u64, right_rows: u64) -> f64 {
+        let left_rows = left_rows as f64;
+        let right_rows = right_rows as f64;
+        match self {
+            JoinAlgorithm::Hash { build_side } => {
+                let (build_rows, probe_rows) = match build_side {
+                    BuildSide::Left => (left_rows, right_rows),
+                    BuildSide::Right => (right_rows, left_rows),
+                };
+                (HASH_BUILD_COST_PER_ROW + HASH_MEMORY_COST_PER_ROW) * build_rows
+                    + HASH_PROBE_COST_PER_ROW * probe_rows
+            }
+            JoinAlgorithm::NestedLoop => NESTED_LOOP_COST_PER_PAIR * left_rows * right_rows,
+            JoinAlgorithm::SortMerge {
+                left_sorted,
+                right_sorted,
+            } => {
+                let left_sort_cost = if *left_sorted { 0.0 } else { get_sort_cost(left_rows) };
+                let right_sort_cost = if *right_sorted { 0.0 } else { get_sort_cost(right_rows) };
+                left_sort_cost + right_sort_cost + MERGE_COST_PER_ROW * (left_rows + right_rows)
+            }
+            JoinAlgorithm::IndexNestedLoop { index_side } => {
+                let outer_rows = match index_side {
+                    BuildSide::Left => right_rows,
+                    BuildSide::Right => left_rows,
+                };
+                INDEX_PROBE_COST_PER_ROW * outer_rows
+            }
+        }
+    }
+}
+
+/// The operand group a hash join manifestation builds from, None for any other manifestation
+pub fn get_build_operand(mexpr: &MExpr) -> Option<Rc<RefCell<Group>>> {
+    match mexpr.join_algorithm()? {
+        JoinAlgorithm::Hash { build_side: BuildSide::Left } => mexpr.operands().first().cloned(),
+        JoinAlgorithm::Hash { build_side: BuildSide::Right } => mexpr.operands().get(1).cloned(),
+        _ => None,
+    }
+}
+
+/// Bytes the hash table of a hash join manifestation takes up, its build rows times their width, None for any other
+/// manifestation
+pub fn get_build_memory(mexpr: &MExpr) -> Option<u64> {
+    let build_operand = get_build_operand(mexpr)?;
+    let build_operand = build_operand.borrow();
+    let row_width = build_operand
+        .start_expression
+        .as_ref()
+        .and_then(|expr| expr.get_schema())
+        .map(|schema| get_row_width(&schema))
+        .unwrap_or(VARIABLE_WIDTH_FIELD_BYTES);
+    Some(build_operand.get_group_row_count().saturating_mul(row_width))
+}
+
+/// Estimated bytes per row, fixed width fields at their width and any other at VARIABLE_WIDTH_FIELD_BYTES
+fn get_row_width(schema: &DFSchema) -> u64 {
+    schema
+        .fields()
+        .iter()
+        .map(|field| field.data_type().primitive_width().map_or(VARIABLE_WIDTH_FIELD_BYTES, |width| width as u64))
+        .sum()
+}
+
+/// Hash joins build from the smaller input, the left one if both inputs have the same row count
+fn select_build_side(mexpr: &MExpr) -> BuildSide {
+    let row_counts: Vec<u64> = mexpr
+        .operands()
+        .iter()
+        .map(|operand| operand.borrow().get_group_row_count())
+        .collect();
+    match row_counts.as_slice() {
+        [left_rows, right_rows] if right_rows < left_rows => BuildSide::Right,
+        _ => BuildSide::Left,
+    }
+}
+
+/// Cost of enforcing a sort over an input
+pub fn get_sort_cost(rows: f64) -> f64 {
+    SORT_COST_PER_ROW * rows * rows.max(2.0).log2()
+}
+
+/// The columns a sort orders its rows on, None unless it sorts on plain columns in ascending order, the only
+/// orderings physical expressions are tracked to provide
+pub fn get_sort_ordering(sort: &Sort) -> Option<Vec<Column>> {
+    sort.expr
+        .iter()
+        .map(|sort_expr| match &sort_expr.expr {
+            Expr::Column(column) if sort_expr.asc => Some(column.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Require `ordering` of the rows of a group, and of the groups below it the group passes its input's ordering through
+/// Filters and projections keeping the ordering's columns pass it through, as in get_provided_ordering
+pub fn require_ordering(group: &Rc<RefCell<Group>>, ordering: Vec<Column>) {
+    let input = group.borrow().start_expression.as_ref().and_then(|start_expression| {
+        match &*start_expression.op().borrow() {
+            LogicalPlan::Filter(_) => Some(Rc::clone(&start_expression.operands()[0])),
+            LogicalPlan::Projection(projection)
+                if ordering.iter().all(|column| projection.input.schema().has_column(column)) =>
+            {
+                Some(Rc::clone(&start_expression.operands()[0]))
+            }
+            _ => None,
+        }
+    });
+    if let Some(input) = input {
+        require_ordering(&input, ordering.clone());
+    }
+    group.borrow_mut().required_ordering = Some(ordering);
+}
+
+/// Cost of a physical mexpr once the ordering required of its group is enforced, with a sort if it does not provide it
+fn get_enforced_cost(physical_mexpr: &MExpr, required_ordering: Option<&[Column]>) -> f64 {
+    match required_ordering {
+        Some(required_ordering) if !physical_mexpr.provided_ordering().starts_with(required_ordering) => {
+            physical_mexpr.cost() + get_sort_cost(physical_mexpr.row_count() as f64)
+        }
+        _ => physical_mexpr.cost(),
+    }
+}
+
+/// The ordering provided by a group's cheapest physical expression
+fn get_group_ordering(group: &Rc<RefCell<Group>>) -> Vec<Column> {
+    group
+        .borrow()
+        .cheapest_physical_expression
+        .as_ref()
+        .map(|expr| expr.provided_ordering().to_vec())
+        .unwrap_or_default()
+}
+
+/// The join key columns coming from the left and from the right input, in join key order
+/// Keys that aren't plain columns are left out
+fn get_join_key_columns(join: &Join, left: &Rc<RefCell<Group>>) -> (Vec<Column>, Vec<Column>) {
+    let left_schema = left.borrow().start_expression.as_ref().and_then(|expr| expr.get_schema());
+    let mut left_columns = Vec::new();
+    let mut right_columns = Vec::new();
+    for (left_key, right_key) in &join.on {
+        let (Expr::Column(left_key), Expr::Column(right_key)) = (left_key, right_key) else {
+            continue;
+        };
+        // The keys are not necessarily oriented the same way as the join inputs
+        if left_schema.as_ref().is_some_and(|schema| schema.has_column(right_key)) {
+            left_columns.push(right_key.clone());
+            right_columns.push(left_key.clone());
+        } else {
+            left_columns.push(left_key.clone());
+            right_columns.push(right_key.clone());
+        }
+    }
+    (left_columns, right_columns)
+}
+
+/// An input is sorted on the join keys if its ordering starts with the first join key
+fn is_sorted_on(ordering: &[Column], key_columns: &[Column]) -> bool {
+    key_columns.first().is_some_and(|key| ordering.first() == Some(key))
+}
+
+/// The algorithms that can implement a logical mexpr, None for operators that have a single implementation
+/// Hash and sort-merge joins need equi-join keys, so joins without them can only be nested loop joins
+/// Index nested loop joins need the indexed input to be a source table, joined on its declared key
+fn get_implementations(mexpr: &MExpr, cost_model: &CostModel) -> Vec<Option<JoinAlgorithm>> {
+    match &*mexpr.op().borrow() {
+        LogicalPlan::Join(join) if !join.on.is_empty() => {
+            let (left_keys, right_keys) = get_join_key_columns(join, &mexpr.operands()[0]);
+            let index_sides = [(BuildSide::Left, &left_keys, 0), (BuildSide::Right, &right_keys, 1)]
+                .into_iter()
+                .filter(|(_, keys, operand)| {
+                    cost_model.is_key(&mexpr.operands()[*operand].borrow().source_set(), keys)
+                })
+                .map(|(index_side, _, _)| Some(JoinAlgorithm::IndexNestedLoop { index_side }));
+            let mut implementations = vec![
+                Some(JoinAlgorithm::Hash {
+                    build_side: select_build_side(mexpr),
+                }),
+                Some(JoinAlgorithm::SortMerge {
+                    left_sorted: is_sorted_on(&get_group_ordering(&mexpr.operands()[0]), &left_keys),
+                    right_sorted: is_sorted_on(&get_group_ordering(&mexpr.operands()[1]), &right_keys),
+                }),
+                Some(JoinAlgorithm::NestedLoop),
+            ];
+            implementations.extend(index_sides);
+            implementations
+        }
+        LogicalPlan::Join(_) => vec![Some(JoinAlgorithm::NestedLoop)],
+        _ => vec![None],
+    }
+}
+
+/// The ordering of the rows produced by a physical mexpr
+/// Only orderings declared on source tables are tracked, passed up through filters and projections, and through
+/// sort-merge joins on their join keys, along with the orderings sorts establish
+fn get_provided_ordering(physical_mexpr: &MExpr, cost_model: &CostModel) -> Vec<Column> {
+    match &*physical_mexpr.op().borrow() {
+        LogicalPlan::TableScan(scan) => cost_model.get_table_ordering(&scan.table_name.to_string()),
+        LogicalPlan::Filter(_) => get_group_ordering(&physical_mexpr.operands()[0]),
+        LogicalPlan::Projection(projection) => get_group_ordering(&physical_mexpr.operands()[0])
+            .into_iter()
+            .take_while(|column| projection.schema.has_column(column))
+            .collect(),
+        LogicalPlan::Join(join)
+            if matches!(physical_mexpr.join_algorithm(), Some(JoinAlgorithm::SortMerge { .. })) =>
+        {
+            get_join_key_columns(join, &physical_mexpr.operands()[0]).0
+        }
+        LogicalPlan::Sort(sort) => get_sort_ordering(sort).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Implement every explored logical mexpr of the groups reachable from `group`, operand groups first
+/// Each group's physical manifestations are replaced and the cheapest of them becomes its cheapest physical expression
+/// A group under a sort weighs each manifestation with the sort it would still need, so one providing the sort's
+/// ordering, e.g. a sort-merge join on the sort keys, wins if it is cheaper than the cheapest plus a sort
+/// Hash joins building more than the cost model's memory budget never win, a join always has a nested loop fallback
+pub fn implement_group(
+    group: &Rc<RefCell<Group>>,
+    cost_model: &CostModel,
+    implemented: &mut HashSet<*const RefCell<Group>>,
+) {
+    if !implemented.insert(Rc::as_ptr(group)) {
+        return;
+    }
+
+    let logical_mexprs = group.borrow().equivalent_logical_mexprs.borrow().clone();
+    let mut physical_mexprs = Vec::new();
+    for mexpr in &logical_mexprs {
+        for operand in mexpr.operands() {
+            implement_group(operand, cost_model, implemented);
+        }
+
+        for algorithm in get_implementations(mexpr, cost_model) {
+            let mut physical_mexpr = MExpr::build_physical(mexpr, algorithm);
+            physical_mexpr.update_physical_cost();
+            physical_mexpr.set_provided_ordering(get_provided_ordering(&physical_mexpr, cost_model));
+            physical_mexprs.push(physical_mexpr);
+        }
+    }
+
+    let required_ordering = group.borrow().required_ordering.clone();
+    let enforced_cost = |physical_mexpr: &MExpr| get_enforced_cost(physical_mexpr, required_ordering.as_deref());
+    // Manifestations over the memory budget stay in the group, they just can't win
+    let fits_memory = |physical_mexpr: &MExpr| {
+        cost_model.get_memory_budget().is_none_or(|max_bytes| {
+            get_build_memory(physical_mexpr).is_none_or(|build_bytes| build_bytes <= max_bytes)
+        })
+    };
+    let mut cheapest: Option<&MExpr> = None;
+    for physical_mexpr in physical_mexprs.iter().filter(|physical_mexpr| fits_memory(physical_mexpr)) {
+        if cheapest.is_none_or(|cheapest| better_cost(enforced_cost(physical_mexpr), enforced_cost(cheapest))) {
+            cheapest = Some(physical_mexpr);
+        }
+    }
+
+    let mut group_borrowed = group.borrow_mut();
+    group_borrowed.cheapest_physical_expression = cheapest.cloned();
+    *group_borrowed.physical_manifestations.borrow_mut() = physical_mexprs.into_iter().collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cascades::Cascades;
+    use crate::cascades::test_utils;
+    use crate::cascades::util::{get_cheapest_physical_tree, get_group_sources};
+    use std::collections::BTreeSet;
+
+    #[tokio::test]
+    async fn test_physical_tree_labels_joins() {
+        let logical_plan = test_utils::generate_logical_plan(vec![100, 2000, 300, 40]).await;
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+        cascades.optimize(root_group.clone());
+
+        let physical_tree = get_cheapest_physical_tree(root_group.clone());
+        println!("{}", physical_tree);
+
+        let join_lines: Vec<&str> = physical_tree.lines().filter(|line| line.contains("Join")).collect();
+        assert_eq!(join_lines.len(), 3);
+        for line in join_lines {
+            let line = line.trim_start_matches([' ', '-', '>']);
+            assert!(
+                ["Hash ", "NestedLoop ", "SortMerge "].iter().any(|label| line.starts_with(label)),
+                "Join is not labelled with an algorithm : {}",
+                line
+            );
+        }
+
+        let group = root_group.borrow();
+        let physical_cost = group.get_group_physical_cost();
+        assert!(physical_cost > 0.0);
+        assert_ne!(physical_cost, group.get_group_cost());
+    }
+
+    #[tokio::test]
+    async fn test_hash_join_builds_smaller_input() {
+        let logical_plan = test_utils::generate_logical_plan(vec![10000, 100]).await;
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+        cascades.optimize(root_group.clone());
+
+        let physical_tree = get_cheapest_physical_tree(root_group.clone());
+        println!("{}", physical_tree);
+        assert!(physical_tree.contains("Build {t2}"));
+
+        let join_group = Rc::clone(&root_group.borrow().cheapest_physical_expression.as_ref().unwrap().operands()[0]);
+        for physical_mexpr in join_group.borrow().physical_manifestations.borrow().iter() {
+            if let Some(build_operand) = get_build_operand(physical_mexpr) {
+                assert_eq!(get_group_sources(&build_operand), BTreeSet::from(["t2".to_string()]));
+            }
+        }
+
+        let cheapest_join = join_group.borrow().cheapest_physical_expression.clone().unwrap();
+        assert!(matches!(cheapest_join.join_algorithm(), Some(JoinAlgorithm::Hash { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_index_nested_loop_join_on_key() {
+        let logical_plan = test_utils::generate_logical_plan(vec![50, 100000]).await;
+        let cheapest_join = |key: Option<&str>| {
+            let mut cascades = Cascades::default();
+            if let Some(key) = key {
+                cascades.set_table_key("t2", &[key]);
+            }
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan.clone())));
+            cascades.optimize(root_group.clone());
+            println!("{}", get_cheapest_physical_tree(root_group.clone()));
+            let join_group =
+                Rc::clone(&root_group.borrow().cheapest_physical_expression.as_ref().unwrap().operands()[0]);
+            join_group.borrow().cheapest_physical_expression.clone().unwrap()
+        };
+
+        // Probing t2's index for each of the 50 rows of t1 beats probing a hash table with all of t2
+        let indexed = cheapest_join(Some("a2"));
+        assert_eq!(indexed.join_algorithm(), Some(JoinAlgorithm::IndexNestedLoop { index_side: BuildSide::Right }));
+
+        // Without the key there is no index to probe, nor is there one when the key is not the join key
+        assert!(matches!(cheapest_join(None).join_algorithm(), Some(JoinAlgorithm::Hash { .. })));
+        assert!(matches!(cheapest_join(Some("b2")).join_algorithm(), Some(JoinAlgorithm::Hash { .. })));
+        assert!(indexed.cost() < cheapest_join(None).cost());
+    }
+
+    #[tokio::test]
+    async fn test_index_nested_loop_join_only_probes_source_table() {
+        let logical_plan = test_utils::generate_logical_plan(vec![50, 100000, 100000]).await;
+        let mut cascades = Cascades::default();
+        cascades.set_table_key("t2", &["a2"]);
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+        cascades.optimize(root_group.clone());
+        println!("{}", get_cheapest_physical_tree(root_group));
+
+        // t2.a2 = t3.a3 is also a join on t2's key from the joins over t2, which have no index to probe
+        let mut index_joins = 0;
+        for group in cascades.get_memo().values() {
+            for physical_mexpr in group.borrow().physical_manifestations.borrow().iter() {
+                let Some(JoinAlgorithm::IndexNestedLoop { index_side }) = physical_mexpr.join_algorithm() else {
+                    continue;
+                };
+                let index_operand = match index_side {
+                    BuildSide::Left => &physical_mexpr.operands()[0],
+                    BuildSide::Right => &physical_mexpr.operands()[1],
+                };
+                assert_eq!(get_group_sources(index_operand), BTreeSet::from(["t2".to_string()]));
+                index_joins += 1;
+            }
+        }
+        assert!(index_joins > 0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_budget_rejects_large_hash_build() {
+        let logical_plan = test_utils::generate_logical_plan(vec![10000, 100]).await;
+        let cheapest_join = |max_bytes: Option<u64>| {
+            let mut cascades = Cascades::default();
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan.clone())));
+            match max_bytes {
+                Some(max_bytes) => cascades.optimize_within_memory(root_group.clone(), max_bytes),
+                None => cascades.optimize(root_group.clone()),
+            }
+            let join_group =
+                Rc::clone(&root_group.borrow().cheapest_physical_expression.as_ref().unwrap().operands()[0]);
+            join_group.borrow().cheapest_physical_expression.clone().unwrap()
+        };
+
+        // Building the 100 rows of t2 takes a few hundred bytes
+        let unbounded = cheapest_join(None);
+        let build_bytes = get_build_memory(&unbounded).expect("Expected a hash join without a memory budget");
+        assert!(build_bytes > 0);
+        assert!(cheapest_join(Some(build_bytes)).join_algorithm() == unbounded.join_algorithm());
+
+        let bounded = cheapest_join(Some(build_bytes - 1));
+        assert!(!matches!(bounded.join_algorithm(), Some(JoinAlgorithm::Hash { .. })));
+        assert!(bounded.cost() > unbounded.cost());
+    }
+
+    #[tokio::test]
+    async fn test_sort_merge_over_sorted_inputs() {
+        let logical_plan = test_utils::generate_logical_plan(vec![10000, 10000]).await;
+
+        let mut unsorted = Cascades::default();
+        let unsorted_root = unsorted.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan.clone())));
+        unsorted.optimize(unsorted_root.clone());
+        let unsorted_tree = get_cheapest_physical_tree(unsorted_root.clone());
+        println!("{}", unsorted_tree);
+        assert!(unsorted_tree.contains("Hash Inner Join"));
+
+        let mut sorted = Cascades::default();
+        sorted.set_table_ordering("t1", &["a1"]);
+        sorted.set_table_ordering("t2", &["a2"]);
+        let sorted_root = sorted.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+        sorted.optimize(sorted_root.clone());
+        let sorted_tree = get_cheapest_physical_tree(sorted_root.clone());
+        println!("{}", sorted_tree);
+        assert!(sorted_tree.contains("SortMerge Inner Join"));
+
+        // The merge join beats the hash join both inputs could otherwise use
+        let join_group = Rc::clone(&sorted_root.borrow().cheapest_physical_expression.as_ref().unwrap().operands()[0]);
+        let join_group = join_group.borrow();
+        let manifestations = join_group.physical_manifestations.borrow();
+        let cheapest_cost = |matches_algorithm: fn(&JoinAlgorithm) -> bool| {
+            manifestations
+                .iter()
+                .filter(|mexpr| mexpr.join_algorithm().as_ref().is_some_and(matches_algorithm))
+                .map(|mexpr| mexpr.cost())
+                .fold(f64::INFINITY, f64::min)
+        };
+        let merge_cost = cheapest_cost(|algorithm| matches!(algorithm, JoinAlgorithm::SortMerge { .. }));
+        let hash_cost = cheapest_cost(|algorithm| matches!(algorithm, JoinAlgorithm::Hash { .. }));
+        assert!(merge_cost < hash_cost);
+        assert!(sorted_root.borrow().get_group_physical_cost() < unsorted_root.borrow().get_group_physical_cost());
+    }
+
+    #[test]
+    fn test_order_by_join_key_prefers_sort_merge() {
+        use datafusion_expr::{JoinType, LogicalPlanBuilder, SortExpr, col};
+
+        let join_plan = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(10000)))
+            .join(
+                test_utils::build_table_scan("t2", &["a2"], Some(10000)),
+                JoinType::Inner,
+                (vec!["a1"], vec!["a2"]),
+                None,
+            )
+            .unwrap()
+            .project(vec![col("t1.a1"), col("t2.a2")])
+            .unwrap()
+            .build()
+            .unwrap();
+        // The sort, and the join below the projection under it
+        let optimize = |sort_expr: SortExpr| {
+            let plan = LogicalPlanBuilder::from(join_plan.clone()).sort(vec![sort_expr]).unwrap().build().unwrap();
+            let mut cascades = Cascades::default();
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+            cascades.optimize(root_group.clone());
+            println!("{}", get_cheapest_physical_tree(root_group.clone()));
+
+            let sort = root_group.borrow().cheapest_physical_expression.clone().unwrap();
+            let projection = sort.operands()[0].borrow().cheapest_physical_expression.clone().unwrap();
+            let join = projection.operands()[0].borrow().cheapest_physical_expression.clone().unwrap();
+            (sort, join.join_algorithm().unwrap())
+        };
+
+        // Sorting the output of the hash join costs more than having the merge join sort its inputs on the key
+        let (by_key, by_key_join) = optimize(col("t1.a1").sort(true, false));
+        assert!(matches!(by_key_join, JoinAlgorithm::SortMerge { .. }));
+        assert!(by_key.is_sort_provided_by_input());
+
+        // A descending order is not provided by the merge join, so the hash join's output is sorted instead
+        let (descending, descending_join) = optimize(col("t1.a1").sort(false, false));
+        assert!(matches!(descending_join, JoinAlgorithm::Hash { .. }));
+        assert!(!descending.is_sort_provided_by_input());
+        assert!(by_key.cost() < descending.cost());
+    }
+}
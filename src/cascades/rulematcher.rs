@@ -1,105 +1,541 @@
+use super::conflict_detector::ConflictDetector;
+use super::cost_model::{CostModel, better_cost, compare_costs};
 use super::group::Group;
 use super::mexpr::MExpr;
+use super::pattern::Pattern;
+use super::Memo;
+use super::search_config::{ExploreOrder, SearchConfig, SearchMode, TieBreak};
+use super::util::{get_group_sources, get_join_leaf_hashes};
 use ahash::AHashMap;
-use datafusion_common::DFSchema;
+use datafusion_common::{DFSchema, JoinType};
 use datafusion_common::Result;
 use datafusion_expr_common::operator::Operator;
 
 use datafusion::logical_expr::lit;
 use datafusion_expr::utils::{conjunction, split_conjunction_owned};
-use datafusion_expr::{BinaryExpr, Expr};
-use datafusion_expr::{Join, LogicalPlan};
-use log::{debug};
+use datafusion_expr::{BinaryExpr, Expr, ExprSchemable};
+use datafusion_expr::{Filter, Join, LogicalPlan};
+use log::{debug, warn};
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::rc::Rc;
 use std::sync::Arc;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use super::expression_utils::infer_equalities;
 
-#[derive(Debug, Default)]
+/// Equi-join key pairs plus the residual (non-equi) filter split out of a join predicate
+type SplitJoinPredicate = (Vec<(Expr, Expr)>, Option<Expr>);
+
+/// The top join, the nested join and the A, B and C groups of a `(A ⋈ B) ⋈ C` binding
+type NestedJoinBinding = (Join, Join, [Rc<RefCell<Group>>; 3]);
+
+/// Called with the sources and min cost of a group once it has been explored
+pub type GroupCostedCallback = Box<dyn FnMut(BTreeSet<String>, f64)>;
+
+/// A single firing of a transformation rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleTrace {
+    pub rule_name: String,
+    pub input_mexpr: u64,
+    /// Hashes of the mexprs the rule produced, each flagged with whether it was new to the memo
+    pub output_mexprs: Vec<(u64, bool)>,
+}
+
+#[derive(Default)]
 pub struct RuleMatcher {
-    // No fields needed as memo is passed as parameter
+    // The memo is passed as parameter, we only hold the search configuration and per-search state
+    config: SearchConfig,
+    deadline: Option<Instant>,
+    group_count: usize,
+    // Inner join groups with a join condition keyed on the hashes of the groups at the leaves of their join trees. join.on is not hashed, so
+    // the same join can be generated with a different content hash, this lets it share the group seen first
+    join_groups: AHashMap<BTreeSet<u64>, Rc<RefCell<Group>>>,
+    // Time spent per phase of the search, accumulated over every search run by this matcher
+    phase_timings: HashMap<&'static str, Duration>,
+    // Number of mexprs costed while exploring, over every search run by this matcher
+    costed_mexprs: usize,
+    // Groups with an explore call on the stack, a group reached again through its own operands is not re-entered
+    exploring: HashSet<*const RefCell<Group>>,
+    on_rule_fired: Option<Box<dyn FnMut(RuleTrace)>>,
+    on_group_costed: Option<GroupCostedCallback>,
+    // Conflict rules of the seeded plans' non-inner joins, checked before a rule places a join
+    conflict_detector: ConflictDetector,
+}
+
+impl std::fmt::Debug for RuleMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RuleMatcher")
+            .field("config", &self.config)
+            .field("deadline", &self.deadline)
+            .field("group_count", &self.group_count)
+            .field("phase_timings", &self.phase_timings)
+            .field("costed_mexprs", &self.costed_mexprs)
+            .finish_non_exhaustive()
+    }
 }
 
 impl RuleMatcher {
+    pub fn new(config: SearchConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    pub fn set_tiebreak_seed(&mut self, seed: u64) {
+        self.config.tiebreak_seed = Some(seed);
+    }
+
+    pub fn set_implicit_commutativity(&mut self, implicit_commutativity: bool) {
+        self.config.implicit_commutativity = implicit_commutativity;
+    }
+
+    /// Key the memo on exact mexpr content rather than on content hashes, the memo has to be empty when this changes
+    pub fn set_structural_keys(&mut self, structural_keys: bool) {
+        self.config.structural_keys = structural_keys;
+    }
+
+    /// Register a callback invoked every time a rule produces mexprs
+    pub fn set_on_rule_fired(&mut self, on_rule_fired: Box<dyn FnMut(RuleTrace)>) {
+        self.on_rule_fired = Some(on_rule_fired);
+    }
+
+    /// Register a callback invoked with the sources and min cost of every group once it has been explored
+    pub fn set_on_group_costed(&mut self, on_group_costed: GroupCostedCallback) {
+        self.on_group_costed = Some(on_group_costed);
+    }
+
+    /// Record the join operators of a seeded plan, so reorderings of its non-inner joins are checked against them
+    /// The operators of the plans seeded before are dropped, each plan is only reordered under its own rules
+    pub fn set_conflict_rules(&mut self, plan: &LogicalPlan) {
+        self.conflict_detector = ConflictDetector::new();
+        self.conflict_detector.add_plan(plan);
+    }
+
+    /// Time spent costing mexprs ("costing") and applying rules ("apply_rules"), and within the latter splitting join
+    /// predicates ("split_predicates") and building join schemas ("schema_build")
+    pub fn phase_timings(&self) -> &HashMap<&'static str, Duration> {
+        &self.phase_timings
+    }
+
+    /// Number of mexprs costed while exploring, over every search run by this matcher
+    pub fn costed_mexprs(&self) -> usize {
+        self.costed_mexprs
+    }
+
+    fn record_phase(&mut self, phase: &'static str, started: Instant) {
+        *self.phase_timings.entry(phase).or_default() += started.elapsed();
+    }
+
+    /// Reset the per-search state, call this before exploring from a root group
+    pub fn begin_search(&mut self, memo: &Memo) {
+        self.deadline = self.config.budget.map(|budget| Instant::now() + budget);
+        self.group_count = memo
+            .values()
+            .map(Rc::as_ptr)
+            .collect::<HashSet<_>>()
+            .len();
+
+        self.join_groups.clear();
+        for group in memo.values() {
+            let is_shareable_join = group
+                .borrow()
+                .start_expression
+                .as_ref()
+                .is_some_and(Self::is_shareable_join);
+            if is_shareable_join {
+                self.join_groups
+                    .entry(get_join_leaf_hashes(group))
+                    .or_insert_with(|| Rc::clone(group));
+            }
+        }
+    }
 
     /// Check and apply rules to a Group.
     /// 1. Produce logically equivalent MExprs and generate new tasks for them
     /// 2. For every new Group for the generated MExpr, check if already have it explored in the memo, if so get the cheapest plan from it
     /// 3. Add any not previously explored groups to TasksQueue
-    /// 4. Mark group as explored - note a cycle can occur where child tasks generate the parent ?? If so detect this cycle and fix it
+    /// 4. Mark group as explored - a group reached again through its own operands while being explored is skipped there,
+    ///    it is costed with the mexprs explored so far
+    ///
+    /// `upper_bound` is the cost of a known plan for the root group. A join mexpr costlier than the bound cannot be
+    /// part of a cheaper plan, so no further join orders are derived from it
     pub fn explore(
         &mut self,
         group: Rc<RefCell<Group>>,
-        memo: &mut AHashMap<u64, Rc<RefCell<Group>>>,
+        memo: &mut Memo,
+        cost_model: &CostModel,
+        mut upper_bound: f64,
     ) {
-        if group.borrow().is_explored() {
-            return; // Already explored
+        if group.borrow().is_explored() || !self.exploring.insert(Rc::as_ptr(&group)) {
+            return; // Already explored, or being explored further up the stack
         }
+        // The queues are RefCells of their own, so only a shared borrow of the group is taken to pop and push mexprs
+        // No borrow of the group is held across the recursive explore calls, an operand may reach this same group
         // Process all unexplored expressions
-        while let Some(mut mexpr) = {
-            let group_borrowed = group.borrow_mut();
-            let mut unexplored = group_borrowed
-                .unexplored_equivalent_logical_mexprs
-                .borrow_mut();
-            unexplored.pop_front()
-        } {
-            // TODO : Pass through upper and lower bound estimates as detailed in 
+        while let Some(mut mexpr) = self.pop_unexplored(&group) {
+            // TODO : Pass through lower bound estimates as detailed in
             // https://15721.courses.cs.cmu.edu/spring2023/papers/16-optimizer1/shapiro-ideas2001.pdf
             // before exploring this mexpr
-            // If we already have a cheaper cost for this group, skip exploring this mexpr
 
             // For now, explore all children of this expression to completion
             // This is the 'traditional' Cascades implementation
             for operand in mexpr.operands() {
-                self.explore(Rc::clone(operand), memo);
+                self.explore(Rc::clone(operand), memo, cost_model, upper_bound);
+            }
+
+            let started = Instant::now();
+            mexpr.update_cost_and_rowcount(cost_model); // Fixup the cost and rowcount for this expression now that operands are explored
+            self.record_phase("costing", started);
+            self.costed_mexprs += 1;
+
+            // The root group is the only one on the stack, each of its mexprs is a complete plan
+            if self.config.explore_order == ExploreOrder::CheapestFirst
+                && self.exploring.len() == 1
+                && better_cost(mexpr.cost(), upper_bound)
+            {
+                upper_bound = mexpr.cost();
             }
 
             // Rule transformations can now match and bind against child groups correctly
-            self.apply_transformation_rules(&group, &mexpr, memo);
+            // With cheapest-first, a mexpr whose operands alone cost more than a known plan is kept to cost the group,
+            // but no rules are applied to it
+            if self.config.explore_order == ExploreOrder::CheapestFirst
+                && better_cost(upper_bound, Self::get_cost_lower_bound(&mexpr))
+            {
+                debug!(
+                    "Skipping rules for mexpr {} with a cost lower bound above the upper bound {}",
+                    mexpr.hash(),
+                    upper_bound
+                );
+            } else {
+                let started = Instant::now();
+                self.apply_transformation_rules(&group, &mexpr, memo, upper_bound);
+                self.record_phase("apply_rules", started);
+            }
 
             // This Expression is now explored
-            mexpr.update_cost_and_rowcount(); // Fixup the cost and rowcount for this expression now that operands are explored
-            group
-                .borrow_mut()
-                .equivalent_logical_mexprs
-                .borrow_mut()
-                .push(mexpr);
+            group.borrow().equivalent_logical_mexprs.borrow_mut().push(mexpr);
+            if let Some(max_alternatives) = self.config.max_alternatives_per_group {
+                group.borrow_mut().retain_cheapest(max_alternatives);
+            }
         }
 
         // Mark the group as fully explored; store the cheapest logical expression and its cost
-        group.borrow_mut().set_explored(true);
+        {
+            let mut group_borrowed = group.borrow_mut();
+            group_borrowed.set_explored(true);
+            self.break_cost_ties(&mut group_borrowed);
+        }
+        self.exploring.remove(&Rc::as_ptr(&group));
+
+        if let Some(on_group_costed) = self.on_group_costed.as_mut() {
+            let min_cost = group.borrow().get_group_cost();
+            on_group_costed(get_group_sources(&group), min_cost);
+        }
+    }
+
+    /// Pop the next unexplored mexpr of a group in the configured explore order, releasing every borrow of the group
+    /// before returning it
+    fn pop_unexplored(&self, group: &Rc<RefCell<Group>>) -> Option<MExpr> {
+        let group_borrowed = group.borrow();
+        let mut unexplored = group_borrowed.unexplored_equivalent_logical_mexprs.borrow_mut();
+        match self.config.explore_order {
+            ExploreOrder::Fifo => unexplored.pop_front(),
+            ExploreOrder::CheapestFirst => {
+                // The bounds are recomputed on every pop with a linear scan rather than kept in a heap : they go stale
+                // as the operand groups are explored, an operand counting as 0 until then, and the queue of a group
+                // stays small next to the cost of exploring the mexpr popped
+                // Among equal bounds the mexpr generated first is popped, as with Fifo
+                let (cheapest, _) = unexplored
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, left), (_, right)| {
+                        compare_costs(Self::get_cost_lower_bound(left), Self::get_cost_lower_bound(right))
+                    })?;
+                unexplored.remove(cheapest)
+            }
+        }
+    }
+
+    /// A quick lower bound on the cost of a mexpr before it is explored, the summed min costs of its operand groups
+    /// An operand group not explored yet counts as 0
+    fn get_cost_lower_bound(mexpr: &MExpr) -> f64 {
+        mexpr
+            .operands()
+            .iter()
+            .map(|operand| operand.borrow().get_group_cost())
+            .sum()
+    }
+
+    fn break_cost_ties(&self, group: &mut Group) {
+        let prefer_simplest = self.config.tie_break == TieBreak::FewestCrossJoinsThenDepth;
+        if prefer_simplest || self.config.tiebreak_seed.is_some() {
+            group.break_cost_ties(prefer_simplest, self.config.tiebreak_seed);
+        }
+    }
+
+    /// Cost the start expression tree of a group as-is, without applying any transformation rules
+    /// Every group in the tree ends up explored with just its start expression
+    pub fn cost_without_rules(&self, group: &Rc<RefCell<Group>>, cost_model: &CostModel) {
+        if group.borrow().is_explored() {
+            return;
+        }
+
+        let Some(mut mexpr) = group.borrow().start_expression.clone() else {
+            return;
+        };
+        for operand in mexpr.operands() {
+            self.cost_without_rules(operand, cost_model);
+        }
+        mexpr.update_cost_and_rowcount(cost_model);
+
+        let mut group_borrowed = group.borrow_mut();
+        group_borrowed.unexplored_equivalent_logical_mexprs.borrow_mut().clear();
+        group_borrowed.equivalent_logical_mexprs.borrow_mut().push(mexpr);
+        group_borrowed.set_explored(true);
+    }
+
+    /// Re-cost every explored mexpr of a group, operand groups first, and re-derive the group's cheapest expression
+    /// No rules are applied, so the memo is left unchanged
+    pub fn recost(
+        &self,
+        group: &Rc<RefCell<Group>>,
+        cost_model: &CostModel,
+        recosted: &mut HashSet<*const RefCell<Group>>,
+    ) {
+        if !recosted.insert(Rc::as_ptr(group)) || !group.borrow().is_explored() {
+            return;
+        }
+
+        // Operands only read the cheapest expression of this group, so we can take the mexprs out while costing them
+        let mut mexprs = group.borrow().equivalent_logical_mexprs.take();
+        for mexpr in &mut mexprs {
+            for operand in mexpr.operands().clone() {
+                self.recost(&operand, cost_model, recosted);
+            }
+            mexpr.update_cost_and_rowcount(cost_model);
+        }
+
+        let mut group_borrowed = group.borrow_mut();
+        *group_borrowed.equivalent_logical_mexprs.borrow_mut() = mexprs;
+        group_borrowed.update_cheapest();
+        self.break_cost_ties(&mut group_borrowed);
     }
 
     fn apply_transformation_rules(
         &mut self,
         group: &Rc<RefCell<Group>>,
         mexpr: &MExpr,
-        memo: &mut AHashMap<u64, Rc<RefCell<Group>>>,
+        memo: &mut Memo,
+        upper_bound: f64,
     ) {
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            debug!("Search budget exhausted, not applying any more rules");
+            return;
+        }
+
         // Replace below with a true rule matcher/binder/transformer
         // For now we simply apply join commutativity & associativity rules since we're only considering IJ reordering
 
         {
             let transformed = self.apply_join_commutativity(mexpr);
-            self.add_new_mexprs(group, transformed, "Join Commutativity", memo);
+            self.add_new_mexprs(group, mexpr, transformed, "Join Commutativity", memo);
         }
 
         {
-            let transformed = self.apply_join_associativity(mexpr, memo);
-            self.add_new_mexprs(group, transformed, "Join Associativity", memo);
+            let transformed = self.apply_filter_into_cross_join(mexpr, memo);
+            self.add_new_mexprs(group, mexpr, transformed, "Filter Into Cross Join", memo);
+        }
+
+        {
+            let transformed = Self::apply_filter_merge(mexpr);
+            self.add_new_mexprs(group, mexpr, transformed, "Filter Merge", memo);
+        }
+
+        // Branch-and-bound : a join already costlier than the upper bound cannot be part of a cheaper plan,
+        // so we don't derive any new join orders from it
+        // Commutativity is still applied, it only registers the swapped form of this same join order
+        // A NaN cost is a broken estimate rather than a known costlier plan, the orders derived from it are still explored
+        if !mexpr.cost().is_nan() && better_cost(upper_bound, mexpr.cost()) {
+            debug!(
+                "Skipping associativity for mexpr {} with cost {} above the upper bound {}",
+                mexpr.hash(),
+                mexpr.cost(),
+                upper_bound
+            );
+            return;
+        }
+
+        match self.config.search_mode {
+            SearchMode::Exhaustive => {
+                let transformed = self.apply_join_associativity(mexpr, memo);
+                self.add_new_mexprs(group, mexpr, transformed, "Join Associativity", memo);
+            }
+            SearchMode::LeftDeepOnly => {
+                let transformed = self.apply_left_join_exchange(mexpr, memo);
+                self.add_new_mexprs(group, mexpr, transformed, "Left Join Exchange", memo);
+            }
         }
     }
 
     // (A ⋈ B) => (B ⋈ A)
     fn apply_join_commutativity(&self, mexpr: &MExpr) -> Vec<MExpr> {
-        if let LogicalPlan::Join(_join_node) = &*mexpr.op().borrow() {
-            let left = Rc::clone(&mexpr.operands()[0]);
-            let right = Rc::clone(&mexpr.operands()[1]);
-            vec![MExpr::build_with_node(mexpr.op(), vec![right, left])]
-        } else {
-            Vec::new()
+        let Some(bindings) = Pattern::join(Pattern::Any, Pattern::Any).bind(mexpr) else {
+            return Vec::new();
+        };
+        let (join_node, [left, right]) = (&bindings.joins[0], &bindings.groups[..]) else {
+            return Vec::new();
+        };
+
+        // The join node is kept as-is, swapping the inputs of an outer or semi join would change the preserved side
+        // With implicit commutativity the swapped form is never stored, the inputs are kept in canonical order instead
+        if !matches!(join_node.join_type, JoinType::Inner | JoinType::Full) || self.is_commutativity_implicit() {
+            return Vec::new();
+        }
+
+        // Both inputs are the same group, the swapped form would be this same mexpr
+        if Rc::ptr_eq(left, right) {
+            return Vec::new();
+        }
+
+        if !self.is_join_applicable(&mexpr.op().borrow(), right, left) {
+            return Vec::new();
+        }
+
+        // Swapping a join input into the right side is only left-deep if it is a base relation
+        if self.config.search_mode == SearchMode::LeftDeepOnly
+            && !(Self::is_base_relation(left) && Self::is_base_relation(right))
+        {
+            return Vec::new();
+        }
+
+        vec![MExpr::build_with_node(mexpr.op(), vec![Rc::clone(right), Rc::clone(left)])]
+    }
+
+    // σ(A × B)  ==>  A ⋈ B, or σ'(A ⋈ B) when some of the filter's conjuncts are not equi join keys between A and B
+    fn apply_filter_into_cross_join(
+        &mut self,
+        mexpr: &MExpr,
+        memo: &mut Memo,
+    ) -> Vec<MExpr> {
+        let mexpr_op_holder = mexpr.op();
+        let mexpr_op = mexpr_op_holder.borrow();
+        let LogicalPlan::Filter(filter) = &*mexpr_op else {
+            return Vec::new();
+        };
+
+        let mut result = Vec::new();
+        for join_mexpr in Self::get_join_mexprs(&mexpr.operands()[0]) {
+            let join_mexpr_holder = join_mexpr.op();
+            let join_op = join_mexpr_holder.borrow();
+            let LogicalPlan::Join(cross_join) = &*join_op else {
+                continue;
+            };
+            if cross_join.join_type != JoinType::Inner || !cross_join.on.is_empty() {
+                continue;
+            }
+
+            let left = Rc::clone(&join_mexpr.operands()[0]);
+            let right = Rc::clone(&join_mexpr.operands()[1]);
+            let (Some(left_schema), Some(right_schema)) =
+                (Self::get_group_schema(&left), Self::get_group_schema(&right))
+            else {
+                continue;
+            };
+
+            // The join filter of an inner join can move up into the filter along with the rest
+            let mut predicates = split_conjunction_owned(filter.predicate.clone());
+            if let Some(join_filter) = &cross_join.filter {
+                predicates.extend(split_conjunction_owned(join_filter.clone()));
+            }
+            let combined_filter = conjunction(predicates.clone()).unwrap_or(lit(true));
+
+            let mut join_node = self.build_inner_join(&combined_filter, &left_schema, &right_schema, None, cross_join);
+            let LogicalPlan::Join(inner_join) = &mut join_node else {
+                continue;
+            };
+            // The conjuncts that are not join keys stay in the filter above the join
+            inner_join.filter = None;
+            if inner_join.on.is_empty() {
+                continue;
+            }
+
+            let residual = conjunction(predicates.into_iter().filter(|predicate| {
+                !Self::is_equi_join_key(predicate, &left_schema, &right_schema)
+            }));
+            let Some(residual) = residual else {
+                result.push(MExpr::build_with_node(Rc::new(RefCell::new(join_node)), vec![left, right]));
+                continue;
+            };
+
+            let Ok(residual_filter) = Filter::try_new(residual, Arc::new(join_node.clone())) else {
+                continue;
+            };
+            let Some(join_group) = self.gen_or_get_from_memo(
+                MExpr::build_with_node(Rc::new(RefCell::new(join_node)), vec![left, right]),
+                memo,
+            ) else {
+                continue;
+            };
+            result.push(MExpr::build_with_node(
+                Rc::new(RefCell::new(LogicalPlan::Filter(residual_filter))),
+                vec![join_group],
+            ));
         }
+
+        result
+    }
+
+    // σp(σq(A))  ==>  σ(q ∧ p)(A)
+    // Each stacked filter charges for the rows it outputs, the single filter only for the final ones
+    fn apply_filter_merge(mexpr: &MExpr) -> Vec<MExpr> {
+        let mexpr_op_holder = mexpr.op();
+        let mexpr_op = mexpr_op_holder.borrow();
+        let LogicalPlan::Filter(filter) = &*mexpr_op else {
+            return Vec::new();
+        };
+
+        let input_mexprs = mexpr.operands()[0].borrow().equivalent_logical_mexprs.borrow().clone();
+        let mut result = Vec::new();
+        for input_mexpr in input_mexprs {
+            let input_op_holder = input_mexpr.op();
+            let input_op = input_op_holder.borrow();
+            let LogicalPlan::Filter(input_filter) = &*input_op else {
+                continue;
+            };
+
+            let mut predicates = split_conjunction_owned(input_filter.predicate.clone());
+            predicates.extend(split_conjunction_owned(filter.predicate.clone()));
+            let Some(merged_predicate) = conjunction(predicates) else {
+                continue;
+            };
+            let Ok(merged_filter) = Filter::try_new(merged_predicate, Arc::clone(&input_filter.input)) else {
+                continue;
+            };
+            result.push(MExpr::build_with_node(
+                Rc::new(RefCell::new(LogicalPlan::Filter(merged_filter))),
+                input_mexpr.operands().clone(),
+            ));
+        }
+
+        result
+    }
+
+    /// Whether a predicate is an equality usable as a join key between the two schemas
+    fn is_equi_join_key(predicate: &Expr, left_schema: &DFSchema, right_schema: &DFSchema) -> bool {
+        let Expr::BinaryExpr(BinaryExpr { left, op: Operator::Eq, right }) = predicate else {
+            return false;
+        };
+        matches!(
+            datafusion_expr::utils::find_valid_equijoin_key_pair(left, right, left_schema, right_schema),
+            Ok(Some(_))
+        )
     }
 
     /// A clone of datafusion_optimizer::extract_equijoin_predicate
@@ -108,19 +544,37 @@ impl RuleMatcher {
     /// `a1 = a3` should be inferred but isn't
     /// We will need to build this inference ourselves
     fn split_eq_and_noneq_join_predicate(
-        &self,
+        &mut self,
         filter: Expr,
         left_schema: Arc<DFSchema>,
         right_schema: Arc<DFSchema>,
-    ) -> Result<(Vec<(Expr, Expr)>, Option<Expr>)> {
+    ) -> Result<SplitJoinPredicate> {
         let exprs = split_conjunction_owned(filter);
         let inferred = infer_equalities(&exprs);
         //debug!("Inferred equalities : {:?}", inferred);
 
-        let mut accum_join_keys: HashSet<(Expr, Expr)> = HashSet::new();
+        // A Vec rather than a set, so the join keys come out in the same order on every run
+        let mut accum_join_keys: Vec<(Expr, Expr)> = Vec::new();
         let mut accum_filters: Vec<Expr> = vec![];
-        for expr in exprs.into_iter().chain(inferred.into_iter()) {
+        let tagged = exprs.into_iter().map(|expr| (expr, false)).chain(inferred.into_iter().map(|expr| (expr, true)));
+        for (expr, is_inferred) in tagged {
             match expr {
+                // A column compared with itself doesn't join anything, it only rejects the rows where it is null
+                Expr::BinaryExpr(BinaryExpr {
+                    ref left,
+                    op: datafusion_expr::Operator::Eq,
+                    ref right,
+                }) if matches!((left.as_ref(), right.as_ref()), (Expr::Column(l), Expr::Column(r)) if l == r) => {
+                    let nullable = [&left_schema, &right_schema]
+                        .iter()
+                        .find_map(|schema| left.nullable(schema.as_ref()).ok())
+                        .unwrap_or(true);
+                    if nullable && !is_inferred {
+                        accum_filters.push(left.as_ref().clone().is_not_null());
+                    } else {
+                        warn!("Dropping degenerate join predicate {}", expr);
+                    }
+                }
                 Expr::BinaryExpr(BinaryExpr {
                     ref left,
                     op: datafusion_expr::Operator::Eq,
@@ -136,9 +590,10 @@ impl RuleMatcher {
                     if let Some((left_expr, right_expr)) = join_key_pair {
                         // TODO : Lot of un-necessary cloning here, fix me
                         if !accum_join_keys.contains(&(right_expr.clone(), left_expr.clone())) && !accum_join_keys.contains(&(left_expr.clone(), right_expr.clone())) {
-                            accum_join_keys.insert((left_expr, right_expr));
+                            accum_join_keys.push((left_expr, right_expr));
                         }
-                    } else {
+                    } else if !is_inferred {
+                        // An inferred equality that is not a join key is implied by the conjuncts it was inferred from
                         accum_filters.push(expr);
                     }
                 }
@@ -147,211 +602,444 @@ impl RuleMatcher {
         }
 
         let result_filter = accum_filters.into_iter().reduce(Expr::and);
-        Ok((accum_join_keys.into_iter().collect(), result_filter))
+        Ok((accum_join_keys, result_filter))
     }
 
     // (A ⋈ B) ⋈ C  ==>  A ⋈ (B ⋈ C)
     fn apply_join_associativity(
-        &self,
+        &mut self,
         mexpr: &MExpr,
-        memo: &mut AHashMap<u64, Rc<RefCell<Group>>>,
+        memo: &mut Memo,
     ) -> Vec<MExpr> {
-        if let LogicalPlan::Join(_) = &*mexpr.op().borrow() {
-            let mut result = Vec::new();
+        let mut result = Vec::new();
+        let mut depths = HashMap::new();
 
-            let left = &mexpr.operands()[0];
-            let right = &mexpr.operands()[1];
+        // One binding per explored join of the left input, and per orientation of the joins when commutativity is
+        // implicit
+        for (current_join, left_join, [left_l, left_r, right]) in self.bind_left_nested_join(mexpr) {
+            let (current_join, left_join) = (&current_join, &left_join);
+            let right = &right;
 
-            let left_borrowed = left.borrow();
-            let left_equivalent = left_borrowed.equivalent_logical_mexprs.borrow();
+            // Extract overall filter from left_mexpr and mexpr into a single conjunction
+            let mut join_clause_plus_filters = Self::get_join_predicates(left_join);
+            join_clause_plus_filters.extend(Self::get_join_predicates(current_join));
+            let combined_filter = conjunction(join_clause_plus_filters).unwrap_or(lit(true));
 
-            // Check if left node is also a join
-            let left_inner_joins: Vec<MExpr> = left_equivalent
-                .iter()
-                .filter(|x| matches!(*x.op().borrow(), LogicalPlan::Join(_)))
-                .cloned()
-                .collect();
+            // A ⋈ (B ⋈ C) is bushy unless A is a base relation
+            if !self.config.allow_bushy && !Self::is_base_relation(&left_l) {
+                continue;
+            }
 
-            if left_inner_joins.is_empty() {
-                return result; // No transformations possible
+            if let Some(max_assoc_depth) = self.config.max_assoc_depth {
+                let new_right_depth = 1 + Self::get_group_depth(&left_r, &mut depths)
+                    .max(Self::get_group_depth(right, &mut depths));
+                let new_depth = 1 + Self::get_group_depth(&left_l, &mut depths).max(new_right_depth);
+                if new_depth > max_assoc_depth {
+                    continue;
+                }
             }
 
-            for left_mexpr in left_inner_joins {
-                // Extract overall filter from left_mexpr and mexpr into a single conjunction
-                // new up an empty vector of expressions
-                let mut join_clause_plus_filters: Vec<Expr> = Vec::new();
-
-                let left_mexpr_holder = left_mexpr.op();
-                let left_op = left_mexpr_holder.borrow();
-                let left_join = match &*left_op {
-                    LogicalPlan::Join(join) => {
-                        // Build a BinaryExpr from join.on
-                        for (left, right) in &join.on {
-                            let binary_expr = BinaryExpr::new(
-                                Box::new(left.clone()),
-                                Operator::Eq,
-                                Box::new(right.clone()),
-                            );
-                            join_clause_plus_filters.push(Expr::BinaryExpr(binary_expr));
-                        }
+            let (Some(left_l_schema), Some(left_r_schema), Some(right_schema)) = (
+                Self::get_group_schema(&left_l),
+                Self::get_group_schema(&left_r),
+                Self::get_group_schema(right),
+            ) else {
+                continue;
+            };
 
-                        // Add join.filter if it exists
-                        if let Some(filter) = &join.filter {
-                            join_clause_plus_filters.push(filter.clone());
-                        }
+            // Build the new right join node, B ⋈ C
+            // A non-inner join keeps its own type and predicate, so the inner join next to it does too
+            let preserves_join_types =
+                current_join.join_type != JoinType::Inner || left_join.join_type != JoinType::Inner;
+            let new_right_join_node = if preserves_join_types {
+                Self::build_join_like(current_join, &left_r_schema, &right_schema)
+            } else {
+                Some(self.build_inner_join(&combined_filter, &left_r_schema, &right_schema, None, current_join))
+            };
+            let Some(new_right_join_node) = new_right_join_node else {
+                continue;
+            };
+            if !self.is_join_applicable(&new_right_join_node, &left_r, right) {
+                continue;
+            }
+            let new_right_join_schema = Arc::clone(new_right_join_node.schema());
 
-                        join
-                    }
-                    _ => continue,
-                };
+            // Build or fetch the group for this join node
+            let Some(new_right) = self.gen_or_get_from_memo(
+                MExpr::build_with_node(
+                    Rc::new(RefCell::new(new_right_join_node)),
+                    vec![left_r, Rc::clone(right)],
+                ),
+                memo,
+            ) else {
+                continue;
+            };
 
-                let mexpr_op_holder = mexpr.op();
-                let mexpr_op = mexpr_op_holder.borrow();
-                let current_join = match &*mexpr_op {
-                    LogicalPlan::Join(join) => {
-                        // Build a BinaryExpr from join.on
-                        for (left, right) in &join.on {
-                            let binary_expr = BinaryExpr::new(
-                                Box::new(left.clone()),
-                                Operator::Eq,
-                                Box::new(right.clone()),
-                            );
-                            join_clause_plus_filters.push(Expr::BinaryExpr(binary_expr));
-                        }
+            // Now build the final top-level join node
+            let new_top_join_node = if preserves_join_types {
+                Self::build_join_like(left_join, &left_l_schema, &new_right_join_schema)
+            } else {
+                Some(self.build_inner_join(
+                    &combined_filter,
+                    &left_l_schema,
+                    &new_right_join_schema,
+                    Some(&new_right_join_schema),
+                    left_join,
+                ))
+            };
+            let Some(new_top_join_node) = new_top_join_node else {
+                continue;
+            };
+            if !self.is_join_applicable(&new_top_join_node, &left_l, &new_right) {
+                continue;
+            }
 
-                        // Add join.filter if it exists
-                        if let Some(filter) = &join.filter {
-                            join_clause_plus_filters.push(filter.clone());
-                        }
+            result.push(MExpr::build_with_node(
+                Rc::new(RefCell::new(new_top_join_node)),
+                vec![left_l, new_right],
+            ));
+        }
 
-                        join
-                    }
-                    _ => continue,
-                };
+        result
+    }
 
-                let combined_filter = conjunction(join_clause_plus_filters).unwrap_or(lit(true));
+    // (A ⋈ B) ⋈ C  ==>  (A ⋈ C) ⋈ B
+    // Keeps a left-deep tree left-deep, so it is used instead of associativity when only left-deep trees are searched
+    fn apply_left_join_exchange(
+        &mut self,
+        mexpr: &MExpr,
+        memo: &mut Memo,
+    ) -> Vec<MExpr> {
+        let mut result = Vec::new();
 
-                let left_l = Rc::clone(&left_mexpr.operands()[0]);
-                let left_r = Rc::clone(&left_mexpr.operands()[1]);
+        for (current_join, left_join, [left_l, left_r, right]) in self.bind_left_nested_join(mexpr) {
+            let (current_join, left_join) = (&current_join, &left_join);
+            let right = &right;
 
-                let left_r_schema = match &left_r.borrow().start_expression {
-                    Some(expr) => match expr.get_schema() {
-                        Some(schema) => schema,
-                        None => continue,
-                    },
-                    None => continue,
-                };
+            let mut join_clause_plus_filters = Self::get_join_predicates(left_join);
+            join_clause_plus_filters.extend(Self::get_join_predicates(current_join));
+            let combined_filter = conjunction(join_clause_plus_filters).unwrap_or(lit(true));
 
-                let right_schema = match &right.borrow().start_expression {
-                    Some(expr) => match expr.get_schema() {
-                        Some(schema) => schema,
-                        None => continue,
-                    },
-                    None => continue,
-                };
+            let (Some(left_l_schema), Some(left_r_schema), Some(right_schema)) = (
+                Self::get_group_schema(&left_l),
+                Self::get_group_schema(&left_r),
+                Self::get_group_schema(right),
+            ) else {
+                continue;
+            };
+
+            // Build the new left join node, A ⋈ C
+            let preserves_join_types =
+                current_join.join_type != JoinType::Inner || left_join.join_type != JoinType::Inner;
+            let new_left_join_node = if preserves_join_types {
+                Self::build_join_like(current_join, &left_l_schema, &right_schema)
+            } else {
+                Some(self.build_inner_join(&combined_filter, &left_l_schema, &right_schema, None, current_join))
+            };
+            let Some(new_left_join_node) = new_left_join_node else {
+                continue;
+            };
+            if !self.is_join_applicable(&new_left_join_node, &left_l, right) {
+                continue;
+            }
+            let new_left_join_schema = Arc::clone(new_left_join_node.schema());
 
-                // Derive the equi join clause and filter between for the new join node
-                let (equi_join_clause, _other) = self
-                    .split_eq_and_noneq_join_predicate(
-                        combined_filter.clone(), //see if we can change to a Rc<Expr>
-                        left_r_schema.clone(),
-                        right_schema.clone(),
-                    )
-                    .unwrap();
+            let Some(new_left) = self.gen_or_get_from_memo(
+                MExpr::build_with_node(
+                    Rc::new(RefCell::new(new_left_join_node)),
+                    vec![left_l, Rc::clone(right)],
+                ),
+                memo,
+            ) else {
+                continue;
+            };
 
-                debug!(
-                    "Combined filter built : {}, Left schema : {}, Right Schema {}, inferred equi-join clause {}",
-                    combined_filter.to_string(),
-                    left_r_schema.to_string(),
-                    right_schema.to_string(),
-                    format!("{:?}", equi_join_clause)
-                );
+            // Then join B back in on top
+            let new_top_join_node = if preserves_join_types {
+                Self::build_join_like(left_join, &new_left_join_schema, &left_r_schema)
+            } else {
+                Some(self.build_inner_join(
+                    &combined_filter,
+                    &new_left_join_schema,
+                    &left_r_schema,
+                    Some(&new_left_join_schema),
+                    left_join,
+                ))
+            };
+            let Some(new_top_join_node) = new_top_join_node else {
+                continue;
+            };
+            if !self.is_join_applicable(&new_top_join_node, &new_left, &left_r) {
+                continue;
+            }
 
-                let left_r_schema_cloned = left_r_schema.clone();
-                let right_schema_cloned = right_schema.clone();
-
-                // Finally, build the new right join node
-                let new_right_join_schema = Arc::new(
-                    datafusion_expr::logical_plan::builder::build_join_schema(
-                        &left_r_schema_cloned,
-                        &right_schema_cloned,
-                        &datafusion_expr::JoinType::Inner,
-                    )
-                    .unwrap(),
-                );
+            result.push(MExpr::build_with_node(
+                Rc::new(RefCell::new(new_top_join_node)),
+                vec![new_left, left_r],
+            ));
+        }
 
-                let new_right_join_node = LogicalPlan::Join(Join {
-                    left: Arc::new(LogicalPlan::default()),
-                    right: Arc::new(LogicalPlan::default()),
-                    on: equi_join_clause,
-                    filter: None, // HACK for now, we need to figure out residual filters
-                    join_type: datafusion_expr::JoinType::Inner,
-                    join_constraint: current_join.join_constraint,
-                    schema: new_right_join_schema.clone(),
-                    null_equality: current_join.null_equality,
-                });
-
-                debug!("New right join built : {}", new_right_join_node.display());
-
-                // Build or fetch the group for this join node
-                let new_right = self.gen_or_get_from_memo(
-                    MExpr::build_with_node(
-                        Rc::new(RefCell::new(new_right_join_node)),
-                        vec![left_r, Rc::clone(right)],
-                    ),
-                    memo,
-                );
+        result
+    }
 
-                // Now build the final top-level join node
-                let left_l_schema = match &left_l.borrow().start_expression {
-                    Some(expr) => match expr.get_schema() {
-                        Some(schema) => schema,
-                        None => continue,
-                    },
-                    None => continue,
+    /// Implicit commutativity is ignored by the left-deep search, see SearchConfig::implicit_commutativity
+    fn is_commutativity_implicit(&self) -> bool {
+        self.config.implicit_commutativity && self.config.search_mode == SearchMode::Exhaustive
+    }
+
+    /// Bind `(A ⋈ B) ⋈ C` against a mexpr, returning the top join, the nested join and the A, B and C groups
+    /// With implicit commutativity only one orientation of each inner join is stored, so the swapped orientations of
+    /// the mexpr and of the nested join are bound too
+    fn bind_left_nested_join(&self, mexpr: &MExpr) -> Vec<NestedJoinBinding> {
+        let mut orientations = vec![mexpr.clone()];
+        if let Some(swapped) = self.get_implicitly_commuted(mexpr) {
+            orientations.push(swapped);
+        }
+
+        let pattern = Pattern::join(Pattern::join(Pattern::Any, Pattern::Any), Pattern::Any);
+        let mut result = Vec::new();
+        for orientation in &orientations {
+            for bindings in pattern.bind_all(orientation) {
+                let ([current_join, left_join], [left_l, left_r, right]) = (&bindings.joins[..], &bindings.groups[..])
+                else {
+                    continue;
                 };
+                let swap_nested = self.is_commutativity_implicit()
+                    && left_join.join_type == JoinType::Inner
+                    && !Rc::ptr_eq(left_l, left_r);
+                result.push((current_join.clone(), left_join.clone(), [
+                    Rc::clone(left_l),
+                    Rc::clone(left_r),
+                    Rc::clone(right),
+                ]));
+                if swap_nested {
+                    result.push((current_join.clone(), left_join.clone(), [
+                        Rc::clone(left_r),
+                        Rc::clone(left_l),
+                        Rc::clone(right),
+                    ]));
+                }
+            }
+        }
+        result
+    }
+
+    /// The swapped orientation of an inner or full join that implicit commutativity does not store, None otherwise
+    fn get_implicitly_commuted(&self, mexpr: &MExpr) -> Option<MExpr> {
+        if !self.is_commutativity_implicit() {
+            return None;
+        }
+        let [left, right] = &mexpr.operands()[..] else {
+            return None;
+        };
+        let is_commutative = matches!(
+            &*mexpr.op().borrow(),
+            LogicalPlan::Join(join) if matches!(join.join_type, JoinType::Inner | JoinType::Full)
+        );
+        (is_commutative && !Rc::ptr_eq(left, right))
+            .then(|| MExpr::build_with_node(mexpr.op(), vec![Rc::clone(right), Rc::clone(left)]))
+    }
 
-                let (equi_join_clause2, _other2) = self
-                    .split_eq_and_noneq_join_predicate(
-                        combined_filter.clone(),
-                        left_l_schema.clone(),
-                        new_right_join_schema.clone(),
-                    )
-                    .unwrap();
-
-                let left_l_schema_cloned = left_l_schema.clone();
-                let new_right_schema_cloned = new_right_join_schema.clone();
-
-                let new_top_join_node = LogicalPlan::Join(Join {
-                    left: Arc::new(LogicalPlan::default()),
-                    right: Arc::new(LogicalPlan::default()),
-                    on: equi_join_clause2,
-                    filter: None, // HACK for now
-                    join_type: datafusion_expr::JoinType::Inner, // Preserve the original join type
-                    join_constraint: left_join.join_constraint,
-                    schema: Arc::new(
-                        datafusion_expr::logical_plan::builder::build_join_schema(
-                            &left_l_schema_cloned,
-                            &new_right_schema_cloned,
-                            &datafusion_expr::JoinType::Inner,
-                        )
-                        .unwrap(),
-                    ),
-                    null_equality: left_join.null_equality,
-                });
-
-                debug!("New top join built : {}", new_top_join_node.display());
-
-                result.push(MExpr::build_with_node(
-                    Rc::new(RefCell::new(new_top_join_node)),
-                    vec![left_l, new_right],
-                ));
+    /// With implicit commutativity, order the inputs of an inner or full join by their source sets, so a join and its
+    /// commuted form are the same mexpr
+    pub fn canonicalize_join_inputs(&self, mexpr: MExpr) -> MExpr {
+        match self.get_implicitly_commuted(&mexpr) {
+            Some(swapped) if get_group_sources(&swapped.operands()[0]) < get_group_sources(&mexpr.operands()[0]) => {
+                swapped
             }
+            _ => mexpr,
+        }
+    }
+
+    /// The explored join mexprs of a group
+    fn get_join_mexprs(group: &Rc<RefCell<Group>>) -> Vec<MExpr> {
+        group
+            .borrow()
+            .equivalent_logical_mexprs
+            .borrow()
+            .iter()
+            .filter(|x| matches!(*x.op().borrow(), LogicalPlan::Join(_)))
+            .cloned()
+            .collect()
+    }
+
+    /// The depth of the shallowest join tree explored so far for a group, 0 for a group without join mexprs
+    /// `depths` caches the depth of the groups already visited, keyed on group id
+    fn get_group_depth(group: &Rc<RefCell<Group>>, depths: &mut HashMap<usize, usize>) -> usize {
+        let group_id = group.borrow().group_id;
+        if let Some(depth) = depths.get(&group_id) {
+            return *depth;
+        }
+
+        let depth = Self::get_join_mexprs(group)
+            .iter()
+            .map(|join_mexpr| {
+                1 + join_mexpr
+                    .operands()
+                    .iter()
+                    .map(|operand| Self::get_group_depth(operand, depths))
+                    .max()
+                    .unwrap_or_default()
+            })
+            .min()
+            .unwrap_or_default();
+        depths.insert(group_id, depth);
+        depth
+    }
+
+    /// A group is a base relation if it was not seeded from a join
+    fn is_base_relation(group: &Rc<RefCell<Group>>) -> bool {
+        group
+            .borrow()
+            .start_expression
+            .as_ref()
+            .is_some_and(|expr| !matches!(*expr.op().borrow(), LogicalPlan::Join(_)))
+    }
+
+    fn get_group_schema(group: &Rc<RefCell<Group>>) -> Option<Arc<DFSchema>> {
+        group.borrow().start_expression.as_ref()?.get_schema()
+    }
+
+    /// The join's equi keys as `left = right` expressions, plus its residual filter if any
+    fn get_join_predicates(join: &Join) -> Vec<Expr> {
+        let mut predicates: Vec<Expr> = join
+            .on
+            .iter()
+            .map(|(left, right)| {
+                Expr::BinaryExpr(BinaryExpr::new(
+                    Box::new(left.clone()),
+                    Operator::Eq,
+                    Box::new(right.clone()),
+                ))
+            })
+            .collect();
+
+        // Add join.filter if it exists
+        if let Some(filter) = &join.filter {
+            predicates.push(filter.clone());
+        }
+        predicates
+    }
+
+    /// Build an inner join node between the two schemas, deriving its equi join clause from `combined_filter`
+    /// The other conjuncts of `combined_filter` over the two inputs become the join filter, except those over
+    /// `applied_below`, the schema of a join just built under this one that already applies them
+    /// The join constraint and null equality are carried over from `template`
+    fn build_inner_join(
+        &mut self,
+        combined_filter: &Expr,
+        left_schema: &Arc<DFSchema>,
+        right_schema: &Arc<DFSchema>,
+        applied_below: Option<&DFSchema>,
+        template: &Join,
+    ) -> LogicalPlan {
+        // Derive the equi join clause and filter between for the new join node
+        let started = Instant::now();
+        let (equi_join_clause, other) = self
+            .split_eq_and_noneq_join_predicate(
+                combined_filter.clone(), //see if we can change to a Rc<Expr>
+                left_schema.clone(),
+                right_schema.clone(),
+            )
+            .unwrap();
+        self.record_phase("split_predicates", started);
+
+        debug!(
+            "Combined filter built : {}, Left schema : {}, Right Schema {}, inferred equi-join clause {:?}",
+            combined_filter, left_schema, right_schema, equi_join_clause
+        );
 
-            result
-        } else {
-            Vec::new()
+        let started = Instant::now();
+        let join_schema = Arc::new(
+            datafusion_expr::logical_plan::builder::build_join_schema(
+                left_schema,
+                right_schema,
+                &datafusion_expr::JoinType::Inner,
+            )
+            .unwrap(),
+        );
+        self.record_phase("schema_build", started);
+
+        let resolves =
+            |expr: &Expr, schema: &DFSchema| expr.column_refs().iter().all(|column| schema.has_column(column));
+        let filter = conjunction(other.map(split_conjunction_owned).unwrap_or_default().into_iter().filter(|expr| {
+            resolves(expr, &join_schema) && !applied_below.is_some_and(|schema| resolves(expr, schema))
+        }));
+
+        let join_node = LogicalPlan::Join(Join {
+            left: Arc::new(LogicalPlan::default()),
+            right: Arc::new(LogicalPlan::default()),
+            on: equi_join_clause,
+            filter,
+            join_type: datafusion_expr::JoinType::Inner,
+            join_constraint: template.join_constraint,
+            schema: join_schema,
+            null_equality: template.null_equality,
+        });
+
+        debug!("New join built : {}", join_node.display());
+        join_node
+    }
+
+    /// Rebuild `template` between the two schemas, keeping its join type, equi keys and filter
+    /// Equi keys are flipped to match the inputs, None if the predicate does not resolve against them
+    fn build_join_like(
+        template: &Join,
+        left_schema: &Arc<DFSchema>,
+        right_schema: &Arc<DFSchema>,
+    ) -> Option<LogicalPlan> {
+        let resolves =
+            |expr: &Expr, schema: &DFSchema| expr.column_refs().iter().all(|column| schema.has_column(column));
+        let mut on = Vec::with_capacity(template.on.len());
+        for (left, right) in &template.on {
+            if resolves(left, left_schema) && resolves(right, right_schema) {
+                on.push((left.clone(), right.clone()));
+            } else if resolves(right, left_schema) && resolves(left, right_schema) {
+                on.push((right.clone(), left.clone()));
+            } else {
+                return None;
+            }
+        }
+        let filter_resolves = template.filter.iter().all(|filter| {
+            filter
+                .column_refs()
+                .iter()
+                .all(|column| left_schema.has_column(column) || right_schema.has_column(column))
+        });
+        if !filter_resolves {
+            return None;
         }
+
+        let join_schema = datafusion_expr::logical_plan::builder::build_join_schema(
+            left_schema,
+            right_schema,
+            &template.join_type,
+        )
+        .ok()?;
+        Some(LogicalPlan::Join(Join {
+            left: Arc::new(LogicalPlan::default()),
+            right: Arc::new(LogicalPlan::default()),
+            on,
+            filter: template.filter.clone(),
+            join_type: template.join_type,
+            join_constraint: template.join_constraint,
+            schema: Arc::new(join_schema),
+            null_equality: template.null_equality,
+        }))
+    }
+
+    /// Whether the conflict rules of the seeded plans allow `join_node` between the two groups
+    fn is_join_applicable(
+        &self,
+        join_node: &LogicalPlan,
+        left: &Rc<RefCell<Group>>,
+        right: &Rc<RefCell<Group>>,
+    ) -> bool {
+        let LogicalPlan::Join(join) = join_node else {
+            return true;
+        };
+        self.conflict_detector.is_empty()
+            || self.conflict_detector.is_applicable(join, &get_group_sources(left), &get_group_sources(right))
     }
 
     /// For each transformed MExpr :
@@ -360,38 +1048,78 @@ impl RuleMatcher {
     fn add_new_mexprs(
         &mut self,
         group: &Rc<RefCell<Group>>,
+        input: &MExpr,
         transformed: Vec<MExpr>,
-        _rule_name: &str,
-        memo: &mut AHashMap<u64, Rc<RefCell<Group>>>,
+        rule_name: &str,
+        memo: &mut Memo,
     ) {
+        let mut output_mexprs = Vec::new();
         for new_expr in transformed {
+            let new_expr = self.canonicalize_join_inputs(new_expr);
             let hash = new_expr.hash();
-            if !memo.contains_key(&hash) {
+            let key = new_expr.memo_key(self.config.structural_keys);
+            let is_new = !memo.contains_key(&key);
+            if is_new {
                 // This is a newly generated transformation since it's missing from the memo
-                memo.insert(hash, Rc::clone(group));
+                memo.insert(key, Rc::clone(group));
                 group
-                    .borrow_mut()
+                    .borrow()
                     .unexplored_equivalent_logical_mexprs
                     .borrow_mut()
                     .push_back(new_expr);
             }
+            output_mexprs.push((hash, is_new));
 
             // The transformed expression has been seen before, and it either
             // 1. Is already explored - no action needed here
             // 2. Added in the unexplored queue - no action needed here either
             // This way we avoid getting stuck in a loop since an already generated transformation is not re-explored
         }
+
+        if let Some(on_rule_fired) = self.on_rule_fired.as_mut()
+            && !output_mexprs.is_empty()
+        {
+            on_rule_fired(RuleTrace {
+                rule_name: rule_name.to_string(),
+                input_mexpr: input.hash(),
+                output_mexprs,
+            });
+        }
     }
 
+    /// Returns None if this would create a new group beyond the configured max_groups
     fn gen_or_get_from_memo(
-        &self,
+        &mut self,
         plan_mexpr: MExpr,
-        memo: &mut AHashMap<u64, Rc<RefCell<Group>>>,
-    ) -> Rc<RefCell<Group>> {
+        memo: &mut Memo,
+    ) -> Option<Rc<RefCell<Group>>> {
+        let plan_mexpr = self.canonicalize_join_inputs(plan_mexpr);
         let hash = plan_mexpr.hash();
+        let key = plan_mexpr.memo_key(self.config.structural_keys);
+
+        if let Some(group) = memo.get(&key) {
+            return Some(Rc::clone(group));
+        }
+
+        let leaf_hashes = Self::is_shareable_join(&plan_mexpr).then(|| {
+            plan_mexpr
+                .operands()
+                .iter()
+                .flat_map(get_join_leaf_hashes)
+                .collect::<BTreeSet<_>>()
+        });
+        if let Some(group) = leaf_hashes.as_ref().and_then(|leaf_hashes| self.join_groups.get(leaf_hashes)) {
+            debug!("Mexpr {} joins the same inputs as group {}, reusing it", hash, group.borrow().group_id);
+            return Some(Rc::clone(group));
+        }
 
-        if let Some(group) = memo.get(&hash) {
-            return Rc::clone(group);
+        if self
+            .config
+            .max_groups
+            .is_some_and(|max_groups| self.group_count >= max_groups)
+        {
+            debug!("Memo is at max_groups, not adding a group for mexpr {}", hash);
+            return None;
         }
 
         // This subplan we have is either
@@ -399,11 +1127,184 @@ impl RuleMatcher {
         // or 2. We have generated a sub-plan of an existing Group but that group has not been explored so far
 
         let new_group = Group::from_mexpr(plan_mexpr);
-        memo.insert(hash, Rc::clone(&new_group));
-        new_group
+        memo.insert(key, Rc::clone(&new_group));
+        self.group_count += 1;
+        if let Some(leaf_hashes) = leaf_hashes {
+            self.join_groups.insert(leaf_hashes, Rc::clone(&new_group));
+        }
+        Some(new_group)
+    }
+
+    /// Inner joins with a join condition. A cross join is left out, its inputs can be joined on a predicate above it
+    /// and a join over the same inputs on that predicate is not equivalent to it
+    fn is_shareable_join(mexpr: &MExpr) -> bool {
+        matches!(
+            &*mexpr.op().borrow(),
+            LogicalPlan::Join(join)
+                if join.join_type == JoinType::Inner && (!join.on.is_empty() || join.filter.is_some())
+        )
+    }
+
+    pub fn test_match(&self, pattern: &Pattern, match_against: &MExpr) -> bool {
+        pattern.bind(match_against).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cascades::test_utils;
+
+    #[test]
+    fn test_no_commutativity_over_identical_inputs() {
+        let LogicalPlan::Join(join) = test_utils::build_join_chain("12") else {
+            panic!("Expected a Join node");
+        };
+        let scan = MExpr::build_with_node(Rc::new(RefCell::new(join.left.as_ref().clone())), vec![]);
+        let scan_group = Group::from_mexpr(scan);
+
+        let self_join = MExpr::build_with_node(
+            Rc::new(RefCell::new(LogicalPlan::Join(join))),
+            vec![Rc::clone(&scan_group), Rc::clone(&scan_group)],
+        );
+        let join_group = Group::from_mexpr(self_join.clone());
+
+        let mut rulematcher = RuleMatcher::default();
+        let mut memo = AHashMap::new();
+        memo.insert(self_join.memo_key(false), Rc::clone(&join_group));
+
+        let transformed = rulematcher.apply_join_commutativity(&self_join);
+        assert!(transformed.is_empty());
+        rulematcher.add_new_mexprs(&join_group, &self_join, transformed, "Join Commutativity", &mut memo);
+        assert_eq!(memo.len(), 1);
+        assert_eq!(join_group.borrow().unexplored_equivalent_logical_mexprs.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_explore_group_reachable_from_itself() {
+        let join_plan = test_utils::build_join_chain("12");
+        let LogicalPlan::Join(join) = join_plan.clone() else {
+            panic!("Expected a Join node");
+        };
+        let scan_groups = [join.left.as_ref(), join.right.as_ref()]
+            .map(|scan| Group::from_mexpr(MExpr::build_with_node(Rc::new(RefCell::new(scan.clone())), vec![])));
+        let join_mexpr = MExpr::build_with_node(Rc::new(RefCell::new(join_plan.clone())), scan_groups.to_vec());
+        let join_group = Group::from_mexpr(join_mexpr);
+
+        // A filter over the join group queued in the join group itself, so exploring it reaches the group again
+        let filter_plan = datafusion_expr::LogicalPlanBuilder::from(join_plan)
+            .filter(datafusion_expr::col("t1.a1").gt(lit(0)))
+            .unwrap()
+            .build()
+            .unwrap();
+        let filter_mexpr = MExpr::build_with_node(Rc::new(RefCell::new(filter_plan)), vec![Rc::clone(&join_group)]);
+        join_group
+            .borrow()
+            .unexplored_equivalent_logical_mexprs
+            .borrow_mut()
+            .push_back(filter_mexpr);
+
+        let mut rulematcher = RuleMatcher::default();
+        let mut memo = AHashMap::new();
+        rulematcher.explore(Rc::clone(&join_group), &mut memo, &CostModel::default(), f64::INFINITY);
+
+        let group_borrowed = join_group.borrow();
+        assert!(group_borrowed.is_explored());
+        assert!(group_borrowed.unexplored_equivalent_logical_mexprs.borrow().is_empty());
+        let explored = group_borrowed.equivalent_logical_mexprs.borrow();
+        assert!(explored.iter().any(|mexpr| matches!(*mexpr.op().borrow(), LogicalPlan::Filter(_))));
+        assert!(rulematcher.exploring.is_empty());
     }
 
-    pub fn test_match(&self, _match_against: &MExpr) -> bool {
-        true
+    #[test]
+    fn test_filter_into_cross_join() {
+        let t1 = test_utils::build_table_scan("t1", &["a1"], Some(1000));
+        let t2 = test_utils::build_table_scan("t2", &["a2"], Some(1000));
+        let cross_join_plan =
+            datafusion_expr::LogicalPlanBuilder::from(t1.clone()).cross_join(t2.clone()).unwrap().build().unwrap();
+        let scan_groups =
+            [t1, t2].map(|scan| Group::from_mexpr(MExpr::build_with_node(Rc::new(RefCell::new(scan)), vec![])));
+        let cross_join = MExpr::build_with_node(Rc::new(RefCell::new(cross_join_plan.clone())), scan_groups.to_vec());
+        let join_group = Group::from_mexpr(cross_join);
+        // The rule binds against the explored joins of the filter's input
+        let explored = join_group.borrow().unexplored_equivalent_logical_mexprs.borrow_mut().pop_front().unwrap();
+        join_group.borrow().equivalent_logical_mexprs.borrow_mut().push(explored);
+
+        let (t1_a1, t2_a2) = (datafusion_expr::col("t1.a1"), datafusion_expr::col("t2.a2"));
+        let filter_plan = datafusion_expr::LogicalPlanBuilder::from(cross_join_plan)
+            .filter(t1_a1.clone().eq(t2_a2.clone()).and(t1_a1.clone().gt(lit(5))))
+            .unwrap()
+            .build()
+            .unwrap();
+        let filter_mexpr = MExpr::build_with_node(Rc::new(RefCell::new(filter_plan)), vec![join_group]);
+
+        // σ(t1.a1 = t2.a2 ∧ t1.a1 > 5)(t1 × t2)  ==>  σ(t1.a1 > 5)(t1 ⋈ t2)
+        let mut rulematcher = RuleMatcher::default();
+        let mut memo = AHashMap::new();
+        let transformed = rulematcher.apply_filter_into_cross_join(&filter_mexpr, &mut memo);
+        let [residual] = &transformed[..] else {
+            panic!("Expected a single mexpr, got {}", transformed.len());
+        };
+        let residual_op = residual.op();
+        let LogicalPlan::Filter(residual_filter) = &*residual_op.borrow() else {
+            panic!("Expected a filter over the join");
+        };
+        assert_eq!(residual_filter.predicate, t1_a1.clone().gt(lit(5)));
+
+        let inner_join_group = Rc::clone(&residual.operands()[0]);
+        assert_eq!(memo.len(), 1);
+        assert!(Rc::ptr_eq(memo.values().next().unwrap(), &inner_join_group));
+        let inner_join = inner_join_group.borrow().start_expression.clone().unwrap();
+        let inner_join_op = inner_join.op();
+        let LogicalPlan::Join(join) = &*inner_join_op.borrow() else {
+            panic!("Expected an inner join below the filter");
+        };
+        assert_eq!(join.join_type, JoinType::Inner);
+        assert_eq!(join.on, vec![(t1_a1, t2_a2)]);
+        assert!(join.filter.is_none());
+        assert_eq!(inner_join.operands().len(), 2);
+    }
+
+    #[test]
+    fn test_self_equality_is_not_a_join_key() {
+        let LogicalPlan::Join(join) = test_utils::build_join_chain("12") else {
+            panic!("Expected a Join node");
+        };
+        let (left_schema, right_schema) = (Arc::clone(join.left.schema()), Arc::clone(join.right.schema()));
+        let t1_a1 = || datafusion_expr::col("t1.a1");
+        let t2_a2 = || datafusion_expr::col("t2.a2");
+
+        let mut rulematcher = RuleMatcher::default();
+        let (join_keys, filter) = rulematcher
+            .split_eq_and_noneq_join_predicate(t1_a1().eq(t1_a1()), Arc::clone(&left_schema), Arc::clone(&right_schema))
+            .unwrap();
+        assert!(join_keys.is_empty());
+        assert!(filter.is_none());
+
+        let (join_keys, filter) = rulematcher
+            .split_eq_and_noneq_join_predicate(t1_a1().eq(t1_a1()).and(t1_a1().eq(t2_a2())), left_schema, right_schema)
+            .unwrap();
+        assert_eq!(join_keys, vec![(t1_a1(), t2_a2())]);
+        assert!(filter.is_none());
+    }
+
+    #[test]
+    fn test_self_equality_on_nullable_column_rejects_nulls() {
+        use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+        let nullable_schema = |table: &str, column: &str| {
+            let schema = Schema::new(vec![Field::new(column, DataType::Int32, true)]);
+            Arc::new(DFSchema::try_from_qualified_schema(table, &schema).unwrap())
+        };
+        let t1_a1 = || datafusion_expr::col("t1.a1");
+
+        // t1.a1 = t1.a1 is false where t1.a1 is null, so those rows are still filtered out
+        let mut rulematcher = RuleMatcher::default();
+        let (left_schema, right_schema) = (nullable_schema("t1", "a1"), nullable_schema("t2", "a2"));
+        let (join_keys, filter) = rulematcher
+            .split_eq_and_noneq_join_predicate(t1_a1().eq(t1_a1()), left_schema, right_schema)
+            .unwrap();
+        assert!(join_keys.is_empty());
+        assert_eq!(filter, Some(t1_a1().is_not_null()));
     }
 }
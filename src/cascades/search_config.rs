@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+/// Shapes of join trees the search is allowed to produce
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// All join trees, including bushy ones, reachable through commutativity and associativity
+    #[default]
+    Exhaustive,
+    /// Only left-deep trees, where the right input of every join is a base relation
+    LeftDeepOnly,
+}
+
+/// Order the unexplored mexprs of a group are explored in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExploreOrder {
+    /// The order the mexprs were generated in
+    #[default]
+    Fifo,
+    /// Lowest cost lower bound first, the summed costs of the operand groups explored so far
+    CheapestFirst,
+}
+
+/// How a group's cheapest mexpr is picked among several of equal cost
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// The mexpr whose cheapest tree has the fewest cross joins, then the shallowest one
+    #[default]
+    FewestCrossJoinsThenDepth,
+    /// The mexpr explored first
+    FirstExplored,
+}
+
+/// Knobs controlling how much of the search space the rule matcher explores
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    pub search_mode: SearchMode,
+    /// When false, associativity never produces a join whose inputs are both joins, limiting the search to
+    /// left-deep and zig-zag trees
+    pub allow_bushy: bool,
+    /// With CheapestFirst, every mexpr explored in the root group tightens the upper bound, so the cheap plans
+    /// explored first keep join orders from being derived from the costlier ones
+    pub explore_order: ExploreOrder,
+    /// When set, associativity never produces a join tree deeper than this many joins, a single join having depth 1
+    pub max_assoc_depth: Option<usize>,
+    /// Stop creating new groups once the memo holds this many groups
+    pub max_groups: Option<usize>,
+    /// Keep at most this many explored mexprs per group, the costliest ones are dropped first. Only the cheapest
+    /// mexpr makes it into the winning plan, but get_all_possible_trees no longer sees every alternative
+    pub max_alternatives_per_group: Option<usize>,
+    /// Stop applying transformation rules once this much time has been spent exploring
+    pub budget: Option<Duration>,
+    /// How a group's cheapest mexpr is picked among several of equal cost
+    pub tie_break: TieBreak,
+    /// When set, a group's cheapest mexpr among several of equal cost, and alike under `tie_break`, is picked by this
+    /// seed rather than by the order the mexprs were explored in
+    pub tiebreak_seed: Option<u64>,
+    /// Store a single orientation of every inner join, its inputs ordered by their source sets, instead of also
+    /// storing the commuted form. Associativity binds both orientations on the fly. Only the exhaustive search
+    /// supports this, a left-deep join needs its base relation on the right whatever the canonical order
+    pub implicit_commutativity: bool,
+    /// Key the memo on the exact content of mexprs instead of on their content hash, see MExpr::memo_key
+    pub structural_keys: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            search_mode: SearchMode::default(),
+            allow_bushy: true,
+            explore_order: ExploreOrder::default(),
+            max_assoc_depth: None,
+            max_groups: None,
+            max_alternatives_per_group: None,
+            budget: None,
+            tie_break: TieBreak::default(),
+            tiebreak_seed: None,
+            implicit_commutativity: false,
+            structural_keys: false,
+        }
+    }
+}
@@ -1,12 +1,18 @@
-use datafusion_expr::{LogicalPlan, LogicalPlanBuilder, lit};
+use datafusion_expr::{LogicalPlan, LogicalPlanBuilder, col, lit};
 
 use crate::planprinter::PlanStringBuilder;
 use datafusion::arrow::array::{Int32Array, RecordBatch};
 use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::util::pretty::pretty_format_batches;
 use datafusion::prelude::SessionContext;
-use datafusion_common::JoinType;
+use datafusion_common::{DataFusionError, JoinType};
 use datafusion_common::tree_node::TreeNode;
+use datafusion_expr::logical_plan::builder::table_scan;
+use std::collections::HashMap;
 use std::sync::Arc;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+use super::cost_model::StatsProvider;
 
 pub async fn generate_logical_plan(table_row_counts: Vec<usize>) -> LogicalPlan {
     let table_count: usize = table_row_counts.len();
@@ -49,14 +55,190 @@ pub async fn generate_logical_plan(table_row_counts: Vec<usize>) -> LogicalPlan
     }
 
     // Add a projection to select a constant value (e.g., SELECT 1)
-    let logical_plan = LogicalPlanBuilder::from(logical_plan.unwrap())
+    LogicalPlanBuilder::from(logical_plan.unwrap())
         .project(vec![lit(1)])
         .ok()
         .unwrap() // SELECT 1
         .build()
         .ok()
+        .unwrap()
+}
+
+/// Build a TableScan over an Int32 table without registering it with a SessionContext
+/// The row count is carried in the scan's fetch, the same way generate_logical_plan does it
+pub fn build_table_scan(table_name: &str, column_names: &[&str], row_count: Option<usize>) -> LogicalPlan {
+    let columns = column_names
+        .iter()
+        .map(|name| (*name, DataType::Int32))
+        .collect::<Vec<_>>();
+    build_typed_table_scan(table_name, &columns, row_count)
+}
+
+/// Like build_table_scan, with the data type of every column given
+pub fn build_typed_table_scan(table_name: &str, columns: &[(&str, DataType)], row_count: Option<usize>) -> LogicalPlan {
+    let schema = Schema::new(
+        columns
+            .iter()
+            .map(|(name, data_type)| Field::new(*name, data_type.clone(), false))
+            .collect::<Vec<_>>(),
+    );
+    let plan = table_scan(Some(table_name), &schema, None)
+        .ok()
+        .unwrap()
+        .build()
+        .ok()
         .unwrap();
-    logical_plan
+
+    match plan {
+        LogicalPlan::TableScan(mut scan) => {
+            scan.fetch = row_count;
+            LogicalPlan::TableScan(scan)
+        }
+        _ => panic!("Expected a TableScan node"),
+    }
+}
+
+/// Build a left-deep chain of inner joins from a string of table digits, e.g. "123" is (t1 ⋈ t2) ⋈ t3
+/// Table tN has a single column aN and adjacent tables are joined on those columns
+pub fn build_join_chain(join_nodes: &str) -> LogicalPlan {
+    let mut logical_plan: Option<LogicalPlan> = None;
+    let mut previous_column = None;
+
+    for node in join_nodes.chars() {
+        assert!(node.is_ascii_digit(), "Expected a table digit, got '{}'", node);
+        let column_name = format!("a{}", node);
+        let table_scan = build_table_scan(&format!("t{}", node), &[&column_name], None);
+
+        logical_plan = Some(match (logical_plan, previous_column) {
+            (Some(plan), Some(left_column)) => LogicalPlanBuilder::from(plan)
+                .join_with_expr_keys(
+                    table_scan,
+                    JoinType::Inner,
+                    (vec![col(left_column)], vec![col(column_name.as_str())]),
+                    None,
+                )
+                .ok()
+                .unwrap()
+                .build()
+                .ok()
+                .unwrap(),
+            _ => table_scan,
+        });
+        previous_column = Some(column_name);
+    }
+
+    logical_plan.expect("Expected at least one table in the join chain")
+}
+
+/// Most tables random_join_graph builds, the exhaustive search over more would make fuzzing slow
+pub const MAX_RANDOM_JOIN_TABLES: usize = 6;
+
+/// Build a left-deep join tree over a random connected join graph of `n_tables` tables t1..tN, the same for a given
+/// seed. Every table is joined to a random earlier table, so the graph is connected, and to each of the other earlier
+/// tables with probability `edge_density`. Every edge joins on its own pair of columns, cN_M of tN joined to tM, so
+/// edges don't imply each other. Each table gets a random row count between 10 and 10009
+pub fn random_join_graph(seed: u64, n_tables: usize, edge_density: f64) -> LogicalPlan {
+    assert!(
+        (1..=MAX_RANDOM_JOIN_TABLES).contains(&n_tables),
+        "Expected 1 to {} tables, got {}",
+        MAX_RANDOM_JOIN_TABLES,
+        n_tables
+    );
+    let mut draws = 0_u64;
+    let mut next_random = || {
+        draws += 1;
+        xxh3_64_with_seed(&draws.to_le_bytes(), seed)
+    };
+
+    // (earlier, later) table pairs, tables numbered from 1
+    let mut edges = Vec::new();
+    for table in 2..=n_tables {
+        let parent = 1 + (next_random() % (table as u64 - 1)) as usize;
+        for earlier in 1..table {
+            let is_edge = (next_random() as f64 / u64::MAX as f64) < edge_density;
+            if earlier == parent || is_edge {
+                edges.push((earlier, table));
+            }
+        }
+    }
+
+    let mut logical_plan: Option<LogicalPlan> = None;
+    for table in 1..=n_tables {
+        let mut column_names = vec![format!("a{}", table)];
+        for &(earlier, later) in &edges {
+            if table == earlier {
+                column_names.push(format!("c{}_{}", table, later));
+            } else if table == later {
+                column_names.push(format!("c{}_{}", table, earlier));
+            }
+        }
+        let column_names: Vec<&str> = column_names.iter().map(String::as_str).collect();
+        let row_count = 10 + (next_random() % 10000) as usize;
+        let table_scan = build_table_scan(&format!("t{}", table), &column_names, Some(row_count));
+
+        logical_plan = Some(match logical_plan {
+            Some(plan) => {
+                let (left_keys, right_keys): (Vec<_>, Vec<_>) = edges
+                    .iter()
+                    .filter(|(_, later)| *later == table)
+                    .map(|(earlier, _)| {
+                        (
+                            col(format!("t{}.c{}_{}", earlier, earlier, table)),
+                            col(format!("t{}.c{}_{}", table, table, earlier)),
+                        )
+                    })
+                    .unzip();
+                LogicalPlanBuilder::from(plan)
+                    .join_with_expr_keys(table_scan, JoinType::Inner, (left_keys, right_keys), None)
+                    .ok()
+                    .unwrap()
+                    .build()
+                    .ok()
+                    .unwrap()
+            }
+            None => table_scan,
+        });
+    }
+
+    logical_plan.expect("Expected at least one table in the join graph")
+}
+
+/// Execute a plan and return its result rows formatted and sorted, so results can be compared as row multisets
+pub async fn collect_sorted_rows(plan: &LogicalPlan) -> Vec<String> {
+    let ctx = SessionContext::new();
+    let batches = ctx
+        .execute_logical_plan(plan.clone())
+        .await
+        .ok()
+        .unwrap()
+        .collect()
+        .await
+        .ok()
+        .unwrap();
+
+    // Skip the header row, keep the data rows
+    let formatted = pretty_format_batches(&batches).ok().unwrap().to_string();
+    let mut rows: Vec<String> = formatted
+        .lines()
+        .filter(|line| line.starts_with('|'))
+        .skip(1)
+        .map(str::to_string)
+        .collect();
+    rows.sort();
+    rows
+}
+
+/// Assert that two plans, typically a plan and its reordered form, return the same rows
+pub async fn assert_same_results(original: &LogicalPlan, reordered: &LogicalPlan) {
+    let original_rows = collect_sorted_rows(original).await;
+    let reordered_rows = collect_sorted_rows(reordered).await;
+    assert_eq!(
+        original_rows,
+        reordered_rows,
+        "Results differ between\n{}\nand\n{}",
+        original.display_indent(),
+        reordered.display_indent()
+    );
 }
 
 pub fn setup_tables(table_count: usize) -> Result<SessionContext, Box<dyn std::error::Error>> {
@@ -71,7 +253,8 @@ pub fn setup_tables(table_count: usize) -> Result<SessionContext, Box<dyn std::e
             DataType::Int32,
             false,
         )]));
-        let data = Int32Array::from((1..=5).map(|x| (x * i) as i32).collect::<Vec<_>>());
+        // Values repeat within and across tables, so chain joins return a non-trivial number of rows
+        let data = Int32Array::from((1..=5).map(|x| ((x * i) % 3) as i32).collect::<Vec<_>>());
         let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(data)])
             .ok()
             .unwrap();
@@ -81,8 +264,45 @@ pub fn setup_tables(table_count: usize) -> Result<SessionContext, Box<dyn std::e
     Ok(ctx)
 }
 
+/// Plan `sql` against the tables of `ctx` through DataFusion's analyzer only, not its optimizer, so the joins are in
+/// the order the FROM clause lists them rather than one the optimizer's rules baked in
+pub async fn plan_from_sql_unoptimized(ctx: &SessionContext, sql: &str) -> Result<LogicalPlan, DataFusionError> {
+    let state = ctx.state();
+    let plan = state.create_logical_plan(sql).await?;
+    state.analyzer().execute_and_check(plan, state.config_options(), |_, _| {})
+}
+
 pub fn custom_print(plan: &LogicalPlan) -> Result<String, Box<dyn std::error::Error>> {
     let mut builder = PlanStringBuilder::new();
     plan.visit(&mut builder)?;
     Ok(builder.get_output())
 }
+
+/// In-memory StatsProvider, to inject table row counts and column NDVs without registering tables
+#[derive(Debug, Default)]
+pub struct HashMapStatsProvider {
+    row_counts: HashMap<String, u64>,
+    ndvs: HashMap<(String, String), u64>,
+}
+
+impl HashMapStatsProvider {
+    pub fn with_row_count(mut self, table: &str, row_count: u64) -> Self {
+        self.row_counts.insert(table.to_string(), row_count);
+        self
+    }
+
+    pub fn with_ndv(mut self, table: &str, column: &str, ndv: u64) -> Self {
+        self.ndvs.insert((table.to_string(), column.to_string()), ndv);
+        self
+    }
+}
+
+impl StatsProvider for HashMapStatsProvider {
+    fn row_count(&self, table: &str) -> Option<u64> {
+        self.row_counts.get(table).copied()
+    }
+
+    fn ndv(&self, table: &str, column: &str) -> Option<u64> {
+        self.ndvs.get(&(table.to_string(), column.to_string())).copied()
+    }
+}
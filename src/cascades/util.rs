@@ -1,8 +1,771 @@
-use datafusion_expr::LogicalPlan;
+use datafusion::prelude::SessionContext;
+use datafusion_common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion_common::{DataFusionError, plan_err};
+use datafusion_expr::utils::{conjunction, split_conjunction};
+use datafusion_expr::{BinaryExpr, Expr, Filter, Join, JoinType, LogicalPlan, Operator};
 
+use super::conflict_detector::is_reorderable_join_type;
+use super::cost_model::compare_costs;
 use super::group::Group;
+use super::mexpr::MExpr;
+use super::physical::{BuildSide, JoinAlgorithm, PhysicalJoinType, get_build_operand};
 use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::rc::Rc;
+use std::sync::Arc;
+
+/// Structured form of a plan picked out of the memo
+#[derive(Debug, Clone)]
+pub struct PlanNode {
+    /// The operator kind, e.g. "Join" or "TableScan"
+    pub operator: String,
+    /// The operator as displayed by DataFusion
+    pub display: String,
+    /// The source tables covered by this node
+    pub sources: BTreeSet<String>,
+    /// Cost of the subtree rooted at this node
+    pub cost: f64,
+    pub row_count: u64,
+    /// Rows of the scanned table before the scan's filters, None for nodes other than table scans
+    pub table_rows: Option<u64>,
+    pub children: Vec<PlanNode>,
+}
+
+/// How much PlanNode::format_tree prints per node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlanFormat {
+    /// The display, cost and row count of each node, as get_cheapest_tree prints them
+    #[default]
+    Plain,
+    /// The cost of each node alone next to the cumulative cost of its subtree, and the row count of the base table at
+    /// each scan, to see where the cost accrues
+    Detailed,
+}
+
+impl PlanNode {
+    /// The tree one node per line, children indented below their parent as in get_cheapest_tree
+    pub fn format_tree(&self, format: PlanFormat) -> String {
+        let mut lines = Vec::new();
+        self.append_tree_lines(format, 0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn append_tree_lines(&self, format: PlanFormat, depth: usize, lines: &mut Vec<String>) {
+        let prefix = "    -> ".repeat(depth);
+        let line = match format {
+            PlanFormat::Plain => format!("{}{}, Cost {}, RowCount {}", prefix, self.display, self.cost, self.row_count),
+            PlanFormat::Detailed => {
+                let mut line = format!(
+                    "{}{}, Self Cost {:.2}, Cumulative Cost {:.2}, RowCount {}",
+                    prefix,
+                    self.display,
+                    self.self_cost(),
+                    self.cost,
+                    self.row_count
+                );
+                if let Some(table_rows) = self.table_rows {
+                    line.push_str(&format!(", Table Rows {}", table_rows));
+                }
+                line
+            }
+        };
+        lines.push(line);
+        for child in &self.children {
+            child.append_tree_lines(format, depth + 1, lines);
+        }
+    }
+
+    /// Cost of this node alone, excluding the cost of its inputs
+    pub fn self_cost(&self) -> f64 {
+        self.cost - self.children.iter().map(|child| child.cost).sum::<f64>()
+    }
+
+    /// Sum of the self costs of all nodes in the tree, per operator kind
+    pub fn total_cost_by_operator(&self) -> HashMap<String, f64> {
+        let mut costs = HashMap::new();
+        self.accumulate_cost_by_operator(&mut costs);
+        costs
+    }
+
+    fn accumulate_cost_by_operator(&self, costs: &mut HashMap<String, f64>) {
+        *costs.entry(self.operator.clone()).or_insert(0.0) += self.self_cost();
+        for child in &self.children {
+            child.accumulate_cost_by_operator(costs);
+        }
+    }
+}
+
+/// Human readable diff of two plan trees, walking them side by side
+/// Every node is listed with its cost in both plans and the delta, a node displayed differently lists both displays.
+/// A join of the same source tables whose inputs are split differently in the two plans is reported as reordered
+pub fn plan_diff(a: &PlanNode, b: &PlanNode) -> String {
+    let mut diff = String::new();
+    append_plan_diff(a, b, 0, &mut diff);
+    diff
+}
+
+fn append_plan_diff(a: &PlanNode, b: &PlanNode, depth: usize, diff: &mut String) {
+    let indent = "  ".repeat(depth);
+    let display = if a.display == b.display {
+        a.display.clone()
+    } else {
+        format!("{} => {}", a.display, b.display)
+    };
+    diff.push_str(&format!(
+        "{}{}, Cost {:.2} -> {:.2} ({:+.2})\n",
+        indent,
+        display,
+        a.cost,
+        b.cost,
+        b.cost - a.cost
+    ));
+
+    if a.operator == "Join" && b.operator == "Join" && a.sources == b.sources {
+        let format_inputs = |node: &PlanNode| {
+            node.children.iter().map(|child| format_sources(&child.sources)).collect::<Vec<_>>().join(" ⋈ ")
+        };
+        let (a_inputs, b_inputs) = (format_inputs(a), format_inputs(b));
+        if a_inputs != b_inputs {
+            diff.push_str(&format!("{}  Reordered {} => {}\n", indent, a_inputs, b_inputs));
+        }
+    }
+
+    for (a_child, b_child) in a.children.iter().zip(&b.children) {
+        append_plan_diff(a_child, b_child, depth + 1, diff);
+    }
+    for a_child in a.children.iter().skip(b.children.len()) {
+        diff.push_str(&format!("{}  - {}, Cost {:.2}\n", indent, a_child.display, a_child.cost));
+    }
+    for b_child in b.children.iter().skip(a.children.len()) {
+        diff.push_str(&format!("{}  + {}, Cost {:.2}\n", indent, b_child.display, b_child.cost));
+    }
+}
+
+/// Structured form of the physical plan picked out of the memo, with the algorithm chosen for every join
+#[derive(Debug, Clone)]
+pub struct PhysicalPlanNode {
+    pub operator: String,
+    pub display: String,
+    /// The source tables covered by this node, which identify a join whatever the shape of the tree around it
+    pub sources: BTreeSet<String>,
+    /// Physical cost of the subtree rooted at this node
+    pub cost: f64,
+    pub row_count: u64,
+    /// None for nodes other than joins
+    pub join_algorithm: Option<JoinAlgorithm>,
+    /// The input a hash join builds from, None for other join algorithms
+    pub build_side: Option<BuildSide>,
+    pub children: Vec<PhysicalPlanNode>,
+}
+
+impl PhysicalPlanNode {
+    /// The preferred DataFusion join ExecutionPlan of every join in the tree, keyed on the source tables it joins
+    pub fn to_datafusion_hints(&self) -> HashMap<BTreeSet<String>, PhysicalJoinType> {
+        let mut hints = HashMap::new();
+        self.accumulate_datafusion_hints(&mut hints);
+        hints
+    }
+
+    fn accumulate_datafusion_hints(&self, hints: &mut HashMap<BTreeSet<String>, PhysicalJoinType>) {
+        if let Some(algorithm) = self.join_algorithm {
+            hints.insert(self.sources.clone(), algorithm.into());
+        }
+        for child in &self.children {
+            child.accumulate_datafusion_hints(hints);
+        }
+    }
+}
+
+/// Short name for the kind of a plan node
+pub fn get_operator_name(plan: &LogicalPlan) -> String {
+    match plan {
+        LogicalPlan::Join(_) => "Join".to_string(),
+        LogicalPlan::TableScan(_) => "TableScan".to_string(),
+        LogicalPlan::Projection(_) => "Projection".to_string(),
+        LogicalPlan::Filter(_) => "Filter".to_string(),
+        LogicalPlan::Aggregate(_) => "Aggregate".to_string(),
+        LogicalPlan::Sort(_) => "Sort".to_string(),
+        LogicalPlan::Limit(_) => "Limit".to_string(),
+        LogicalPlan::Window(_) => "Window".to_string(),
+        LogicalPlan::Distinct(_) => "Distinct".to_string(),
+        _ => {
+            let display = plan.display().to_string();
+            display.split(':').next().unwrap_or_default().to_string()
+        }
+    }
+}
+
+/// Get the cheapest plan for a group as a PlanNode tree, None if the group has not been costed
+pub fn get_cheapest_plan(group: Rc<RefCell<Group>>) -> Option<PlanNode> {
+    let cheapest_expr = group.borrow().cheapest_logical_expression.clone()?;
+    let op = cheapest_expr.op();
+
+    let mut children = Vec::new();
+    for operand in cheapest_expr.operands() {
+        children.push(get_cheapest_plan(Rc::clone(operand))?);
+    }
+
+    let plan_node = PlanNode {
+        operator: get_operator_name(&op.borrow()),
+        display: op.borrow().display().to_string(),
+        sources: get_group_sources(&group),
+        cost: cheapest_expr.cost(),
+        row_count: cheapest_expr.row_count(),
+        table_rows: cheapest_expr.table_rows(),
+        children,
+    };
+    Some(plan_node)
+}
+
+/// Get the cheapest physical plan for a group as a PhysicalPlanNode tree, None if the group has not been implemented
+pub fn get_cheapest_physical_plan(group: Rc<RefCell<Group>>) -> Option<PhysicalPlanNode> {
+    let cheapest_expr = group.borrow().cheapest_physical_expression.clone()?;
+    let op = cheapest_expr.op();
+
+    let mut children = Vec::new();
+    for operand in cheapest_expr.operands() {
+        children.push(get_cheapest_physical_plan(Rc::clone(operand))?);
+    }
+
+    let build_side = match cheapest_expr.join_algorithm() {
+        Some(JoinAlgorithm::Hash { build_side }) => Some(build_side),
+        _ => None,
+    };
+    let plan_node = PhysicalPlanNode {
+        operator: get_operator_name(&op.borrow()),
+        display: op.borrow().display().to_string(),
+        sources: get_group_sources(&group),
+        cost: cheapest_expr.cost(),
+        row_count: cheapest_expr.row_count(),
+        join_algorithm: cheapest_expr.join_algorithm(),
+        build_side,
+        children,
+    };
+    Some(plan_node)
+}
+
+/// Get the `k` cheapest plans for a group out of its explored mexprs, cheapest first
+/// Only the k cheapest plans of every group are kept, since a plan of a group can only be among its k cheapest if the
+/// sub plans of its operands are among theirs. Memoized per group in `group_plans`
+pub fn get_top_k_plans(
+    group: &Rc<RefCell<Group>>,
+    k: usize,
+    group_plans: &mut HashMap<*const RefCell<Group>, Rc<Vec<PlanNode>>>,
+) -> Rc<Vec<PlanNode>> {
+    if let Some(plans) = group_plans.get(&Rc::as_ptr(group)) {
+        return Rc::clone(plans);
+    }
+
+    let mut plans = Vec::new();
+    for mexpr in group.borrow().equivalent_logical_mexprs.borrow().iter() {
+        let operand_costs: f64 = mexpr.operands().iter().map(|operand| operand.borrow().get_group_cost()).sum();
+        let self_cost = mexpr.cost() - operand_costs;
+
+        let mut combinations = vec![(0.0, Vec::new())];
+        for operand in mexpr.operands() {
+            let operand_plans = get_top_k_plans(operand, k, group_plans);
+            combinations = get_k_cheapest_combinations(&combinations, &operand_plans, k);
+        }
+
+        let op = mexpr.op();
+        for (children_cost, children) in combinations {
+            plans.push(PlanNode {
+                operator: get_operator_name(&op.borrow()),
+                display: op.borrow().display().to_string(),
+                sources: get_group_sources(group),
+                cost: self_cost + children_cost,
+                row_count: mexpr.row_count(),
+                table_rows: mexpr.table_rows(),
+                children,
+            });
+        }
+    }
+    plans.sort_by(|left, right| compare_costs(left.cost, right.cost));
+    plans.truncate(k);
+
+    let plans = Rc::new(plans);
+    group_plans.insert(Rc::as_ptr(group), Rc::clone(&plans));
+    plans
+}
+
+/// Extend each of the cost sorted `combinations` of sub plans with one of the cost sorted `plans`, keeping the `k`
+/// cheapest. Pairs are visited in cost order starting from the two cheapest, stopping once k have been taken
+fn get_k_cheapest_combinations(
+    combinations: &[(f64, Vec<PlanNode>)],
+    plans: &[PlanNode],
+    k: usize,
+) -> Vec<(f64, Vec<PlanNode>)> {
+    let pair_cost = |(i, j): (usize, usize)| combinations[i].0 + plans[j].cost;
+
+    let mut result = Vec::new();
+    let mut frontier = Vec::new();
+    let mut seen = HashSet::new();
+    if !combinations.is_empty() && !plans.is_empty() {
+        frontier.push((0, 0));
+        seen.insert((0, 0));
+    }
+
+    while result.len() < k && !frontier.is_empty() {
+        let (index, _) = frontier
+            .iter()
+            .enumerate()
+            .min_by(|(_, left), (_, right)| compare_costs(pair_cost(**left), pair_cost(**right)))
+            .unwrap();
+        let (i, j) = frontier.swap_remove(index);
+
+        let mut sub_plans = combinations[i].1.clone();
+        sub_plans.push(plans[j].clone());
+        result.push((pair_cost((i, j)), sub_plans));
+
+        for next in [(i + 1, j), (i, j + 1)] {
+            if next.0 < combinations.len() && next.1 < plans.len() && seen.insert(next) {
+                frontier.push(next);
+            }
+        }
+    }
+    result
+}
+
+/// Get the names of the source tables covered by a group, derived from its start expression
+pub fn get_group_sources(group: &Rc<RefCell<Group>>) -> BTreeSet<String> {
+    group.borrow().source_set()
+}
+
+/// Get the hashes of the groups at the leaves of a group's join tree, the groups below it that are not joins
+pub fn get_join_leaf_hashes(group: &Rc<RefCell<Group>>) -> BTreeSet<u64> {
+    let group_borrowed = group.borrow();
+    let Some(start_expression) = group_borrowed.start_expression.as_ref() else {
+        return BTreeSet::new();
+    };
+
+    if !matches!(*start_expression.op().borrow(), LogicalPlan::Join(_)) {
+        return BTreeSet::from([group_borrowed.get_group_hash()]);
+    }
+
+    start_expression
+        .operands()
+        .iter()
+        .flat_map(get_join_leaf_hashes)
+        .collect()
+}
+
+/// The SubqueryAliases of a plan that rename a single source table, as (alias, table) pairs
+pub fn get_table_aliases(plan: &LogicalPlan) -> Vec<(String, String)> {
+    let mut aliases = Vec::new();
+    plan.apply(|node| {
+        if let LogicalPlan::SubqueryAlias(alias) = node {
+            let tables = get_schema_relations(&alias.input);
+            if let [table] = &tables.into_iter().collect::<Vec<_>>()[..] {
+                aliases.push((alias.alias.to_string(), table.clone()));
+            }
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })
+    .expect("visiting a logical plan does not fail");
+    aliases
+}
+
+/// Get the canonicalized predicates of the joins in a group's join tree, down to the groups returned by
+/// get_join_leaf_hashes()
+pub fn get_join_tree_predicates(group: &Rc<RefCell<Group>>) -> BTreeSet<String> {
+    let group_borrowed = group.borrow();
+    let Some(start_expression) = group_borrowed.start_expression.as_ref() else {
+        return BTreeSet::new();
+    };
+    let op_holder = start_expression.op();
+    let LogicalPlan::Join(join) = &*op_holder.borrow() else {
+        return BTreeSet::new();
+    };
+
+    let mut predicates: BTreeSet<String> = MExpr::canonicalize_join_predicates(join).into_iter().collect();
+    predicates.extend(start_expression.operands().iter().flat_map(get_join_tree_predicates));
+    predicates
+}
+
+/// Whether a group is reached more than once from `group` through start expressions, that is whether seeding mapped
+/// identical subtrees to the same group. `visited` holds the ids of the groups reached so far
+pub fn has_shared_subtree(group: &Rc<RefCell<Group>>, visited: &mut HashSet<usize>) -> bool {
+    if !visited.insert(group.borrow().group_id) {
+        return true;
+    }
+
+    let Some(start_expression) = group.borrow().start_expression.clone() else {
+        return false;
+    };
+    start_expression
+        .operands()
+        .iter()
+        .any(|operand| has_shared_subtree(operand, visited))
+}
+
+/// How far a join's estimated output row count in the cheapest plan is from the rows it actually produces
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinEstimate {
+    pub sources: BTreeSet<String>,
+    pub estimated_rows: u64,
+    pub actual_rows: u64,
+    /// max(estimated / actual, actual / estimated), 1.0 for an exact estimate
+    pub q_error: f64,
+}
+
+/// Execute every join of the cheapest plan of an explored group with `ctx` and compare its estimated row count,
+/// taken from the winning mexpr, with the rows it produces. Joins are listed top down
+pub async fn get_join_estimates(
+    group: &Rc<RefCell<Group>>,
+    ctx: &SessionContext,
+) -> Result<Vec<JoinEstimate>, DataFusionError> {
+    let mut estimates = Vec::new();
+    let mut pending = vec![Rc::clone(group)];
+    while let Some(group) = pending.pop() {
+        let Some(cheapest_expr) = group.borrow().cheapest_logical_expression.clone() else {
+            return plan_err!("Group {} has not been explored", group.borrow().get_group_hash());
+        };
+        pending.extend(cheapest_expr.operands().iter().rev().cloned());
+        if !matches!(*cheapest_expr.op().borrow(), LogicalPlan::Join(_)) {
+            continue;
+        }
+
+        let plan = get_cheapest_logical_plan(&group)?;
+        let actual_rows = ctx.execute_logical_plan(plan).await?.count().await? as u64;
+        let estimated_rows = cheapest_expr.row_count();
+        estimates.push(JoinEstimate {
+            sources: get_group_sources(&group),
+            estimated_rows,
+            actual_rows,
+            q_error: get_q_error(estimated_rows, actual_rows),
+        });
+    }
+    Ok(estimates)
+}
+
+/// Both row counts are taken to be at least 1, so an empty result or estimate still gives a finite q-error
+fn get_q_error(estimated_rows: u64, actual_rows: u64) -> f64 {
+    let estimated = estimated_rows.max(1) as f64;
+    let actual = actual_rows.max(1) as f64;
+    (estimated / actual).max(actual / estimated)
+}
+
+/// Whether a join mexpr keeps the relations named in `order` in that order from left to right, that is whether every
+/// named source below its left input comes before every named source below its right input
+/// Mexprs that are not joins, and sources not named in `order`, are unconstrained
+pub fn follows_join_order(mexpr: &MExpr, order: &[String]) -> bool {
+    if !matches!(*mexpr.op().borrow(), LogicalPlan::Join(_)) {
+        return true;
+    }
+
+    let positions = |group: &Rc<RefCell<Group>>| -> Vec<usize> {
+        get_group_sources(group)
+            .iter()
+            .filter_map(|source| order.iter().position(|name| name == source))
+            .collect()
+    };
+    let left = positions(&mexpr.operands()[0]);
+    let right = positions(&mexpr.operands()[1]);
+    match (left.iter().max(), right.iter().min()) {
+        (Some(last_left), Some(first_right)) => last_left < first_right,
+        _ => true,
+    }
+}
+
+/// Format a set of sources as `{t1, t2}`
+pub fn format_sources(sources: &BTreeSet<String>) -> String {
+    format!("{{{}}}", sources.iter().cloned().collect::<Vec<_>>().join(", "))
+}
+
+/// Rebuild the cheapest logical plan of an explored group as a DataFusion plan, e.g. to execute it
+/// Commuted joins share their operator with the original join, so the equi-join keys are re-oriented to the inputs.
+/// The rebuilt plan is checked with validate_plan_schema, so a schema mismatch is reported here rather than at
+/// execution
+pub fn get_cheapest_logical_plan(group: &Rc<RefCell<Group>>) -> Result<LogicalPlan, DataFusionError> {
+    let plan = build_cheapest_logical_plan(group)?;
+    validate_plan_schema(&plan)?;
+    Ok(plan)
+}
+
+/// Check that the schema stored on every node of `plan` is the schema recomputed from its inputs, bottom-up
+pub fn validate_plan_schema(plan: &LogicalPlan) -> Result<(), DataFusionError> {
+    for input in plan.inputs() {
+        validate_plan_schema(input)?;
+    }
+
+    let recomputed = plan.clone().recompute_schema()?;
+    if recomputed.schema() != plan.schema() {
+        return plan_err!(
+            "Schema of {} does not match its inputs, stored {} but recomputed {}",
+            plan.display(),
+            plan.schema(),
+            recomputed.schema()
+        );
+    }
+    Ok(())
+}
+
+fn build_cheapest_logical_plan(group: &Rc<RefCell<Group>>) -> Result<LogicalPlan, DataFusionError> {
+    let Some(cheapest_expr) = group.borrow().cheapest_logical_expression.clone() else {
+        return plan_err!("Group {} has not been explored", group.borrow().get_group_hash());
+    };
+
+    let inputs = cheapest_expr
+        .operands()
+        .iter()
+        .map(build_cheapest_logical_plan)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let op = cheapest_expr.op();
+    let op = op.borrow();
+    if inputs.is_empty() {
+        return Ok(op.clone());
+    }
+
+    let mut exprs = op.expressions();
+    if let (LogicalPlan::Join(join), [left, _]) = (&*op, inputs.as_slice()) {
+        for (index, (left_key, _)) in join.on.iter().enumerate() {
+            let resolves_left = left_key
+                .column_refs()
+                .iter()
+                .all(|column| left.schema().has_column(column));
+            if !resolves_left {
+                exprs.swap(2 * index, 2 * index + 1);
+            }
+        }
+    }
+    op.with_new_exprs(exprs, inputs)
+}
+
+/// Find a join without any join condition in `plan` whose inputs are connected by a predicate of `original`
+/// Returns the relations on either side of the join and the predicate connecting them
+pub fn find_unjustified_cross_join(
+    original: &LogicalPlan,
+    plan: &LogicalPlan,
+) -> Option<(BTreeSet<String>, BTreeSet<String>, Expr)> {
+    let predicates = get_plan_predicates(original);
+    let mut offending = None;
+    plan.apply(|node| {
+        let LogicalPlan::Join(join) = node else {
+            return Ok(TreeNodeRecursion::Continue);
+        };
+        if !join.on.is_empty() || join.filter.is_some() {
+            return Ok(TreeNodeRecursion::Continue);
+        }
+
+        let left = get_schema_relations(&join.left);
+        let right = get_schema_relations(&join.right);
+        for predicate in &predicates {
+            let relations: BTreeSet<String> = predicate
+                .column_refs()
+                .iter()
+                .filter_map(|column| column.relation.as_ref().map(|relation| relation.to_string()))
+                .collect();
+            if !relations.is_disjoint(&left) && !relations.is_disjoint(&right) {
+                offending = Some((left, right, predicate.clone()));
+                return Ok(TreeNodeRecursion::Stop);
+            }
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })
+    .expect("visiting a logical plan does not fail");
+    offending
+}
+
+/// Why `plan` cannot be fully reordered : joins of a type without conflict rules, nodes the memo cannot be seeded with
+/// and subqueries
+pub fn get_non_reorderable_reasons(plan: &LogicalPlan) -> Vec<String> {
+    let mut reasons = Vec::new();
+    plan.apply(|node| {
+        match node {
+            LogicalPlan::Join(join) if !is_reorderable_join_type(join.join_type) => {
+                reasons.push(format!("{} join is not reordered", join.join_type));
+            }
+            LogicalPlan::Join(_)
+            | LogicalPlan::TableScan(_)
+            | LogicalPlan::EmptyRelation(_)
+            | LogicalPlan::Values(_)
+            | LogicalPlan::Projection(_)
+            | LogicalPlan::Filter(_)
+            | LogicalPlan::SubqueryAlias(_)
+            | LogicalPlan::Aggregate(_)
+            | LogicalPlan::Distinct(_)
+            | LogicalPlan::Window(_)
+            | LogicalPlan::Sort(_) => {}
+            _ => reasons.push(format!("{} nodes are not supported", get_operator_name(node))),
+        }
+
+        let has_subquery = node.expressions().iter().any(|expr| {
+            expr.exists(|expr| {
+                Ok(matches!(expr, Expr::ScalarSubquery(_) | Expr::InSubquery(_) | Expr::Exists(_)))
+            })
+            .expect("visiting an expression does not fail")
+        });
+        if has_subquery {
+            reasons.push(format!("{} has a subquery", get_operator_name(node)));
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })
+    .expect("visiting a logical plan does not fail");
+    reasons
+}
+
+/// Push a filter over inner joins onto the relations below them, so the filter does not split the join tree into
+/// separately reordered groups. None unless `plan` is such a filter and each of its conjuncts filters a single relation
+pub fn push_filter_to_leaves(plan: &LogicalPlan) -> Option<LogicalPlan> {
+    let LogicalPlan::Filter(filter) = plan else {
+        return None;
+    };
+    if !matches!(filter.input.as_ref(), LogicalPlan::Join(join) if join.join_type == JoinType::Inner) {
+        return None;
+    }
+
+    split_conjunction(&filter.predicate)
+        .into_iter()
+        .try_fold(filter.input.as_ref().clone(), |input, conjunct| {
+            let relations: BTreeSet<_> = conjunct
+                .column_refs()
+                .iter()
+                .map(|column| column.relation.clone())
+                .collect();
+            match relations.first() {
+                Some(Some(_)) if relations.len() == 1 => push_predicate(input, conjunct),
+                _ => None,
+            }
+        })
+}
+
+/// Filter the relation of `plan` the columns of `predicate` resolve against, looking through inner joins only
+fn push_predicate(plan: LogicalPlan, predicate: &Expr) -> Option<LogicalPlan> {
+    let resolves = |plan: &LogicalPlan| {
+        predicate
+            .column_refs()
+            .iter()
+            .all(|column| plan.schema().has_column(column))
+    };
+
+    match plan {
+        LogicalPlan::Join(join) if join.join_type == JoinType::Inner => {
+            if resolves(&join.left) {
+                let left = push_predicate(join.left.as_ref().clone(), predicate)?;
+                Some(LogicalPlan::Join(Join { left: Arc::new(left), ..join }))
+            } else if resolves(&join.right) {
+                let right = push_predicate(join.right.as_ref().clone(), predicate)?;
+                Some(LogicalPlan::Join(Join { right: Arc::new(right), ..join }))
+            } else {
+                None
+            }
+        }
+        LogicalPlan::Join(_) => None,
+        plan if resolves(&plan) => Filter::try_new(predicate.clone(), Arc::new(plan))
+            .ok()
+            .map(LogicalPlan::Filter),
+        _ => None,
+    }
+}
+
+/// Turn a filter over a cross join into an inner join keyed on the filter's equi predicates between the join's inputs,
+/// the seeding-time analog of the Filter Into Cross Join rule. Conjuncts over an input that is a cross join itself are
+/// merged into it the same way, so a filter over nested cross joins, e.g. from `FROM a, b, c WHERE ..`, turns each of
+/// them into an inner join. The other conjuncts stay in a filter above the join
+/// None unless `plan` is a filter over a cross join with an equi predicate between the join's inputs
+pub fn merge_filter_into_cross_join(plan: &LogicalPlan) -> Option<LogicalPlan> {
+    let LogicalPlan::Filter(filter) = plan else {
+        return None;
+    };
+    let LogicalPlan::Join(join) = filter.input.as_ref() else {
+        return None;
+    };
+    if join.join_type != JoinType::Inner || !join.on.is_empty() || join.filter.is_some() {
+        return None;
+    }
+
+    let resolves = |expr: &Expr, plan: &LogicalPlan| {
+        let columns = expr.column_refs();
+        !columns.is_empty() && columns.iter().all(|column| plan.schema().has_column(column))
+    };
+    let mut on = Vec::new();
+    let mut left_conjuncts = Vec::new();
+    let mut right_conjuncts = Vec::new();
+    let mut residual = Vec::new();
+    for conjunct in split_conjunction(&filter.predicate) {
+        match conjunct {
+            Expr::BinaryExpr(BinaryExpr { left, op: Operator::Eq, right })
+                if resolves(left, &join.left) && resolves(right, &join.right) =>
+            {
+                on.push((left.as_ref().clone(), right.as_ref().clone()));
+            }
+            Expr::BinaryExpr(BinaryExpr { left, op: Operator::Eq, right })
+                if resolves(right, &join.left) && resolves(left, &join.right) =>
+            {
+                on.push((right.as_ref().clone(), left.as_ref().clone()));
+            }
+            _ if resolves(conjunct, &join.left) => left_conjuncts.push(conjunct.clone()),
+            _ if resolves(conjunct, &join.right) => right_conjuncts.push(conjunct.clone()),
+            _ => residual.push(conjunct.clone()),
+        }
+    }
+    if on.is_empty() {
+        return None;
+    }
+
+    // The input with its conjuncts merged into it if it is a cross join they key, else the input and its conjuncts
+    // are left as they are
+    let merge_into_input = |input: &LogicalPlan, conjuncts: &mut Vec<Expr>| {
+        let merged = conjunction(conjuncts.clone())
+            .and_then(|predicate| Filter::try_new(predicate, Arc::new(input.clone())).ok())
+            .and_then(|filtered| merge_filter_into_cross_join(&LogicalPlan::Filter(filtered)));
+        match merged {
+            Some(merged) => {
+                conjuncts.clear();
+                merged
+            }
+            None => input.clone(),
+        }
+    };
+    let left = merge_into_input(&join.left, &mut left_conjuncts);
+    let right = merge_into_input(&join.right, &mut right_conjuncts);
+    let inner_join = LogicalPlan::Join(
+        Join::try_new(
+            Arc::new(left),
+            Arc::new(right),
+            on,
+            None,
+            JoinType::Inner,
+            join.join_constraint,
+            join.null_equality,
+        )
+        .ok()?,
+    );
+    match conjunction(left_conjuncts.into_iter().chain(right_conjuncts).chain(residual)) {
+        Some(predicate) => Filter::try_new(predicate, Arc::new(inner_join)).ok().map(LogicalPlan::Filter),
+        None => Some(inner_join),
+    }
+}
+
+/// The conjuncts of every filter and join condition in a plan
+fn get_plan_predicates(plan: &LogicalPlan) -> Vec<Expr> {
+    let mut predicates = Vec::new();
+    plan.apply(|node| {
+        match node {
+            LogicalPlan::Filter(filter) => {
+                predicates.extend(split_conjunction(&filter.predicate).into_iter().cloned());
+            }
+            LogicalPlan::Join(join) => {
+                predicates.extend(join.on.iter().map(|(left, right)| left.clone().eq(right.clone())));
+                if let Some(filter) = &join.filter {
+                    predicates.extend(split_conjunction(filter).into_iter().cloned());
+                }
+            }
+            _ => {}
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })
+    .expect("visiting a logical plan does not fail");
+    predicates
+}
+
+fn get_schema_relations(plan: &LogicalPlan) -> BTreeSet<String> {
+    plan.schema()
+        .iter()
+        .filter_map(|(qualifier, _)| qualifier.map(|qualifier| qualifier.to_string()))
+        .collect()
+}
 
 /// Get all possible trees for a given group.
 pub fn get_all_possible_trees(group: Rc<RefCell<Group>>) -> Vec<String> {
@@ -27,6 +790,41 @@ pub fn get_all_possible_trees(group: Rc<RefCell<Group>>) -> Vec<String> {
     output
 }
 
+/// Like get_all_possible_trees, but every inner or full join is also listed with its inputs swapped, so a memo
+/// storing a single orientation of each join, see Cascades::with_implicit_commutativity, lists the trees of both
+pub fn get_all_possible_trees_with_commuted(group: Rc<RefCell<Group>>) -> Vec<String> {
+    let mut output = Vec::new();
+    let mut seen = HashSet::new();
+
+    for mexpr in group.borrow().equivalent_logical_mexprs.borrow().iter() {
+        let op = mexpr.op();
+        if let LogicalPlan::TableScan(table_scan) = &*op.borrow() {
+            return vec![table_scan.table_name.to_string()];
+        }
+
+        let mut lists: Vec<Vec<String>> = mexpr
+            .operands()
+            .iter()
+            .map(|operand| get_all_possible_trees_with_commuted(Rc::clone(operand)))
+            .collect();
+        let mut products = get_cartesian_product(&lists);
+        if matches!(&*op.borrow(), LogicalPlan::Join(join) if matches!(join.join_type, JoinType::Inner | JoinType::Full))
+        {
+            lists.reverse();
+            products.extend(get_cartesian_product(&lists));
+        }
+
+        for product in products {
+            let tree = format!("({})", product);
+            if seen.insert(tree.clone()) {
+                output.push(tree);
+            }
+        }
+    }
+
+    output
+}
+
 /// Get the Cartesian product of a list of lists.
 pub fn get_cartesian_product(lists: &[Vec<String>]) -> Vec<String> {
     if lists.is_empty() {
@@ -51,37 +849,118 @@ pub fn get_cartesian_product(lists: &[Vec<String>]) -> Vec<String> {
 }
 
 /// Get the count of all possible trees for a given group.
-pub fn get_all_possible_trees_count(group: Rc<RefCell<Group>>) -> u64 {
+/// Errors if the group or a group below it is not fully explored yet, e.g. when called mid-optimization
+pub fn get_all_possible_trees_count(group: Rc<RefCell<Group>>) -> Result<u64, String> {
     let mut output = 0;
 
     // Verify that the group is explored and has no unexplored logical expressions
-    assert!(
-        group
-            .borrow()
-            .unexplored_equivalent_logical_mexprs
-            .borrow()
-            .is_empty()
-    );
-    assert!(group.borrow().is_explored());
+    let is_fully_explored = {
+        let group = group.borrow();
+        group.is_explored() && group.unexplored_equivalent_logical_mexprs.borrow().is_empty()
+    };
+    if !is_fully_explored {
+        return Err(format!("group G{} not fully explored", group.borrow().group_id));
+    }
 
     for mexpr in group.borrow().equivalent_logical_mexprs.borrow().iter() {
         let op = mexpr.op();
         if let LogicalPlan::TableScan(_) = &*op.borrow() {
-            return 1;
+            return Ok(1);
         }
 
         let mut tree_count = 1;
         for operand in mexpr.operands() {
             // Assuming the operator is multiplicative, e.g., InnerJoin
-            tree_count *= get_all_possible_trees_count(Rc::clone(operand));
+            tree_count *= get_all_possible_trees_count(Rc::clone(operand))?;
+        }
+
+        output += tree_count;
+    }
+
+    Ok(output)
+}
+
+/// Like get_all_possible_trees, but only the left-deep trees, where the right input of every join reads from a single
+/// source relation. Joins with a join on their right are pruned before walking their operands
+pub fn get_left_deep_trees(group: Rc<RefCell<Group>>) -> Vec<String> {
+    let mut output = Vec::new();
+
+    for mexpr in group.borrow().equivalent_logical_mexprs.borrow().iter() {
+        let op = mexpr.op();
+        if let LogicalPlan::TableScan(table_scan) = &*op.borrow() {
+            return vec![table_scan.table_name.to_string()];
+        }
+        if !is_left_deep_join(mexpr) {
+            continue;
         }
 
+        let mut lists = Vec::new();
+        for operand in mexpr.operands() {
+            lists.push(get_left_deep_trees(Rc::clone(operand)));
+        }
+
+        for product in get_cartesian_product(&lists) {
+            output.push(format!("({})", product));
+        }
+    }
+
+    output
+}
+
+/// Count the trees get_left_deep_trees lists without building them
+pub fn get_left_deep_trees_count(group: Rc<RefCell<Group>>) -> u64 {
+    let mut output = 0;
+
+    for mexpr in group.borrow().equivalent_logical_mexprs.borrow().iter() {
+        if let LogicalPlan::TableScan(_) = &*mexpr.op().borrow() {
+            return 1;
+        }
+        if !is_left_deep_join(mexpr) {
+            continue;
+        }
+
+        let mut tree_count = 1;
+        for operand in mexpr.operands() {
+            tree_count *= get_left_deep_trees_count(Rc::clone(operand));
+        }
         output += tree_count;
     }
 
     output
 }
 
+/// Whether a mexpr can be part of a left-deep tree : any operator other than a join, or a join whose right input reads
+/// from a single source relation
+fn is_left_deep_join(mexpr: &MExpr) -> bool {
+    if !matches!(&*mexpr.op().borrow(), LogicalPlan::Join(_)) {
+        return true;
+    }
+    mexpr.operands().get(1).is_some_and(|right| get_group_sources(right).len() == 1)
+}
+
+/// Count the distinct trees a group can produce without building them, each group is visited once
+/// Unlike get_all_possible_trees_count the group need not be fully explored, only the mexprs explored so far count
+pub fn get_search_space_size(
+    group: &Rc<RefCell<Group>>,
+    group_sizes: &mut HashMap<*const RefCell<Group>, u128>,
+) -> u128 {
+    if let Some(size) = group_sizes.get(&Rc::as_ptr(group)) {
+        return *size;
+    }
+
+    let mut size: u128 = 0;
+    for mexpr in group.borrow().equivalent_logical_mexprs.borrow().iter() {
+        let mut mexpr_size: u128 = 1;
+        for operand in mexpr.operands() {
+            mexpr_size = mexpr_size.saturating_mul(get_search_space_size(operand, group_sizes));
+        }
+        size = size.saturating_add(mexpr_size);
+    }
+
+    group_sizes.insert(Rc::as_ptr(group), size);
+    size
+}
+
 pub fn get_cheapest_tree(group: Rc<RefCell<Group>>) -> String {
     if group.borrow().cheapest_logical_expression.is_none() {
         return "None".to_string();
@@ -108,3 +987,295 @@ pub fn get_cheapest_tree(group: Rc<RefCell<Group>>) -> String {
 
     result.trim_end().to_string()
 }
+
+/// Like get_cheapest_tree, but walks the cheapest physical expressions and labels joins with their algorithm
+pub fn get_cheapest_physical_tree(group: Rc<RefCell<Group>>) -> String {
+    let Some(cheapest_expr) = group.borrow().cheapest_physical_expression.clone() else {
+        return "None".to_string();
+    };
+
+    let op = cheapest_expr.op();
+    let display = match (cheapest_expr.join_algorithm(), get_build_operand(&cheapest_expr)) {
+        (Some(algorithm), Some(build_operand)) => format!(
+            "{} {}, Build {}",
+            algorithm,
+            op.borrow().display(),
+            format_sources(&get_group_sources(&build_operand))
+        ),
+        (Some(algorithm), None) => format!("{} {}", algorithm, op.borrow().display()),
+        (None, _) => op.borrow().display().to_string(),
+    };
+
+    let mut result = format!("{}, Cost {}, RowCount {}", display, cheapest_expr.cost(), cheapest_expr.row_count());
+    for operand in cheapest_expr.operands() {
+        for line in get_cheapest_physical_tree(Rc::clone(operand)).lines() {
+            result.push_str(&format!("\n    -> {}", line));
+        }
+    }
+    result
+}
+
+/// Render the cheapest plan of a group the way PostgreSQL's EXPLAIN does, e.g. `Hash Join  (cost=84.00..84.01 rows=1)`
+/// The physical plan is rendered if the group has been implemented, the logical plan otherwise. The startup cost of
+/// a node is taken to be the cost of its inputs, so scans start at 0.00
+pub fn format_cheapest_plan_pg(group: Rc<RefCell<Group>>) -> String {
+    get_pg_lines(&group).join("\n")
+}
+
+fn get_pg_lines(group: &Rc<RefCell<Group>>) -> Vec<String> {
+    let (cheapest_expr, is_physical) = {
+        let group_borrowed = group.borrow();
+        match (&group_borrowed.cheapest_physical_expression, &group_borrowed.cheapest_logical_expression) {
+            (Some(physical), _) => (physical.clone(), true),
+            (None, Some(logical)) => (logical.clone(), false),
+            (None, None) => return vec!["None".to_string()],
+        }
+    };
+
+    let op = cheapest_expr.op();
+    let op = op.borrow();
+    let mut details = Vec::new();
+    let name = match &*op {
+        LogicalPlan::TableScan(scan) => {
+            details.extend(scan.filters.iter().map(|filter| format!("Filter: ({})", filter)));
+            format!("Seq Scan on {}", scan.table_name)
+        }
+        LogicalPlan::Join(join) => {
+            if !join.on.is_empty() {
+                let keys: Vec<String> = join.on.iter().map(|(left, right)| format!("{} = {}", left, right)).collect();
+                details.push(format!("{}: ({})", get_pg_join_condition(cheapest_expr.join_algorithm()), keys.join(" AND ")));
+            }
+            if let Some(filter) = &join.filter {
+                details.push(format!("Join Filter: ({})", filter));
+            }
+            let join_type = match join.join_type {
+                JoinType::Inner => String::new(),
+                join_type => format!(" {}", join_type),
+            };
+            match cheapest_expr.join_algorithm() {
+                Some(JoinAlgorithm::Hash { .. }) => format!("Hash{} Join", join_type),
+                Some(JoinAlgorithm::SortMerge { .. }) => format!("Merge{} Join", join_type),
+                Some(JoinAlgorithm::NestedLoop) => format!("Nested Loop{}", join_type),
+                Some(JoinAlgorithm::IndexNestedLoop { .. }) => format!("Index Nested Loop{}", join_type),
+                None => format!("{} Join", join.join_type),
+            }
+        }
+        LogicalPlan::Filter(filter) => {
+            details.push(format!("Filter: ({})", filter.predicate));
+            "Filter".to_string()
+        }
+        LogicalPlan::Projection(_) => "Result".to_string(),
+        LogicalPlan::Aggregate(_) => "HashAggregate".to_string(),
+        LogicalPlan::Distinct(_) => "Unique".to_string(),
+        LogicalPlan::Window(_) => "WindowAgg".to_string(),
+        _ => get_operator_name(&op),
+    };
+
+    let startup_cost = cheapest_expr.operands().iter().fold(0.0, |cost, operand| {
+        let operand = operand.borrow();
+        cost + if is_physical { operand.get_group_physical_cost() } else { operand.get_group_cost() }
+    });
+    let mut lines = vec![format!(
+        "{}  (cost={:.2}..{:.2} rows={})",
+        name,
+        startup_cost,
+        cheapest_expr.cost(),
+        cheapest_expr.row_count()
+    )];
+    lines.extend(details.into_iter().map(|detail| format!("  {}", detail)));
+    for operand in cheapest_expr.operands() {
+        for (index, line) in get_pg_lines(operand).into_iter().enumerate() {
+            let prefix = if index == 0 { "  ->  " } else { "      " };
+            lines.push(format!("{}{}", prefix, line));
+        }
+    }
+    lines
+}
+
+fn get_pg_join_condition(join_algorithm: Option<JoinAlgorithm>) -> &'static str {
+    match join_algorithm {
+        Some(JoinAlgorithm::Hash { .. }) => "Hash Cond",
+        Some(JoinAlgorithm::SortMerge { .. }) => "Merge Cond",
+        Some(JoinAlgorithm::NestedLoop) => "Join Filter",
+        Some(JoinAlgorithm::IndexNestedLoop { .. }) => "Index Cond",
+        None => "Join Cond",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cascades::Cascades;
+    use crate::cascades::cost_model::{ColumnStats, CostModel};
+    use crate::cascades::test_utils;
+    use datafusion_expr::{col, lit};
+
+    #[tokio::test]
+    async fn test_total_cost_by_operator() {
+        let logical_plan = test_utils::generate_logical_plan(vec![100, 2000, 300, 40]).await;
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+        cascades.optimize(root_group.clone());
+
+        let plan = get_cheapest_plan(root_group.clone()).expect("root group should have a plan");
+        let costs = plan.total_cost_by_operator();
+        println!("Cost by operator {:?}", costs);
+
+        assert!(costs.contains_key("Join"));
+        assert!(costs.contains_key("TableScan"));
+        let total: f64 = costs.values().sum();
+        assert!((total - root_group.borrow().get_group_cost()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_trees_count_of_partially_explored_memo() {
+        let mut cascades = Cascades::default();
+        let root_group = cascades.seed_memo("123");
+
+        // Only the (t1 ⋈ t2) group below the root is explored
+        let join_group = Rc::clone(&root_group.borrow().start_expression.as_ref().unwrap().operands()[0]);
+        cascades.optimize(join_group.clone());
+        assert_eq!(get_all_possible_trees_count(join_group), Ok(2));
+
+        let root_id = root_group.borrow().group_id;
+        assert_eq!(
+            get_all_possible_trees_count(root_group.clone()),
+            Err(format!("group G{} not fully explored", root_id))
+        );
+
+        cascades.optimize(root_group.clone());
+        assert!(get_all_possible_trees_count(root_group).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_format_tree_detailed() {
+        let logical_plan = test_utils::generate_logical_plan(vec![100, 2000, 300]).await;
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+        cascades.optimize(root_group.clone());
+        let plan = get_cheapest_plan(root_group.clone()).unwrap();
+
+        let detailed = plan.format_tree(PlanFormat::Detailed);
+        println!("{}", detailed);
+        let min_cost = root_group.borrow().get_group_cost();
+        let root_line = detailed.lines().next().unwrap();
+        assert!(root_line.contains(&format!("Cumulative Cost {:.2}", min_cost)), "{}", root_line);
+        assert!(root_line.contains("Self Cost "), "{}", root_line);
+        for table_rows in ["Table Rows 100", "Table Rows 2000", "Table Rows 300"] {
+            assert_eq!(detailed.lines().filter(|line| line.ends_with(table_rows)).count(), 1, "{}", table_rows);
+        }
+
+        // A scan's filters cut its row count, not the size of its table
+        let LogicalPlan::TableScan(mut filtered_scan) = test_utils::build_table_scan("t1", &["a1"], Some(1000)) else {
+            panic!("Expected a TableScan node");
+        };
+        filtered_scan.filters = vec![col("a1").gt(lit(5))];
+        let mut cascades = Cascades::default();
+        let scan_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(LogicalPlan::TableScan(filtered_scan))));
+        cascades.optimize(scan_group.clone());
+        let scan = get_cheapest_plan(scan_group).unwrap();
+        assert!(scan.row_count < 1000);
+        let scan_line = scan.format_tree(PlanFormat::Detailed);
+        assert!(scan_line.ends_with(&format!("RowCount {}, Table Rows 1000", scan.row_count)), "{}", scan_line);
+
+        // The plain format is the same tree get_cheapest_tree prints
+        assert_eq!(plan.format_tree(PlanFormat::Plain), get_cheapest_tree(root_group));
+    }
+
+    #[test]
+    fn test_format_cheapest_plan_pg() {
+        let mut cascades = Cascades::default();
+        let root_group = cascades.seed_memo("123");
+        cascades.optimize(root_group.clone());
+
+        let explain = format_cheapest_plan_pg(root_group);
+        let lines: Vec<&str> = explain.lines().collect();
+        assert!(lines.iter().any(|line| line.contains("Join") && line.contains("(cost=")));
+        assert!(lines[1].trim_start().contains(": (t"));
+        for table in ["t1", "t2", "t3"] {
+            let scan = format!("->  Seq Scan on {}  (cost=0.00..", table);
+            assert!(lines.iter().any(|line| line.contains(&scan) && line.ends_with("rows=42)")));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plan_diff() {
+        let optimize = |cascades: &mut Cascades, logical_plan: LogicalPlan| {
+            let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+            cascades.optimize(root_group.clone());
+            get_cheapest_plan(root_group).expect("root group should have a plan")
+        };
+        let logical_plan = test_utils::generate_logical_plan(vec![100, 2000, 300, 40]).await;
+        let default_plan = optimize(&mut Cascades::default(), logical_plan.clone());
+
+        // Make t1 ⋈ t4, the first join of the default plan, a cross product in all but name
+        let mut cost_model = CostModel::default();
+        cost_model.set_column_stats("t1", "a1", ColumnStats::with_ndv(1));
+        cost_model.set_column_stats("t4", "a4", ColumnStats::with_ndv(1));
+        let mut cascades = Cascades::builder().cost_model(cost_model).build();
+        let modified_plan = optimize(&mut cascades, logical_plan);
+
+        let diff = plan_diff(&default_plan, &modified_plan);
+        println!("Plan diff\n{}", diff);
+
+        let root_delta = format!(
+            "Cost {:.2} -> {:.2} ({:+.2})",
+            default_plan.cost,
+            modified_plan.cost,
+            modified_plan.cost - default_plan.cost
+        );
+        assert!(diff.lines().next().unwrap().ends_with(&root_delta));
+        assert!(modified_plan.cost > default_plan.cost);
+        assert!(diff.contains("Reordered"));
+        assert!(plan_diff(&default_plan, &default_plan).lines().all(|line| line.ends_with("(+0.00)")));
+    }
+
+    #[tokio::test]
+    async fn test_validate_plan_schema() {
+        let logical_plan = test_utils::generate_logical_plan(vec![100, 2000, 300, 40]).await;
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+        cascades.optimize(root_group.clone());
+
+        let cheapest = get_cheapest_logical_plan(&root_group).expect("failed to rebuild the cheapest plan");
+        assert!(validate_plan_schema(&cheapest).is_ok());
+
+        // A join claiming the schema of its left input alone
+        let LogicalPlan::Projection(projection) = &cheapest else {
+            panic!("Expected a Projection node");
+        };
+        let LogicalPlan::Join(join) = projection.input.as_ref() else {
+            panic!("Expected a Join node");
+        };
+        let mut mismatched = join.clone();
+        mismatched.schema = Arc::clone(join.left.schema());
+        let error = validate_plan_schema(&LogicalPlan::Join(mismatched)).unwrap_err();
+        println!("{}", error);
+        assert!(error.to_string().contains("does not match its inputs"));
+    }
+
+    #[tokio::test]
+    async fn test_datafusion_join_hints() {
+        let logical_plan = test_utils::generate_logical_plan(vec![100, 2000, 300, 40]).await;
+        let mut cascades = Cascades::default();
+        let root_group = cascades.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+        cascades.optimize(root_group.clone());
+
+        let plan = get_cheapest_physical_plan(root_group.clone()).expect("root group should be implemented");
+        let hints = plan.to_datafusion_hints();
+        println!("Join hints {:?}", hints);
+
+        // One hint per join of the 4 tables, the topmost join covering all of them
+        assert_eq!(hints.len(), 3);
+        assert!(hints.contains_key(&get_group_sources(&root_group)));
+        let mut joins = vec![&plan];
+        while let Some(node) = joins.pop() {
+            joins.extend(&node.children);
+            let Some(algorithm) = node.join_algorithm else {
+                continue;
+            };
+            assert_eq!(hints[&node.sources], PhysicalJoinType::from(algorithm));
+            assert_eq!(node.build_side.is_some(), hints[&node.sources] == PhysicalJoinType::HashJoin);
+        }
+    }
+}
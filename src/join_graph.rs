@@ -1,16 +1,35 @@
-use datafusion_expr::{LogicalPlan, Expr, JoinType, Operator};
+use datafusion_expr::{LogicalPlan, LogicalPlanBuilder, Expr, JoinType, Operator};
 use datafusion_common::tree_node::{TreeNode, TreeNodeRecursion, TreeNodeVisitor};
 use datafusion_common::DataFusionError;
+use crate::cascades::constants::DEFAULT_ROW_COUNT;
+use crate::cascades::expression_utils::{flip_equality, get_equivalence_classes, get_new_equalities};
+use crate::cascades::mexpr::MExpr;
+use crate::cascades::util::get_operator_name;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 /// Represents a join graph extracted from a logical plan
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct JoinGraph {
-    /// Vector of join expressions of the form `left = right`
+    /// Vector of join expressions of the form `left = right`, each equality listed once whichever way round it appears
     pub join_expressions: Vec<Expr>,
     /// Vector of source plan nodes (non-join, non-projection nodes)
     pub sources: Vec<LogicalPlan>,
 }
 
+/// Columns of three or more sources that the join expressions all equate, e.g. `t1.a = t2.a AND t1.a = t3.a`
+/// Every pair of its sources is connected on the key, whether or not the query spells out their equality
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hyperedge {
+    /// The equated column of each source, the first one in display order if a source has several
+    pub columns: BTreeMap<usize, Expr>,
+}
+
+impl Hyperedge {
+    pub fn sources(&self) -> BTreeSet<usize> {
+        self.columns.keys().copied().collect()
+    }
+}
+
 impl JoinGraph {
     pub fn new() -> Self {
         Self {
@@ -25,6 +44,248 @@ impl JoinGraph {
         plan.visit(&mut visitor)?;
         Ok(visitor.join_graph)
     }
+
+    /// Add a join expression unless it, or its flipped form, is already in the graph
+    /// Returns whether the expression was added
+    pub fn add_join_expression(&mut self, expr: Expr) -> bool {
+        let flipped = flip_equality(&expr);
+        if self.join_expressions.iter().any(|existing| *existing == expr || *existing == flipped) {
+            return false;
+        }
+        self.join_expressions.push(expr);
+        true
+    }
+
+    /// Find the source that all the columns referenced by `expr` resolve to
+    /// Returns None if the expression references no columns, or columns from more than one source
+    pub fn find_source_index(&self, expr: &Expr) -> Option<usize> {
+        let columns = expr.column_refs();
+        if columns.is_empty() {
+            return None;
+        }
+
+        let mut matching_sources = self.sources.iter().enumerate().filter(|(_, source)| {
+            columns.iter().all(|column| source.schema().has_column(column))
+        });
+        let (index, _) = matching_sources.next()?;
+        if matching_sources.next().is_some() {
+            return None; // Ambiguous
+        }
+        Some(index)
+    }
+
+    /// Resolve each `left = right` join expression to the pair of sources it connects
+    /// Returns (left source, right source, left expr, right expr) tuples
+    fn resolve_edges(&self) -> Vec<(usize, usize, Expr, Expr)> {
+        self.join_expressions
+            .iter()
+            .filter_map(|expr| match expr {
+                Expr::BinaryExpr(datafusion_expr::BinaryExpr {
+                    left,
+                    op: Operator::Eq,
+                    right,
+                }) => {
+                    let left_index = self.find_source_index(left)?;
+                    let right_index = self.find_source_index(right)?;
+                    (left_index != right_index).then(|| {
+                        (left_index, right_index, left.as_ref().clone(), right.as_ref().clone())
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The equivalence classes of the join expressions spanning three or more sources
+    pub fn hyperedges(&self) -> Vec<Hyperedge> {
+        let equalities: Vec<(Expr, Expr)> =
+            self.resolve_edges().into_iter().map(|(_, _, left, right)| (left, right)).collect();
+
+        get_equivalence_classes(&equalities)
+            .into_iter()
+            .filter_map(|class| {
+                let mut columns = BTreeMap::new();
+                for expr in class {
+                    if let Some(index) = self.find_source_index(&expr) {
+                        columns.entry(index).or_insert(expr);
+                    }
+                }
+                (columns.len() >= 3).then_some(Hyperedge { columns })
+            })
+            .collect()
+    }
+
+    /// The explicit edges plus, for every hyperedge, an edge between each pair of its sources
+    /// Implied edges may be redundant with explicit ones, callers only applying the equalities not already implied
+    fn resolve_edges_with_hyperedges(&self) -> Vec<(usize, usize, Expr, Expr)> {
+        let mut edges = self.resolve_edges();
+        for hyperedge in self.hyperedges() {
+            let columns: Vec<(&usize, &Expr)> = hyperedge.columns.iter().collect();
+            for (i, (left, left_expr)) in columns.iter().enumerate() {
+                for (right, right_expr) in &columns[i + 1..] {
+                    edges.push((**left, **right, (*left_expr).clone(), (*right_expr).clone()));
+                }
+            }
+        }
+        edges
+    }
+
+    /// Map each source index to its neighbors in the query graph and the `left = right` predicate connecting them
+    /// Every source has an entry, join expressions that don't resolve to two different sources are left out
+    pub fn adjacency(&self) -> HashMap<usize, Vec<(usize, Expr)>> {
+        let mut adjacency: HashMap<usize, Vec<(usize, Expr)>> =
+            (0..self.sources.len()).map(|index| (index, Vec::new())).collect();
+
+        for (left, right, left_expr, right_expr) in self.resolve_edges() {
+            let join_expr = left_expr.eq(right_expr);
+            adjacency.entry(left).or_default().push((right, join_expr.clone()));
+            adjacency.entry(right).or_default().push((left, join_expr));
+        }
+
+        for neighbors in adjacency.values_mut() {
+            neighbors.sort_by_key(|(index, _)| *index);
+        }
+        adjacency
+    }
+
+    /// Render the join graph in Graphviz DOT, a node per source labelled with its table name and row count, and an edge
+    /// per join expression connecting two sources. Each connected component is drawn as its own cluster, so sources
+    /// that can only be cross joined stand apart
+    pub fn to_dot(&self) -> String {
+        let adjacency = self.adjacency();
+        let mut component_of: Vec<Option<usize>> = vec![None; self.sources.len()];
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        for start in 0..self.sources.len() {
+            if component_of[start].is_some() {
+                continue;
+            }
+            component_of[start] = Some(components.len());
+            let mut component = vec![start];
+            let mut pending = vec![start];
+            while let Some(index) = pending.pop() {
+                for (neighbor, _) in &adjacency[&index] {
+                    if component_of[*neighbor].is_none() {
+                        component_of[*neighbor] = Some(components.len());
+                        component.push(*neighbor);
+                        pending.push(*neighbor);
+                    }
+                }
+            }
+            component.sort();
+            components.push(component);
+        }
+
+        let escape = |label: String| label.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut dot = String::from("graph join_graph {\n");
+        for (component_index, component) in components.iter().enumerate() {
+            dot.push_str(&format!("  subgraph cluster_{} {{\n", component_index));
+            dot.push_str(&format!("    label=\"component {}\";\n", component_index));
+            for index in component {
+                let source = &self.sources[*index];
+                let name = match source {
+                    LogicalPlan::TableScan(scan) => scan.table_name.to_string(),
+                    _ => get_operator_name(source),
+                };
+                dot.push_str(&format!(
+                    "    s{} [label=\"{}\\n{} rows\"];\n",
+                    index,
+                    escape(name),
+                    source_row_count(source)
+                ));
+            }
+            dot.push_str("  }\n");
+        }
+        for (left, neighbors) in adjacency.iter().collect::<BTreeMap<_, _>>() {
+            for (right, join_expr) in neighbors.iter().filter(|(right, _)| right > left) {
+                dot.push_str(&format!("  s{} -- s{} [label=\"{}\"];\n", left, right, escape(join_expr.to_string())));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Build a left-deep plan greedily following a minimum-selectivity spanning tree of the join graph
+    /// We start off with the most selective edge and keep adding the source connected to the sources joined so far
+    /// by the most selective edge, breaking ties on the smaller source. Disconnected sources are cross joined
+    /// in last, smallest first. Returns None if there is nothing to join
+    /// The sources of a hyperedge are all connected to each other, so they are joined in any order without a cross join
+    pub fn build_min_selectivity_spanning_tree(&self) -> Result<Option<LogicalPlan>, DataFusionError> {
+        if self.sources.len() < 2 {
+            return Ok(None);
+        }
+
+        let edges = self.resolve_edges_with_hyperedges();
+        let edge_selectivity =
+            |left: &Expr, right: &Expr| MExpr::get_join_selectivity(&[(left.clone(), right.clone())]);
+        let row_counts: Vec<u64> = self.sources.iter().map(source_row_count).collect();
+
+        // Pick the starting source, the smaller end of the most selective edge
+        let start = edges
+            .iter()
+            .min_by(|a, b| {
+                edge_selectivity(&a.2, &a.3)
+                    .total_cmp(&edge_selectivity(&b.2, &b.3))
+                    .then((row_counts[a.0] + row_counts[a.1]).cmp(&(row_counts[b.0] + row_counts[b.1])))
+            })
+            .map(|(left, right, _, _)| if row_counts[*left] <= row_counts[*right] { *left } else { *right })
+            .unwrap_or_else(|| (0..self.sources.len()).min_by_key(|i| row_counts[*i]).unwrap());
+
+        let mut joined = vec![false; self.sources.len()];
+        joined[start] = true;
+        let mut builder = LogicalPlanBuilder::from(self.sources[start].clone());
+        let mut applied_keys: Vec<(Expr, Expr)> = Vec::new();
+
+        for _ in 1..self.sources.len() {
+            // Find the most selective edge from a source joined so far to one that isn't
+            let next = edges
+                .iter()
+                .filter_map(|(left, right, left_expr, right_expr)| match (joined[*left], joined[*right]) {
+                    (true, false) => Some((*right, edge_selectivity(left_expr, right_expr))),
+                    (false, true) => Some((*left, edge_selectivity(left_expr, right_expr))),
+                    _ => None,
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1).then(row_counts[a.0].cmp(&row_counts[b.0])))
+                .map(|(index, _)| index)
+                .unwrap_or_else(|| {
+                    (0..self.sources.len())
+                        .filter(|i| !joined[*i])
+                        .min_by_key(|i| row_counts[*i])
+                        .unwrap()
+                });
+
+            // Every edge between the joined sources and the new one becomes a join key, unless the keys applied
+            // so far already imply it
+            let mut candidate_keys = Vec::new();
+            for (left, right, left_expr, right_expr) in &edges {
+                if joined[*left] && *right == next {
+                    candidate_keys.push((left_expr.clone(), right_expr.clone()));
+                } else if joined[*right] && *left == next {
+                    candidate_keys.push((right_expr.clone(), left_expr.clone()));
+                }
+            }
+            let new_keys = get_new_equalities(&applied_keys, &candidate_keys);
+            applied_keys.extend(new_keys.iter().cloned());
+            let (left_keys, right_keys): (Vec<Expr>, Vec<Expr>) = new_keys.into_iter().unzip();
+
+            builder = builder.join_with_expr_keys(
+                self.sources[next].clone(),
+                JoinType::Inner,
+                (left_keys, right_keys),
+                None,
+            )?;
+            joined[next] = true;
+        }
+
+        Ok(Some(builder.build()?))
+    }
+}
+
+/// Row count estimate for a source, the TableScan fetch if set
+fn source_row_count(source: &LogicalPlan) -> u64 {
+    match source {
+        LogicalPlan::TableScan(scan) => scan.fetch.map(|fetch| fetch as u64).unwrap_or(DEFAULT_ROW_COUNT),
+        _ => DEFAULT_ROW_COUNT,
+    }
 }
 
 /// Visitor that traverses a logical plan and builds a join graph
@@ -55,7 +316,7 @@ impl TreeNodeVisitor<'_> for JoinGraphVisitor {
                             op: Operator::Eq,
                             right: Box::new(right_expr.clone()),
                         });
-                        self.join_graph.join_expressions.push(join_expr);
+                        self.join_graph.add_join_expression(join_expr);
                     }
                 }
                 // Continue traversing to process children
@@ -68,8 +329,8 @@ impl TreeNodeVisitor<'_> for JoinGraphVisitor {
             _ => {
                 // For any other plan node type, add it to sources
                 self.join_graph.sources.push(node.clone());
-                // Stop traversing children since we've captured this source - THIS NEEDS TO BE FIXED
-                Ok(TreeNodeRecursion::Continue)
+                // Stop traversing children since we've captured this source
+                Ok(TreeNodeRecursion::Jump)
             }
         }
     }
@@ -83,7 +344,13 @@ impl TreeNodeVisitor<'_> for JoinGraphVisitor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cascades::Cascades;
+    use crate::cascades::test_utils::build_table_scan;
+    use crate::cascades::util;
     use datafusion::prelude::*;
+    use datafusion_expr::Join;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     #[tokio::test]
     async fn test_join_graph_extraction() -> Result<(), Box<dyn std::error::Error>> {
@@ -102,4 +369,109 @@ mod tests {
         
         Ok(())
     }
+
+    #[test]
+    fn test_chain_adjacency() -> Result<(), Box<dyn std::error::Error>> {
+        let plan = crate::cascades::test_utils::build_join_chain("1234");
+        let join_graph = JoinGraph::from_plan(&plan)?;
+        let adjacency = join_graph.adjacency();
+
+        let neighbors = |index: usize| adjacency[&index].iter().map(|(neighbor, _)| *neighbor).collect::<Vec<_>>();
+        assert_eq!(adjacency.len(), 4);
+        assert_eq!(neighbors(0), vec![1]);
+        assert_eq!(neighbors(1), vec![0, 2]);
+        assert_eq!(neighbors(2), vec![1, 3]);
+        assert_eq!(neighbors(3), vec![2]);
+
+        let (_, predicate) = &adjacency[&1][1];
+        assert_eq!(predicate.to_string(), "t2.a2 = t3.a3");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_join_expressions() -> Result<(), Box<dyn std::error::Error>> {
+        // The chain's t1.a1 = t2.a2 is repeated, flipped, on the join with t3
+        let LogicalPlan::Join(join) = crate::cascades::test_utils::build_join_chain("123") else {
+            panic!("expected a join");
+        };
+        let mut on = join.on.clone();
+        on.push((col("t2.a2"), col("t1.a1")));
+        let plan = LogicalPlan::Join(Join::try_new(
+            join.left.clone(),
+            join.right.clone(),
+            on,
+            None,
+            JoinType::Inner,
+            join.join_constraint,
+            join.null_equality,
+        )?);
+
+        let join_graph = JoinGraph::from_plan(&plan)?;
+        let expressions: Vec<String> = join_graph.join_expressions.iter().map(|expr| expr.to_string()).collect();
+        // Joins are visited top down, so the flipped form seen first is the one kept
+        assert_eq!(expressions, vec!["t2.a2 = t3.a3", "t2.a2 = t1.a1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_triangle_to_dot() -> Result<(), Box<dyn std::error::Error>> {
+        let scan = |n: usize| build_table_scan(&format!("t{}", n), &[&format!("a{}", n), &format!("b{}", n)], Some(n * 10));
+        let triangle = LogicalPlanBuilder::from(scan(1))
+            .join_with_expr_keys(scan(2), JoinType::Inner, (vec![col("t1.a1")], vec![col("t2.a2")]), None)?
+            .join_with_expr_keys(
+                scan(3),
+                JoinType::Inner,
+                (vec![col("t2.b2"), col("t1.b1")], vec![col("t3.a3"), col("t3.b3")]),
+                None,
+            )?
+            .build()?;
+
+        let dot = JoinGraph::from_plan(&triangle)?.to_dot();
+        println!("{}", dot);
+        assert!(dot.starts_with("graph join_graph {"));
+        assert_eq!(dot.matches(" -- ").count(), 3);
+        assert!(dot.contains("s0 -- s1 [label=\"t1.a1 = t2.a2\"]"));
+        assert!(dot.contains("s2 [label=\"t3\\n30 rows\"]"));
+        assert_eq!(dot.matches("subgraph cluster_").count(), 1);
+
+        // A cross joined source is a component of its own
+        let with_cross_join = LogicalPlanBuilder::from(triangle).cross_join(scan(4))?.build()?;
+        let dot = JoinGraph::from_plan(&with_cross_join)?.to_dot();
+        assert_eq!(dot.matches(" -- ").count(), 3);
+        assert_eq!(dot.matches("subgraph cluster_").count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_single_key_hyperedge() -> Result<(), Box<dyn std::error::Error>> {
+        // t1 is the hub of a star joined on a single key, t2 ⋈ t4 is only implied
+        let scan = |n: usize, rows: usize| build_table_scan(&format!("t{}", n), &[&format!("a{}", n)], Some(rows));
+        let mut builder = LogicalPlanBuilder::from(scan(1, 1000));
+        for (n, rows) in [(2, 10), (3, 100000), (4, 50)] {
+            let keys = (vec![col("t1.a1")], vec![col(format!("t{}.a{}", n, n))]);
+            builder = builder.join_with_expr_keys(scan(n, rows), JoinType::Inner, keys, None)?;
+        }
+        let plan = builder.build()?;
+
+        let join_graph = JoinGraph::from_plan(&plan)?;
+        let hyperedges = join_graph.hyperedges();
+        assert_eq!(hyperedges.len(), 1);
+        assert_eq!(hyperedges[0].sources(), BTreeSet::from([0, 1, 2, 3]));
+
+        let has_cross_join = |plan: &LogicalPlan| {
+            plan.exists(|node| Ok(matches!(node, LogicalPlan::Join(join) if join.on.is_empty())))
+                .unwrap()
+        };
+        let mst_plan = join_graph.build_min_selectivity_spanning_tree()?.unwrap();
+        println!("{}", mst_plan.display_indent());
+        assert!(!has_cross_join(&mst_plan));
+
+        let mut cascades = Cascades::new();
+        let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+        cascades.optimize(root.clone());
+        let cheapest = util::get_cheapest_logical_plan(&root)?;
+        println!("{}", cheapest.display_indent());
+        assert!(!has_cross_join(&cheapest));
+        Ok(())
+    }
 }
@@ -1,14 +1,9 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::time::Instant;
-use crate::cascades::Cascades;
-use crate::cascades::util::get_cheapest_tree;
-mod planprinter;
-mod join_graph;
-
-pub mod cascades;
-
-use crate::cascades::test_utils;
+use disagg_optimizer::cascades::Cascades;
+use disagg_optimizer::cascades::util::{get_cheapest_physical_tree, get_cheapest_tree};
+use disagg_optimizer::cascades::test_utils;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -60,14 +55,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     cascades.optimize(root_group.clone());
     let duration = start_time.elapsed();
     println!("Optimization completed in: {:.2?}", duration);
+    let mut phase_timings: Vec<_> = cascades.phase_timings().into_iter().collect();
+    phase_timings.sort();
+    for (phase, phase_duration) in phase_timings {
+        println!("  {}: {:.2?}", phase, phase_duration);
+    }
 
     //Print memo stats
     println!("Memo stats");
     cascades.print_memo_stats();
 
+    if let Some(baseline_cost) = cascades.baseline_cost() {
+        let optimized_cost = root_group.borrow().get_group_cost();
+        println!(
+            "Original join order cost {}, optimized cost {}, improvement {:.2}x",
+            baseline_cost,
+            optimized_cost,
+            baseline_cost / optimized_cost
+        );
+    }
+
     println!("Cheapest plan:");
     println!("{}",  get_cheapest_tree(root_group.clone()));
 
+    println!("Cheapest physical plan:");
+    println!("{}", get_cheapest_physical_tree(root_group.clone()));
+
     // println!("Generating all possible join trees");
     // let all_trees = get_all_possible_trees(root_group);
     
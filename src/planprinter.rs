@@ -2,6 +2,7 @@ use datafusion_common::tree_node::{TreeNodeRecursion, TreeNodeVisitor};
 use datafusion_expr::LogicalPlan;
 
 // Custom string builder for formatting the logical plan
+#[derive(Default)]
 pub struct PlanStringBuilder {
     output: String,
     depth: usize,
@@ -0,0 +1,1696 @@
+use datafusion_common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion_expr::{JoinType, LogicalPlan, LogicalPlanBuilder, col, lit};
+use disagg_optimizer::cascades::Cascades;
+use disagg_optimizer::cascades::constants::JOIN_COST_PER_ROW;
+use disagg_optimizer::cascades::group::Group;
+use disagg_optimizer::cascades::cost_model::{ColumnStats, CostModel};
+use disagg_optimizer::cascades::rulematcher::RuleTrace;
+use disagg_optimizer::cascades::search_config::{ExploreOrder, SearchMode, TieBreak};
+use disagg_optimizer::cascades::util::{self, PlanNode};
+use disagg_optimizer::cascades::test_utils;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use std::rc::Rc;
+
+#[tokio::test]
+async fn test_mst_seed_reduces_memo_size() {
+    let logical_plan = test_utils::generate_logical_plan(vec![1000, 10, 500, 20, 2000, 50]).await;
+
+    let mut unseeded = Cascades::default();
+    let unseeded_root = unseeded.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan.clone())));
+    unseeded.optimize(unseeded_root.clone());
+
+    let mut seeded = Cascades::default();
+    let seeded_root = seeded.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan.clone())));
+    seeded
+        .optimize_with_mst_seed(seeded_root.clone(), &logical_plan)
+        .expect("failed to optimize with the spanning tree seed");
+
+    println!(
+        "Memo entries without seed {}, with seed {}",
+        unseeded.get_memo().len(),
+        seeded.get_memo().len()
+    );
+    assert!(seeded.get_memo().len() < unseeded.get_memo().len());
+    assert_eq!(
+        seeded_root.borrow().get_group_cost(),
+        unseeded_root.borrow().get_group_cost()
+    );
+}
+
+fn assert_left_deep(node: &PlanNode) {
+    if node.operator == "Join" {
+        assert_ne!(node.children[1].operator, "Join", "Join has a join as its right input : {}", node.display);
+    }
+    node.children.iter().for_each(assert_left_deep);
+}
+
+#[tokio::test]
+async fn test_builder_left_deep_search() {
+    let logical_plan = test_utils::generate_logical_plan(vec![1000, 10, 500, 20]).await;
+
+    let mut left_deep = Cascades::builder().search_mode(SearchMode::LeftDeepOnly).build();
+    assert_eq!(left_deep.search_config().search_mode, SearchMode::LeftDeepOnly);
+    let left_deep_root = left_deep.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan.clone())));
+    left_deep.optimize(left_deep_root.clone());
+
+    let mut exhaustive = Cascades::new();
+    assert_eq!(exhaustive.search_config().search_mode, SearchMode::Exhaustive);
+    let exhaustive_root = exhaustive.gen_group_logical_plan(Rc::new(RefCell::new(logical_plan)));
+    exhaustive.optimize(exhaustive_root.clone());
+
+    let cheapest = util::get_cheapest_plan(left_deep_root.clone()).expect("no cheapest plan found");
+    assert_left_deep(&cheapest);
+
+    assert!(left_deep.get_memo().len() < exhaustive.get_memo().len());
+    assert!(left_deep_root.borrow().get_group_cost() >= exhaustive_root.borrow().get_group_cost());
+}
+
+#[test]
+fn test_seed_memo_and_gen_group_logical_plan() {
+    let mut seeded = Cascades::new();
+    let seeded_root = seeded.seed_memo("1234");
+
+    let mut generated = Cascades::new();
+    let generated_root =
+        generated.gen_group_logical_plan(Rc::new(RefCell::new(test_utils::build_join_chain("1234"))));
+
+    assert_eq!(seeded_root.borrow().get_group_hash(), generated_root.borrow().get_group_hash());
+    assert_eq!(seeded.get_memo().len(), generated.get_memo().len());
+
+    seeded.optimize(seeded_root.clone());
+    generated.optimize(generated_root.clone());
+    assert_eq!(
+        util::get_all_possible_trees_count(seeded_root).unwrap(),
+        util::get_all_possible_trees_count(generated_root).unwrap()
+    );
+}
+
+/// t1 ⋈ t2 on a1 = a2, then ⋈ Filter(b3 = 1)(t3) on b2 = b3
+fn build_filtered_star_plan() -> LogicalPlan {
+    let t1 = test_utils::build_table_scan("t1", &["a1"], Some(10));
+    let t2 = test_utils::build_table_scan("t2", &["a2", "b2"], Some(1000));
+    let t3 = LogicalPlanBuilder::from(test_utils::build_table_scan("t3", &["b3"], Some(1000)))
+        .filter(col("b3").eq(lit(1)))
+        .unwrap()
+        .build()
+        .unwrap();
+
+    LogicalPlanBuilder::from(t1)
+        .join_with_expr_keys(t2, datafusion_common::JoinType::Inner, (vec![col("a1")], vec![col("a2")]), None)
+        .unwrap()
+        .join_with_expr_keys(t3, datafusion_common::JoinType::Inner, (vec![col("b2")], vec![col("b3")]), None)
+        .unwrap()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_recost_after_stats_change() {
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_filtered_star_plan())));
+    cascades.optimize(root.clone());
+
+    let memo_size = cascades.get_memo().len();
+    let winner = root.borrow().cheapest_logical_expression.as_ref().unwrap().hash();
+
+    // With the default filter selectivity t1 ⋈ t2 is joined first, a high NDV makes the filtered t3 small enough
+    // that joining it with t2 first is cheaper
+    cascades.set_column_stats("t3", "b3", ColumnStats::with_ndv(1000));
+    cascades.recost();
+
+    assert_eq!(cascades.get_memo().len(), memo_size);
+    assert_ne!(root.borrow().cheapest_logical_expression.as_ref().unwrap().hash(), winner);
+
+    let mut fresh = Cascades::new();
+    fresh.set_column_stats("t3", "b3", ColumnStats::with_ndv(1000));
+    let fresh_root = fresh.gen_group_logical_plan(Rc::new(RefCell::new(build_filtered_star_plan())));
+    fresh.optimize(fresh_root.clone());
+    assert_eq!(root.borrow().get_group_cost(), fresh_root.borrow().get_group_cost());
+}
+
+#[test]
+fn test_explain_lists_every_group() {
+    let mut cascades = Cascades::new();
+    let root = cascades.seed_memo("1234");
+    cascades.optimize(root.clone());
+
+    let explanation = cascades.explain(root.clone());
+    println!("{}", explanation);
+
+    let groups = cascades.get_unique_groups_in_memo();
+    let mut unique_groups: Vec<_> = groups.iter().map(Rc::as_ptr).collect();
+    unique_groups.sort();
+    unique_groups.dedup();
+    assert_eq!(explanation.lines().filter(|line| line.starts_with("Group ")).count(), unique_groups.len());
+
+    for group in groups {
+        let group_line = format!(
+            "Group G{} {} : min_cost {},",
+            group.borrow().group_id,
+            util::format_sources(&util::get_group_sources(&group)),
+            group.borrow().get_group_cost()
+        );
+        assert!(explanation.contains(&group_line), "Missing line {}", group_line);
+    }
+    assert_eq!(explanation, cascades.explain(root));
+}
+
+#[test]
+fn test_aggregate_wraps_optimized_join() {
+    let plan = LogicalPlanBuilder::from(test_utils::build_join_chain("123"))
+        .aggregate(vec![col("t1.a1")], Vec::<datafusion_expr::Expr>::new())
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    cascades.optimize(root.clone());
+
+    let cheapest = util::get_cheapest_plan(root.clone()).expect("no cheapest plan found");
+    assert_eq!(cheapest.operator, "Aggregate");
+    assert_eq!(cheapest.children.len(), 1);
+    assert_eq!(cheapest.children[0].operator, "Join");
+
+    // The join subtree was reordered on its own, and every order keeps the grouping column
+    let join_group = Rc::clone(&root.borrow().cheapest_logical_expression.as_ref().unwrap().operands()[0]);
+    assert!(join_group.borrow().equivalent_logical_mexprs.borrow().len() > 1);
+    assert_eq!(
+        util::get_all_possible_trees_count(root.clone()).unwrap(),
+        util::get_all_possible_trees_count(join_group.clone()).unwrap()
+    );
+    let grouping_column = datafusion_common::Column::from_qualified_name("t1.a1");
+    for mexpr in join_group.borrow().equivalent_logical_mexprs.borrow().iter() {
+        assert!(mexpr.get_schema().unwrap().has_column(&grouping_column));
+    }
+}
+
+#[test]
+fn test_rule_fired_traces() {
+    let traces: Rc<RefCell<Vec<RuleTrace>>> = Rc::new(RefCell::new(Vec::new()));
+    let mut cascades = Cascades::new();
+    let collected = Rc::clone(&traces);
+    cascades.on_rule_fired(Box::new(move |trace| collected.borrow_mut().push(trace)));
+
+    let root = cascades.seed_memo("123");
+    let root_hash = root.borrow().get_group_hash();
+    cascades.optimize(root);
+
+    let traces = traces.borrow();
+    let root_commutativity = traces
+        .iter()
+        .find(|trace| trace.rule_name == "Join Commutativity" && trace.input_mexpr == root_hash)
+        .expect("commutativity did not fire for the root join");
+    assert_eq!(root_commutativity.output_mexprs.len(), 1);
+    assert!(root_commutativity.output_mexprs[0].1);
+    assert!(traces.iter().any(|trace| trace.rule_name == "Join Associativity"));
+}
+
+#[test]
+fn test_allow_bushy_toggle() {
+    let tree_shapes = |allow_bushy: bool| {
+        let mut cascades = Cascades::builder().allow_bushy(allow_bushy).build();
+        let root = cascades.seed_memo("1234");
+        cascades.optimize(root.clone());
+        util::get_all_possible_trees(root).into_iter().collect::<HashSet<_>>()
+    };
+
+    let bushy = tree_shapes(true);
+    let non_bushy = tree_shapes(false);
+    println!("{} trees with bushy, {} without", bushy.len(), non_bushy.len());
+
+    // A join with two joins as inputs shows up as two parenthesized children next to each other
+    let is_bushy = |tree: &String| tree.contains(") (");
+    assert!(bushy.iter().any(is_bushy));
+    assert!(!non_bushy.iter().any(is_bushy));
+    assert!(non_bushy.len() < bushy.len());
+    assert!(non_bushy.is_subset(&bushy));
+}
+
+#[test]
+fn test_materialized_subsets() {
+    let mut cascades = Cascades::new();
+    let root = cascades.seed_memo("123");
+    cascades.optimize(root);
+
+    let subset = |tables: &[&str]| tables.iter().map(|table| table.to_string()).collect::<BTreeSet<_>>();
+    // t1 ⋈ t3 is reachable through the inferred t1.a1 = t3.a3 predicate
+    assert_eq!(
+        cascades.materialized_subsets(),
+        vec![
+            subset(&["t1"]),
+            subset(&["t2"]),
+            subset(&["t3"]),
+            subset(&["t1", "t2"]),
+            subset(&["t1", "t3"]),
+            subset(&["t2", "t3"]),
+            subset(&["t1", "t2", "t3"]),
+        ]
+    );
+}
+
+#[test]
+fn test_persisted_subplans_are_reused() {
+    // The second query joins t1 and t2 through a join filter, so its t1 ⋈ t2 join doesn't hash like the first one's
+    let first_query = test_utils::build_join_chain("123");
+    let second_query = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], None))
+        .join_on(
+            test_utils::build_table_scan("t2", &["a2"], None),
+            datafusion_common::JoinType::Inner,
+            vec![col("t1.a1").eq(col("t2.a2"))],
+        )
+        .unwrap()
+        .join_with_expr_keys(
+            test_utils::build_table_scan("t4", &["a4"], None),
+            datafusion_common::JoinType::Inner,
+            (vec![col("t2.a2")], vec![col("t4.a4")]),
+            None,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::new();
+    cascades.persist_subplans();
+    let first_root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(first_query)));
+    cascades.optimize(first_root.clone());
+    let shared_group = Rc::clone(
+        &first_root.borrow().start_expression.as_ref().unwrap().operands()[0],
+    );
+    let shared_mexprs = shared_group.borrow().equivalent_logical_mexprs.borrow().len();
+
+    let fired_on_shared_group: Rc<RefCell<Vec<RuleTrace>>> = Rc::new(RefCell::new(Vec::new()));
+    let collected = Rc::clone(&fired_on_shared_group);
+    let shared_hashes: HashSet<u64> = shared_group
+        .borrow()
+        .equivalent_logical_mexprs
+        .borrow()
+        .iter()
+        .map(|mexpr| mexpr.hash())
+        .collect();
+    cascades.on_rule_fired(Box::new(move |trace| {
+        if shared_hashes.contains(&trace.input_mexpr) {
+            collected.borrow_mut().push(trace);
+        }
+    }));
+
+    let second_root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(second_query)));
+    let second_shared_group = Rc::clone(
+        &second_root.borrow().start_expression.as_ref().unwrap().operands()[0],
+    );
+    assert!(Rc::ptr_eq(&shared_group, &second_shared_group));
+
+    cascades.optimize(second_root.clone());
+    assert!(fired_on_shared_group.borrow().is_empty());
+    assert_eq!(shared_group.borrow().equivalent_logical_mexprs.borrow().len(), shared_mexprs);
+    assert!(second_root.borrow().is_explored());
+}
+
+#[test]
+fn test_persisted_subplans_need_the_same_predicates() {
+    let build_query = |key: &str| {
+        LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1", "b1"], None))
+            .join(
+                test_utils::build_table_scan("t2", &["a2", "b2"], None),
+                datafusion_common::JoinType::Inner,
+                (vec![format!("{}1", key)], vec![format!("{}2", key)]),
+                None,
+            )
+            .unwrap()
+            .project(vec![lit(1)])
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+
+    // The structural keys tell joins on other keys apart in the memo, so they only meet in the persisted subplans
+    let mut cascades = Cascades::new().with_structural_keys();
+    cascades.persist_subplans();
+    let first_root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_query("a"))));
+    cascades.optimize(first_root.clone());
+    let first_join = Rc::clone(&first_root.borrow().start_expression.as_ref().unwrap().operands()[0]);
+
+    // Joined on other keys, t1 ⋈ t2 is another join over the same leaves
+    let second_root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_query("b"))));
+    let second_join = Rc::clone(&second_root.borrow().start_expression.as_ref().unwrap().operands()[0]);
+    assert!(!Rc::ptr_eq(&first_join, &second_join));
+    assert!(!second_join.borrow().is_explored());
+
+    let third_root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_query("a"))));
+    let third_join = Rc::clone(&third_root.borrow().start_expression.as_ref().unwrap().operands()[0]);
+    assert!(Rc::ptr_eq(&first_join, &third_join));
+}
+
+#[test]
+fn test_cardinality_override() {
+    let t1_t2: BTreeSet<String> = ["t1".to_string(), "t2".to_string()].into();
+
+    let mut cascades = Cascades::new();
+    cascades.set_cardinality_override(t1_t2.clone(), 12345);
+    let root = cascades.seed_memo("123");
+    cascades.optimize(root);
+
+    let overridden_groups: Vec<_> = cascades
+        .get_unique_groups_in_memo()
+        .into_iter()
+        .filter(|group| util::get_group_sources(group) == t1_t2)
+        .collect();
+    assert!(!overridden_groups.is_empty());
+    for group in overridden_groups {
+        assert_eq!(group.borrow().get_group_row_count(), 12345);
+    }
+
+    // Other relation sets still use the selectivity estimate
+    let t2_t3: BTreeSet<String> = ["t2".to_string(), "t3".to_string()].into();
+    for group in cascades.get_unique_groups_in_memo() {
+        if util::get_group_sources(&group) == t2_t3 {
+            assert_eq!(group.borrow().get_group_row_count(), 1);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_plan_from_sql_unoptimized_keeps_from_order() {
+    let ctx = test_utils::setup_tables(3).unwrap();
+    let scan_order = |plan: &LogicalPlan| {
+        let mut tables = Vec::new();
+        plan.apply(|node| {
+            if let LogicalPlan::TableScan(scan) = node {
+                tables.push(scan.table_name.to_string());
+            }
+            Ok(TreeNodeRecursion::Continue)
+        })
+        .unwrap();
+        tables
+    };
+
+    for sql in [
+        "SELECT * FROM t3 JOIN t1 ON t3.a3 = t1.a1 JOIN t2 ON t1.a1 = t2.a2",
+        "SELECT * FROM t3, t1, t2 WHERE t3.a3 = t1.a1 AND t1.a1 = t2.a2",
+    ] {
+        let plan = test_utils::plan_from_sql_unoptimized(&ctx, sql).await.unwrap();
+        println!("{}", plan.display_indent());
+        assert_eq!(scan_order(&plan), vec!["t3", "t1", "t2"], "{}", sql);
+
+        // The analyzed plan is seeded and reordered as-is
+        let mut cascades = Cascades::new();
+        let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+        cascades.optimize(root.clone());
+        let reordered = util::get_cheapest_logical_plan(&root).unwrap();
+        test_utils::assert_same_results(&plan, &reordered).await;
+    }
+}
+
+#[tokio::test]
+async fn test_subquery_alias_seeding() {
+    let ctx = test_utils::setup_tables(2).unwrap();
+    let plan = ctx
+        .sql("SELECT * FROM (SELECT * FROM t1) x JOIN t2 ON x.a1 = t2.a2")
+        .await
+        .unwrap()
+        .into_unoptimized_plan();
+
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    cascades.optimize(root.clone());
+    assert!(root.borrow().is_explored());
+
+    let alias_groups: Vec<_> = cascades
+        .get_unique_groups_in_memo()
+        .into_iter()
+        .filter(|group| {
+            let group = group.borrow();
+            let start_expression = group.start_expression.as_ref().unwrap();
+            matches!(*start_expression.op().borrow(), LogicalPlan::SubqueryAlias(_))
+        })
+        .collect();
+    assert!(!alias_groups.is_empty());
+
+    let aliased_column = datafusion_common::Column::from_qualified_name("x.a1");
+    for group in alias_groups {
+        let schema = group.borrow().start_expression.as_ref().unwrap().get_schema().unwrap();
+        assert!(schema.has_column(&aliased_column));
+    }
+}
+
+#[test]
+fn test_stats_apply_through_subquery_alias() {
+    let build_join = |alias: Option<&str>| {
+        let t1 = test_utils::build_table_scan("t1", &["a1"], Some(1000));
+        let (t1, t1_a1) = match alias {
+            Some(alias) => {
+                let aliased = LogicalPlanBuilder::from(t1).alias(alias).unwrap().build().unwrap();
+                (aliased, format!("{}.a1", alias))
+            }
+            None => (t1, "t1.a1".to_string()),
+        };
+        LogicalPlanBuilder::from(t1)
+            .filter(col(t1_a1.as_str()).eq(lit(5)))
+            .unwrap()
+            .join_on(
+                test_utils::build_table_scan("t2", &["a2"], Some(1000)),
+                JoinType::Inner,
+                vec![col(t1_a1.as_str()).eq(col("t2.a2"))],
+            )
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+    let row_counts = |plan: LogicalPlan| {
+        let mut cascades = Cascades::new();
+        cascades.set_column_stats("t1", "a1", ColumnStats::with_ndv(100));
+        let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+        cascades.optimize(root.clone());
+        let filter_group = cascades
+            .get_unique_groups_in_memo()
+            .into_iter()
+            .find(|group| {
+                let group = group.borrow();
+                matches!(*group.start_expression.as_ref().unwrap().op().borrow(), LogicalPlan::Filter(_))
+            })
+            .unwrap();
+        let filter_rows = filter_group.borrow().get_group_row_count();
+        let join_rows = root.borrow().get_group_row_count();
+        (filter_rows, join_rows)
+    };
+
+    // x.a1 reads t1.a1, so the NDV of t1.a1 and the canned t1 ⋈ t2 selectivity of 0.001 apply to it
+    assert_eq!(row_counts(build_join(None)), (10, 10));
+    assert_eq!(row_counts(build_join(Some("x"))), (10, 10));
+}
+
+/// Replace the constant projection generate_logical_plan puts on top of its joins with one over every column
+fn project_all_columns(plan: LogicalPlan) -> LogicalPlan {
+    let LogicalPlan::Projection(projection) = plan else {
+        panic!("Expected a Projection node");
+    };
+    let join = projection.input.as_ref().clone();
+    let columns: Vec<datafusion_expr::Expr> = join
+        .schema()
+        .columns()
+        .into_iter()
+        .map(datafusion_expr::Expr::Column)
+        .collect();
+    LogicalPlanBuilder::from(join).project(columns).unwrap().build().unwrap()
+}
+
+async fn assert_reordered_plan_is_equivalent(table_row_counts: Vec<usize>) {
+    let generated_plan = test_utils::generate_logical_plan(table_row_counts).await;
+    for plan in [generated_plan.clone(), project_all_columns(generated_plan)] {
+        let mut cascades = Cascades::new();
+        let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+        cascades.optimize(root.clone());
+
+        let reordered = util::get_cheapest_logical_plan(&root).unwrap();
+        println!("{}", reordered.display_indent());
+        assert!(!test_utils::collect_sorted_rows(&plan).await.is_empty());
+        test_utils::assert_same_results(&plan, &reordered).await;
+    }
+}
+
+#[tokio::test]
+async fn test_three_table_reorder_is_equivalent() {
+    assert_reordered_plan_is_equivalent(vec![5, 500, 50]).await;
+}
+
+#[tokio::test]
+async fn test_four_table_reorder_is_equivalent() {
+    assert_reordered_plan_is_equivalent(vec![500, 5, 5000, 50]).await;
+}
+
+#[test]
+fn test_search_space_size_of_cliques() {
+    // All join columns are in one equivalence class, so a chain join is a clique
+    // A clique of n tables has n! * Catalan(n - 1) ordered bushy trees
+    for (join_nodes, expected_trees) in [("1234", 24 * 5), ("12345", 120 * 14)] {
+        let mut cascades = Cascades::new();
+        let root = cascades.seed_memo(join_nodes);
+        cascades.optimize(root.clone());
+
+        assert_eq!(cascades.search_space_size(root.clone()), expected_trees);
+        assert_eq!(util::get_all_possible_trees_count(root).unwrap() as u128, expected_trees);
+    }
+}
+
+#[test]
+fn test_left_deep_trees_of_clique() {
+    // Every ordering of the 4 tables is a left-deep tree, commuted first joins included
+    let mut cascades = Cascades::new();
+    let root = cascades.seed_memo("1234");
+    cascades.optimize(root.clone());
+
+    let left_deep_trees = util::get_left_deep_trees(root.clone());
+    assert_eq!(left_deep_trees.len(), 24);
+    assert_eq!(util::get_left_deep_trees_count(root.clone()), 24);
+    assert_eq!(left_deep_trees.iter().collect::<HashSet<_>>().len(), 24);
+    assert!(left_deep_trees.contains(&"(((t1 t2) t3) t4)".to_string()));
+    assert!(!left_deep_trees.contains(&"((t1 t2) (t3 t4))".to_string()));
+    assert!(util::get_left_deep_trees_count(root.clone()) < util::get_all_possible_trees_count(root).unwrap());
+}
+
+#[test]
+fn test_strict_no_cross_join() {
+    let t1 = test_utils::build_table_scan("t1", &["a1"], Some(1000));
+    let t2 = test_utils::build_table_scan("t2", &["a2"], Some(1000));
+
+    // A non-equi condition in a filter above the cross join is left there, so the cross join stays
+    let connected = LogicalPlanBuilder::from(t1.clone())
+        .cross_join(t2.clone())
+        .unwrap()
+        .filter(col("t1.a1").lt(col("t2.a2")))
+        .unwrap()
+        .build()
+        .unwrap();
+
+    assert!(Cascades::default().optimize_plan(&connected).is_ok());
+
+    let err = Cascades::default()
+        .with_strict_no_cross_join(true)
+        .optimize_plan(&connected)
+        .expect_err("strict mode should reject the cross join");
+    let message = err.to_string();
+    assert!(message.contains("{t1}"), "{message}");
+    assert!(message.contains("{t2}"), "{message}");
+
+    // Nothing connects the inputs of a cross join without predicates, so it is justified
+    let disconnected = LogicalPlanBuilder::from(t1).cross_join(t2).unwrap().build().unwrap();
+    assert!(
+        Cascades::default()
+            .with_strict_no_cross_join(true)
+            .optimize_plan(&disconnected)
+            .is_ok()
+    );
+}
+
+/// Fact table f joined with dimensions d1, d2 and d3, largest dimension first
+fn build_star_plan() -> LogicalPlan {
+    let fact = test_utils::build_table_scan("f", &["k1", "k2", "k3"], Some(100_000));
+    let mut plan = LogicalPlanBuilder::from(fact);
+    for (dimension, row_count) in [("d3", 1000), ("d2", 100), ("d1", 10)] {
+        let key = format!("{dimension}_k");
+        let fact_key = format!("k{}", &dimension[1..]);
+        plan = plan
+            .join_with_expr_keys(
+                test_utils::build_table_scan(dimension, &[&key], Some(row_count)),
+                datafusion_common::JoinType::Inner,
+                (vec![col(fact_key)], vec![col(key)]),
+                None,
+            )
+            .unwrap();
+    }
+    plan.build().unwrap()
+}
+
+#[test]
+fn test_cost_of_plan_compares_with_optimized() {
+    let original = build_star_plan();
+    let mut cascades = Cascades::default();
+    let optimized = cascades.optimize_plan(&original).unwrap();
+
+    let original_cost = cascades.cost_of_plan(&original);
+    let optimized_cost = cascades.cost_of_plan(&optimized);
+    println!("Original plan cost {original_cost}, optimized plan cost {optimized_cost}");
+    assert!(optimized_cost <= original_cost);
+    assert!(optimized_cost.is_finite());
+}
+
+#[test]
+fn test_one_group_per_relation_set() {
+    // Every non-empty subset of the chain's tables gets exactly one group
+    for (join_nodes, expected_groups) in [("123", 7), ("1234", 15)] {
+        let mut cascades = Cascades::default();
+        let root = cascades.seed_memo(join_nodes);
+        cascades.optimize(root);
+
+        let unique_groups: Vec<_> = cascades
+            .get_unique_groups_in_memo()
+            .into_iter()
+            .filter({
+                let mut seen = HashSet::new();
+                move |group| seen.insert(Rc::as_ptr(group))
+            })
+            .collect();
+        let relation_sets: HashSet<_> = unique_groups.iter().map(util::get_group_sources).collect();
+        println!("{join_nodes} : {} groups, {} relation sets", unique_groups.len(), relation_sets.len());
+        assert_eq!(unique_groups.len(), relation_sets.len());
+        assert_eq!(unique_groups.len(), expected_groups);
+    }
+}
+
+#[test]
+fn test_phase_timings() {
+    let mut cascades = Cascades::default();
+    assert!(cascades.phase_timings().is_empty());
+
+    let root = cascades.seed_memo("1234");
+    cascades.optimize(root);
+
+    let timings = cascades.phase_timings();
+    println!("Phase timings : {timings:?}");
+    for phase in ["apply_rules", "schema_build", "split_predicates", "costing"] {
+        assert!(timings.contains_key(phase), "missing phase {phase}");
+    }
+    assert!(timings["schema_build"] <= timings["apply_rules"]);
+}
+
+#[test]
+fn test_top_k_plans() {
+    let mut cascades = Cascades::default();
+    let root = cascades.seed_memo("123");
+    cascades.optimize(root.clone());
+
+    let plans = cascades.top_k_plans(root.clone(), 2);
+    assert_eq!(plans.len(), 2);
+    assert!(plans[0].1 <= plans[1].1);
+    assert!((plans[0].1 - root.borrow().get_group_cost()).abs() < 1e-6);
+
+    // Asking for more plans than the memo holds returns all of them, still in cost order
+    let search_space_size = cascades.search_space_size(root.clone()) as usize;
+    let all_plans = cascades.top_k_plans(root, search_space_size + 10);
+    assert_eq!(all_plans.len(), search_space_size);
+    assert!(all_plans.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+}
+
+#[test]
+fn test_optimize_subset() {
+    let mut cascades = Cascades::default();
+    let root = cascades.seed_memo("1234");
+    cascades.optimize(root);
+
+    let subset: Vec<String> = ["t1", "t2", "t3"].iter().map(|table| table.to_string()).collect();
+    let plan = cascades.optimize_subset(&subset).expect("no plan for the subset");
+    fn scans(node: &PlanNode) -> Vec<String> {
+        if node.children.is_empty() {
+            return vec![node.display.clone()];
+        }
+        node.children.iter().flat_map(scans).collect()
+    }
+    let mut tables = scans(&plan);
+    tables.sort();
+    assert_eq!(plan.operator, "Join");
+    assert_eq!(tables, vec!["TableScan: t1", "TableScan: t2", "TableScan: t3"]);
+
+    // The subset's plan is as cheap as optimizing the three tables on their own
+    let mut subset_cascades = Cascades::default();
+    let subset_root = subset_cascades.seed_memo("123");
+    subset_cascades.optimize(subset_root.clone());
+    assert!((plan.cost - subset_root.borrow().get_group_cost()).abs() < 1e-9);
+
+    // t5 is not in the memo
+    let missing: Vec<String> = ["t1", "t5"].iter().map(|table| table.to_string()).collect();
+    assert!(cascades.optimize_subset(&missing).is_none());
+}
+
+#[test]
+fn test_baseline_cost() {
+    let mut cascades = Cascades::default();
+    assert_eq!(cascades.baseline_cost(), None);
+
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(build_star_plan())));
+    let baseline_cost = cascades.baseline_cost().unwrap();
+    cascades.optimize(root.clone());
+
+    // The star plan joins the largest dimension first, so reordering strictly helps
+    assert!(baseline_cost > root.borrow().get_group_cost());
+    assert_eq!(baseline_cost, cascades.cost_of_plan(&build_star_plan()));
+}
+
+#[test]
+fn test_window_over_join() {
+    use datafusion::functions_window::expr_fn::row_number;
+    use datafusion_expr::ExprFunctionExt;
+
+    let window_expr = row_number().partition_by(vec![col("f.k1")]).build().unwrap();
+    let plan = LogicalPlanBuilder::from(build_star_plan())
+        .window(vec![window_expr])
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    cascades.optimize(root.clone());
+
+    let cheapest = util::get_cheapest_plan(root.clone()).expect("no cheapest plan found");
+    assert_eq!(cheapest.operator, "Window");
+    assert_eq!(cheapest.row_count, cheapest.children[0].row_count);
+    assert!(cheapest.row_count > 0);
+    assert!(cheapest.self_cost() > 0.0);
+}
+
+#[test]
+fn test_stats_provider_ndv_join_selectivity() {
+    let stats = test_utils::HashMapStatsProvider::default()
+        .with_row_count("t1", 1000)
+        .with_row_count("t2", 2000)
+        .with_ndv("t1", "a1", 100)
+        .with_ndv("t2", "a2", 500);
+    let plan = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], None))
+        .join_with_expr_keys(
+            test_utils::build_table_scan("t2", &["a2"], None),
+            datafusion_common::JoinType::Inner,
+            (vec![col("a1")], vec![col("a2")]),
+            None,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::new();
+    cascades.set_stats_provider(Box::new(stats));
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    cascades.optimize(root.clone());
+
+    // 1000 * 2000 rows joined at a selectivity of 1 / max(100, 500)
+    assert_eq!(root.borrow().get_group_row_count(), 4000);
+}
+
+#[test]
+fn test_memo_dump_round_trip() {
+    let mut cascades = Cascades::default();
+    let root = cascades.seed_memo("123");
+    cascades.optimize(root.clone());
+
+    let path = std::env::temp_dir().join(format!("memo_dump_{}.json", std::process::id()));
+    cascades.dump_memo(&path).unwrap();
+    let dump = Cascades::load_memo_dump(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let root_group_id = root.borrow().group_id;
+    assert_eq!(dump.get_cheapest_tree(root_group_id), util::get_cheapest_tree(root.clone()));
+    assert_eq!(dump.explain(root_group_id), cascades.explain(root.clone()));
+    assert_eq!(dump.memo.len(), cascades.get_memo().len());
+}
+
+#[test]
+fn test_dump_all_mexprs() {
+    let mut cascades = Cascades::default();
+    let root = cascades.seed_memo("12");
+    cascades.optimize(root.clone());
+
+    let path = std::env::temp_dir().join(format!("all_mexprs_{}.txt", std::process::id()));
+    cascades.dump_all_mexprs(&path).unwrap();
+    let dump = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    println!("{}", dump);
+
+    // The seeded t1 ⋈ t2 and its commuted t2 ⋈ t1 share their operator, they differ in the order of their operands
+    let start_expression = root.borrow().start_expression.clone().unwrap();
+    let [left, right] = start_expression.operands().as_slice() else {
+        panic!("Expected a join over two groups");
+    };
+    let (left_id, right_id) = (left.borrow().group_id, right.borrow().group_id);
+    let join_lines: Vec<&str> = dump.lines().filter(|line| line.contains("Inner Join")).collect();
+    assert_eq!(join_lines.len(), 2);
+    assert!(join_lines.iter().any(|line| line.contains(&format!("[G{}, G{}]", left_id, right_id))));
+    assert!(join_lines.iter().any(|line| line.contains(&format!("[G{}, G{}]", right_id, left_id))));
+    // Both joins carry the row count of their group
+    let row_count = root.borrow().get_group_row_count();
+    assert_eq!(row_count, 1);
+    for line in &join_lines {
+        assert!(line.contains(" cost "), "No cost in {}", line);
+        assert!(line.ends_with(&format!(", row_count {}", row_count)), "Wrong row count in {}", line);
+    }
+}
+
+#[test]
+fn test_memo_graph() {
+    let mut cascades = Cascades::default();
+    let root = cascades.seed_memo("123");
+    cascades.optimize(root.clone());
+
+    // The 3 scans and a group for each pair of tables, every pair being joined through the equivalent keys, and the
+    // root. Each pair group has its 2 tables as children, the root has every pair and every table
+    let graph = cascades.memo_graph();
+    assert_eq!(graph.groups.len(), 7);
+    assert_eq!(graph.edges.len(), 3 * 2 + 6);
+
+    let root_id = root.borrow().group_id;
+    let root_info = graph.get_group(root_id).unwrap();
+    assert_eq!(root_info.source_set, BTreeSet::from(["t1".to_string(), "t2".to_string(), "t3".to_string()]));
+    assert_eq!(root_info.min_cost, root.borrow().get_group_cost());
+    assert_eq!(root_info.n_logical, root.borrow().equivalent_logical_mexprs.borrow().len());
+    assert!(root_info.n_physical > 0);
+    assert_eq!(graph.get_children(root_id).len(), 6);
+    for group in &graph.groups {
+        let children = graph.get_children(group.id);
+        assert_eq!(children.is_empty(), group.source_set.len() == 1);
+    }
+}
+
+#[test]
+fn test_distinct_over_join() {
+    let plan = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(1000)))
+        .join_with_expr_keys(
+            test_utils::build_table_scan("t2", &["a2"], Some(1000)),
+            datafusion_common::JoinType::Inner,
+            (vec![col("a1")], vec![col("a2")]),
+            None,
+        )
+        .unwrap()
+        .distinct()
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::new();
+    cascades.set_column_stats("t1", "a1", ColumnStats::with_ndv(10));
+    cascades.set_column_stats("t2", "a2", ColumnStats::with_ndv(20));
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    cascades.optimize(root.clone());
+
+    let cheapest = util::get_cheapest_plan(root).expect("no cheapest plan found");
+    assert_eq!(cheapest.operator, "Distinct");
+    assert_eq!(cheapest.children[0].operator, "Join");
+    let join_rows = cheapest.children[0].row_count;
+    assert!(cheapest.row_count < join_rows, "{} distinct rows out of {}", cheapest.row_count, join_rows);
+    assert_eq!(cheapest.row_count, 200);
+}
+
+#[test]
+fn test_filter_over_cross_join_becomes_inner_join() {
+    let cross_join = || {
+        LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(1000)))
+            .cross_join(test_utils::build_table_scan("t2", &["a2"], Some(1000)))
+            .unwrap()
+    };
+
+    let equi_filtered = cross_join().filter(col("t1.a1").eq(col("t2.a2"))).unwrap().build().unwrap();
+    let optimized = Cascades::default().optimize_plan(&equi_filtered).unwrap();
+    let LogicalPlan::Join(join) = &optimized else {
+        panic!("Expected the filter to become a join, got {}", optimized.display_indent());
+    };
+    assert_eq!(join.join_type, datafusion_common::JoinType::Inner);
+    assert_eq!(join.on, vec![(col("t1.a1"), col("t2.a2"))]);
+    assert!(join.filter.is_none());
+
+    // Conjuncts that are not join keys are left in a filter, which is pushed down to the table it reads
+    let residual_filtered = cross_join()
+        .filter(col("t1.a1").eq(col("t2.a2")).and(col("t1.a1").gt(lit(5))))
+        .unwrap()
+        .build()
+        .unwrap();
+    let optimized = Cascades::default().optimize_plan(&residual_filtered).unwrap();
+    let LogicalPlan::Join(join) = &optimized else {
+        panic!("Expected the filter to become a join, got {}", optimized.display_indent());
+    };
+    assert_eq!(join.on, vec![(col("t1.a1"), col("t2.a2"))]);
+    assert!(join.filter.is_none());
+    let filters: Vec<_> = [join.left.as_ref(), join.right.as_ref()]
+        .into_iter()
+        .filter_map(|input| match input {
+            LogicalPlan::Filter(filter) => Some(&filter.predicate),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(filters, vec![&col("t1.a1").gt(lit(5))]);
+}
+
+#[test]
+fn test_seed_filter_over_cross_joins_as_inner_joins() {
+    // FROM t1, t2, t3 WHERE t1.a1 = t2.a2 AND t3.a3 = t2.a2 AND t1.a1 > 5
+    let plan = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(1000)))
+        .cross_join(test_utils::build_table_scan("t2", &["a2"], Some(1000)))
+        .unwrap()
+        .cross_join(test_utils::build_table_scan("t3", &["a3"], Some(1000)))
+        .unwrap()
+        .filter(
+            col("t1.a1")
+                .eq(col("t2.a2"))
+                .and(col("t3.a3").eq(col("t2.a2")))
+                .and(col("t1.a1").gt(lit(5))),
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+    let start_expression = root.borrow().start_expression.clone().unwrap();
+    let LogicalPlan::Join(join) = start_expression.op().borrow().clone() else {
+        panic!("Expected the root to be seeded as a join");
+    };
+    assert_eq!(join.join_type, JoinType::Inner);
+    assert_eq!(join.on, vec![(col("t2.a2"), col("t3.a3"))]);
+
+    // The nested cross join is keyed too, and the filter on t1 is seeded below it
+    let left = Rc::clone(&start_expression.operands()[0]);
+    let left_expression = left.borrow().start_expression.clone().unwrap();
+    let LogicalPlan::Join(left_join) = left_expression.op().borrow().clone() else {
+        panic!("Expected the left input to be seeded as a join");
+    };
+    assert_eq!(left_join.on, vec![(col("t1.a1"), col("t2.a2"))]);
+    let t1_expression = left_expression.operands()[0].borrow().start_expression.clone().unwrap();
+    let LogicalPlan::Filter(filter) = t1_expression.op().borrow().clone() else {
+        panic!("Expected t1 to be seeded under its filter");
+    };
+    assert_eq!(filter.predicate, col("t1.a1").gt(lit(5)));
+
+    // Costing the plan leaves it as written, cross joins included
+    let inner_joins = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(1000)))
+        .filter(col("t1.a1").gt(lit(5)))
+        .unwrap()
+        .join(
+            test_utils::build_table_scan("t2", &["a2"], Some(1000)),
+            JoinType::Inner,
+            (vec!["t1.a1"], vec!["t2.a2"]),
+            None,
+        )
+        .unwrap()
+        .join(
+            test_utils::build_table_scan("t3", &["a3"], Some(1000)),
+            JoinType::Inner,
+            (vec!["t2.a2"], vec!["t3.a3"]),
+            None,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+    assert!(cascades.cost_of_plan(&plan) > cascades.cost_of_plan(&inner_joins));
+}
+
+#[test]
+fn test_risk_averse_picks_tighter_cost_band() {
+    // u1 ⋈ u2 has no pre-canned selectivity and uses the default, u2 ⋈ u3 is estimated from NDVs, both at 0.01
+    let plan = LogicalPlanBuilder::from(test_utils::build_table_scan("u1", &["a1"], Some(1000)))
+        .join_with_expr_keys(
+            test_utils::build_table_scan("u2", &["a2", "b2"], Some(100)),
+            JoinType::Inner,
+            (vec![col("u1.a1")], vec![col("u2.a2")]),
+            None,
+        )
+        .unwrap()
+        .join_with_expr_keys(
+            test_utils::build_table_scan("u3", &["b3"], Some(1000)),
+            JoinType::Inner,
+            (vec![col("u2.b2")], vec![col("u3.b3")]),
+            None,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+    let build_cascades = || {
+        let mut cost_model = CostModel::default();
+        cost_model.set_default_join_selectivity(0.01);
+        cost_model.set_column_stats("u2", "b2", ColumnStats::with_ndv(100));
+        cost_model.set_column_stats("u3", "b3", ColumnStats::with_ndv(100));
+        Cascades::builder().cost_model(cost_model).build()
+    };
+    let sources = |tables: &[&str]| tables.iter().map(|table| table.to_string()).collect::<BTreeSet<_>>();
+    let joins_first = |mexpr: &disagg_optimizer::cascades::mexpr::MExpr, tables: &[&str]| {
+        mexpr.operands().iter().any(|operand| util::get_group_sources(operand) == sources(tables))
+    };
+
+    // (u1 ⋈ u2) ⋈ u3 and u1 ⋈ (u2 ⋈ u3) both produce 1000 rows at the first join and 10000 at the second
+    let mut cascades = build_cascades();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+    cascades.optimize(root.clone());
+    let mexprs = root.borrow().equivalent_logical_mexprs.borrow().clone();
+    let band_joining_first = |tables: &[&str]| {
+        mexprs.iter().find(|mexpr| joins_first(mexpr, tables)).unwrap().cost_band()
+    };
+    let uncertain_first = band_joining_first(&["u1", "u2"]);
+    let certain_first = band_joining_first(&["u2", "u3"]);
+    println!("Joining u1 ⋈ u2 first {:?}, u2 ⋈ u3 first {:?}", uncertain_first, certain_first);
+    assert!((uncertain_first.mid - certain_first.mid).abs() < 1e-9);
+    assert!(uncertain_first.low <= uncertain_first.mid && uncertain_first.mid <= uncertain_first.high);
+
+    // Joining u1 ⋈ u2 first keeps the uncertain estimate under the smaller join, the worst case is cheaper
+    assert!(uncertain_first.high < certain_first.high);
+    let mut cascades = build_cascades();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    cascades.optimize_risk_averse(root.clone());
+    let cheapest = root.borrow().cheapest_logical_expression.clone().unwrap();
+    assert!(joins_first(&cheapest, &["u1", "u2"]));
+    assert_eq!(cheapest.cost(), cheapest.cost_band().high);
+}
+
+#[test]
+fn test_random_join_graphs() {
+    for seed in 0..24_u64 {
+        let n_tables = 1 + (seed as usize % test_utils::MAX_RANDOM_JOIN_TABLES);
+        let edge_density = (seed % 4) as f64 / 4.0;
+        let plan = test_utils::random_join_graph(seed, n_tables, edge_density);
+
+        let mut cascades = Cascades::default();
+        let optimized = cascades.optimize_plan(&plan).unwrap();
+
+        // The graph is connected, so every join of the cheapest plan can join on a condition
+        optimized
+            .apply(|node| {
+                if let LogicalPlan::Join(join) = node {
+                    assert!(
+                        !join.on.is_empty() || join.filter.is_some(),
+                        "Seed {} picked a cross join in\n{}",
+                        seed,
+                        optimized.display_indent()
+                    );
+                }
+                Ok(TreeNodeRecursion::Continue)
+            })
+            .unwrap();
+        if let Err(violations) = cascades.validate_memo() {
+            panic!("Seed {} left an invalid memo : {:?}", seed, violations);
+        }
+    }
+}
+
+#[test]
+fn test_stacked_filters_are_merged() {
+    let stacked = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1", "b1"], Some(1000)))
+        .filter(col("t1.a1").eq(lit(1)))
+        .unwrap()
+        .filter(col("t1.b1").eq(lit(2)))
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::default();
+    let optimized = cascades.optimize_plan(&stacked).unwrap();
+    let LogicalPlan::Filter(filter) = &optimized else {
+        panic!("Expected a filter, got {}", optimized.display_indent());
+    };
+    assert_eq!(filter.predicate, col("t1.a1").eq(lit(1)).and(col("t1.b1").eq(lit(2))));
+    assert!(matches!(filter.input.as_ref(), LogicalPlan::TableScan(_)));
+
+    // The merged filter keeps the same rows, without paying for the rows in between the two filters
+    assert!(cascades.cost_of_plan(&optimized) < cascades.cost_of_plan(&stacked));
+}
+
+/// Content hashes of the cheapest mexprs of a group and its operands, top down
+fn cheapest_mexpr_hashes(group: &Rc<RefCell<disagg_optimizer::cascades::group::Group>>) -> Vec<u64> {
+    let cheapest = group.borrow().cheapest_logical_expression.clone().unwrap();
+    let mut hashes = vec![cheapest.hash()];
+    for operand in cheapest.operands() {
+        hashes.extend(cheapest_mexpr_hashes(operand));
+    }
+    hashes
+}
+
+#[test]
+fn test_tiebreak_seed() {
+    // Plans are compared by the content hashes of their cheapest mexprs, group by group
+    let cheapest_tree = |seed: u64| {
+        let mut cascades = Cascades::default().with_tiebreak_seed(seed);
+        let root = cascades.seed_memo("123");
+        cascades.optimize(root.clone());
+        let cost = root.borrow().get_group_cost();
+        (cheapest_mexpr_hashes(&root), cost)
+    };
+
+    assert_eq!(cheapest_tree(7), cheapest_tree(7));
+
+    // Commuted joins cost the same, so the seed picks between them without changing the cost
+    let outcomes: Vec<_> = (0..16).map(cheapest_tree).collect();
+    let distinct_trees: HashSet<_> = outcomes.iter().map(|(tree, _)| tree.clone()).collect();
+    assert!(distinct_trees.len() > 1);
+    assert!(outcomes.iter().all(|(_, cost)| *cost == outcomes[0].1));
+}
+
+#[test]
+fn test_tie_break_prefers_fewer_cross_joins() {
+    // Single row tables and no cross join penalty, so every join order costs the same. The seeded plan starts with
+    // the cross join t1 × t3, and is explored first
+    let plan = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(1)))
+        .cross_join(test_utils::build_table_scan("t3", &["b3"], Some(1)))
+        .unwrap()
+        .join(
+            test_utils::build_table_scan("t2", &["a2", "b2"], Some(1)),
+            JoinType::Inner,
+            (vec!["a1", "b3"], vec!["a2", "b2"]),
+            None,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+    let cheapest_tree = |tie_break: TieBreak| {
+        let mut cost_model = CostModel::default();
+        cost_model.set_cross_join_penalty(1.0);
+        let mut cascades = Cascades::builder().cost_model(cost_model).tie_break(tie_break).build();
+        let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+        cascades.optimize(root.clone());
+        let cost = root.borrow().get_group_cost();
+        (util::get_cheapest_tree(root), cost)
+    };
+
+    let (first_explored, first_explored_cost) = cheapest_tree(TieBreak::FirstExplored);
+    let (simplest, simplest_cost) = cheapest_tree(TieBreak::default());
+    println!("First explored\n{}\nSimplest\n{}", first_explored, simplest);
+    assert_eq!(first_explored_cost, simplest_cost);
+    assert!(first_explored.contains("Cross Join"));
+    assert!(!simplest.contains("Cross Join"));
+}
+
+#[test]
+fn test_is_reorderable() {
+    assert_eq!(Cascades::is_reorderable(&test_utils::build_join_chain("123")), Ok(()));
+
+    let right_join = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(1000)))
+        .join_on(
+            test_utils::build_table_scan("t2", &["a2"], Some(1000)),
+            datafusion_common::JoinType::Right,
+            vec![col("t1.a1").eq(col("t2.a2"))],
+        )
+        .unwrap()
+        .limit(0, Some(10))
+        .unwrap()
+        .build()
+        .unwrap();
+    let reasons = Cascades::is_reorderable(&right_join).unwrap_err();
+    assert_eq!(reasons, vec!["Limit nodes are not supported", "Right join is not reordered"]);
+}
+
+#[test]
+fn test_max_assoc_depth() {
+    let memo_size = |cascades: &mut Cascades| {
+        let root = cascades.seed_memo("123456");
+        cascades.optimize(root.clone());
+        cascades.get_memo().len()
+    };
+
+    let unbounded = memo_size(&mut Cascades::new());
+    let bounded = memo_size(&mut Cascades::builder().max_assoc_depth(3).build());
+    println!("{} memo entries unbounded, {} with a depth limit of 3", unbounded, bounded);
+    assert!(bounded < unbounded);
+}
+
+#[tokio::test]
+async fn test_join_estimates() {
+    let plan = test_utils::generate_logical_plan(vec![5, 5, 5, 5]).await;
+    let ctx = test_utils::setup_tables(4).unwrap();
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    cascades.optimize(root.clone());
+
+    // The root is a projection, so its join input is the first join listed
+    let estimates = cascades.join_estimates(root, &ctx).await.unwrap();
+    for estimate in &estimates {
+        println!("{:?}", estimate);
+    }
+    assert_eq!(estimates.len(), 3);
+    assert_eq!(estimates[0].sources.len(), 4);
+    assert!(estimates.iter().all(|estimate| estimate.q_error.is_finite() && estimate.q_error >= 1.0));
+}
+
+#[test]
+fn test_filter_between_joins_does_not_block_reordering() {
+    // (Filter(t1.a1 < 10)(t1 ⋈ t2)) ⋈ t3, where joining the small t3 to t2 first is cheapest
+    let plan = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(100000)))
+        .join_with_expr_keys(
+            test_utils::build_table_scan("t2", &["a2", "b2"], Some(100000)),
+            datafusion_common::JoinType::Inner,
+            (vec![col("a1")], vec![col("a2")]),
+            None,
+        )
+        .unwrap()
+        .filter(col("t1.a1").lt(lit(10)))
+        .unwrap()
+        .join_with_expr_keys(
+            test_utils::build_table_scan("t3", &["b3"], Some(10)),
+            datafusion_common::JoinType::Inner,
+            (vec![col("b2")], vec![col("b3")]),
+            None,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    cascades.optimize(root.clone());
+
+    let t2_t3 = BTreeSet::from(["t2".to_string(), "t3".to_string()]);
+    assert!(cascades.materialized_subsets().contains(&t2_t3));
+    assert!(root.borrow().get_group_cost() < cascades.baseline_cost().unwrap());
+
+    let cheapest = util::get_cheapest_logical_plan(&root).unwrap();
+    let LogicalPlan::Join(join) = &cheapest else {
+        panic!("Expected the filter to be below the joins, got {}", cheapest.display_indent());
+    };
+    assert!(util::get_operator_name(&join.left) == "Filter" || util::get_operator_name(&join.right) == "Filter");
+}
+
+#[test]
+fn test_structural_keys() {
+    let unique_groups = |mut cascades: Cascades| {
+        let root = cascades.seed_memo("123");
+        cascades.optimize(root.clone());
+        let groups: HashSet<_> = cascades.get_unique_groups_in_memo().iter().map(Rc::as_ptr).collect();
+        (groups.len(), util::get_cheapest_tree(root))
+    };
+
+    // Three base relations, three joins of two of them and the root join of all three
+    let (structural_groups, structural_tree) = unique_groups(Cascades::new().with_structural_keys());
+    let (hashed_groups, _) = unique_groups(Cascades::new());
+    assert_eq!(structural_groups, 7);
+    assert_eq!(structural_groups, hashed_groups);
+    assert_ne!(structural_tree, "None");
+}
+
+type GroupCostedEvents = Rc<RefCell<Vec<(BTreeSet<String>, f64)>>>;
+
+#[test]
+fn test_group_costed_events() {
+    let events: GroupCostedEvents = Rc::new(RefCell::new(Vec::new()));
+    let mut cascades = Cascades::new();
+    let collected = Rc::clone(&events);
+    cascades.on_group_costed(Box::new(move |sources, cost| collected.borrow_mut().push((sources, cost))));
+
+    let root = cascades.seed_memo("123");
+    cascades.optimize(root.clone());
+
+    let events = events.borrow();
+    let root_sources = util::get_group_sources(&root);
+    let (last_sources, last_cost) = events.last().expect("no group was reported");
+    assert_eq!(*last_sources, root_sources);
+    assert_eq!(*last_cost, root.borrow().get_group_cost());
+    assert!(events.iter().any(|(sources, _)| sources.len() == 1));
+}
+
+#[test]
+fn test_max_alternatives_per_group() {
+    let root_costs = |cascades: &mut Cascades| {
+        let root = cascades.seed_memo("123");
+        cascades.optimize(root.clone());
+        let mut costs: Vec<f64> = root.borrow().equivalent_plans().iter().map(|(_, cost, _)| *cost).collect();
+        costs.sort_by(f64::total_cmp);
+        costs
+    };
+
+    let unbounded = root_costs(&mut Cascades::new());
+    assert!(unbounded.len() > 2);
+
+    // The two-relation groups hold exactly two mexprs, so only the root loses alternatives
+    let mut cascades = Cascades::builder().max_alternatives_per_group(2).build();
+    let bounded = root_costs(&mut cascades);
+    assert_eq!(bounded, unbounded[..2]);
+    for group in cascades.get_memo().values() {
+        assert!(group.borrow().equivalent_logical_mexprs.borrow().len() <= 2);
+    }
+}
+
+#[test]
+fn test_costed_mexprs() {
+    // The chain's join keys are a single equivalence class, so "123" is a clique every split of which is joinable
+    let mut cascades = Cascades::new();
+    let root = cascades.seed_memo("123");
+    cascades.optimize(root);
+
+    // One mexpr costed per ordered split of every subset : the 3 scans, 2 per pair and 6 for the root. Every split
+    // is needed to find the cheapest, rederived joins are dropped by the memo before they are costed
+    assert_eq!(cascades.costed_mexprs(), 3 + 3 * 2 + 6);
+}
+
+#[tokio::test]
+async fn test_cheapest_first_explore_order() {
+    let optimize = |plan: &LogicalPlan, explore_order: ExploreOrder| {
+        let mut cascades = Cascades::builder().explore_order(explore_order).build();
+        let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+        cascades.optimize_with_mst_seed(root.clone(), plan).unwrap();
+        if let Err(violations) = cascades.validate_memo() {
+            panic!("{:?} left an invalid memo : {:?}", explore_order, violations);
+        }
+        let cost = root.borrow().get_group_cost();
+        (cascades.costed_mexprs(), cost)
+    };
+
+    let plan = test_utils::generate_logical_plan(vec![1000, 10, 500, 20, 2000]).await;
+    let (fifo_costed, fifo_cost) = optimize(&plan, ExploreOrder::Fifo);
+    let (cheapest_first_costed, cheapest_first_cost) = optimize(&plan, ExploreOrder::CheapestFirst);
+    println!("Costed {fifo_costed} mexprs in FIFO order, {cheapest_first_costed} cheapest first");
+    assert!(cheapest_first_costed < fifo_costed);
+    assert_eq!(cheapest_first_cost, fifo_cost);
+
+    // Both orders find the same cheapest plan off a chain too
+    let mut plans = vec![test_utils::generate_logical_plan(vec![10, 100000, 10, 100000, 10]).await];
+    plans.extend((0..40).map(|seed| test_utils::random_join_graph(seed, 6, 0.3)));
+    for plan in &plans {
+        let (_, fifo_cost) = optimize(plan, ExploreOrder::Fifo);
+        let (_, cheapest_first_cost) = optimize(plan, ExploreOrder::CheapestFirst);
+        assert_eq!(cheapest_first_cost, fifo_cost, "Different costs for\n{}", plan.display_indent());
+    }
+}
+
+#[test]
+fn test_implicit_commutativity() {
+    let optimize = |cascades: Cascades| {
+        let mut cascades = cascades;
+        let root = cascades.seed_memo("1234");
+        cascades.optimize(root.clone());
+        // Several memo keys can point at the same group
+        let mut seen = HashSet::new();
+        let mexpr_count: usize = cascades
+            .get_memo()
+            .values()
+            .filter(|group| seen.insert(Rc::as_ptr(group)))
+            .map(|group| group.borrow().equivalent_logical_mexprs.borrow().len())
+            .sum();
+        (cascades, root, mexpr_count)
+    };
+
+    let (_explicit, explicit_root, explicit_mexprs) = optimize(Cascades::new());
+    let (_implicit, implicit_root, implicit_mexprs) = optimize(Cascades::new().with_implicit_commutativity());
+    println!("{} mexprs with explicit commutativity, {} with implicit", explicit_mexprs, implicit_mexprs);
+
+    // Each join is stored once instead of twice, only the 4 scans are not halved
+    assert_eq!(implicit_mexprs - 4, (explicit_mexprs - 4) / 2);
+    assert_eq!(implicit_root.borrow().get_group_cost(), explicit_root.borrow().get_group_cost());
+
+    // Listing the commuted joins too recovers every tree of the explicit memo
+    let explicit_trees: HashSet<String> = util::get_all_possible_trees(explicit_root).into_iter().collect();
+    let implicit_trees: HashSet<String> =
+        util::get_all_possible_trees_with_commuted(implicit_root).into_iter().collect();
+    assert_eq!(implicit_trees, explicit_trees);
+}
+
+#[test]
+fn test_implicit_commutativity_seeds_canonical_join() {
+    let root_mexprs = |join_nodes: &str| {
+        let mut cascades = Cascades::new().with_implicit_commutativity();
+        let root = cascades.seed_memo(join_nodes);
+        cascades.optimize(root.clone());
+        root.borrow().equivalent_logical_mexprs.borrow().len()
+    };
+
+    // Whichever order the join is seeded in, its group stores it once
+    assert_eq!(root_mexprs("12"), 1);
+    assert_eq!(root_mexprs("21"), 1);
+    assert_eq!(root_mexprs("321"), root_mexprs("123"));
+}
+
+#[test]
+fn test_shared_subjoin() {
+    let subjoin = |alias: &str| {
+        LogicalPlanBuilder::from(test_utils::build_join_chain("12"))
+            .alias(alias)
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+    let plan = LogicalPlanBuilder::from(subjoin("l"))
+        .join_with_expr_keys(subjoin("r"), JoinType::Inner, (vec![col("l.a1")], vec![col("r.a2")]), None)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan)));
+    assert!(cascades.shares_subtrees());
+
+    // Both aliases are over the one t1 ⋈ t2 group
+    let operands = root.borrow().start_expression.as_ref().unwrap().operands().clone();
+    let alias_input = |alias: &Rc<RefCell<Group>>| Rc::clone(&alias.borrow().start_expression.as_ref().unwrap().operands()[0]);
+    let subjoin_group = alias_input(&operands[0]);
+    assert!(Rc::ptr_eq(&subjoin_group, &alias_input(&operands[1])));
+    let canonicalized = |group: &Rc<RefCell<Group>>| group.borrow().start_expression.as_ref().unwrap().canonicalized().to_string();
+    let subjoin_groups = cascades
+        .get_unique_groups_in_memo()
+        .iter()
+        .filter(|group| canonicalized(group) == canonicalized(&subjoin_group))
+        .map(|group| group.borrow().group_id)
+        .collect::<HashSet<_>>();
+    assert_eq!(subjoin_groups.len(), 1);
+
+    // The shared subjoin is only charged once
+    cascades.optimize(root.clone());
+    let input_costs: f64 = operands.iter().map(|operand| operand.borrow().get_group_cost()).sum();
+    let join_cost = JOIN_COST_PER_ROW * root.borrow().get_group_row_count() as f64;
+    let expected_cost = join_cost + input_costs - subjoin_group.borrow().get_group_cost();
+    assert!((root.borrow().get_group_cost() - expected_cost).abs() < 1e-9);
+
+    let mut unshared = Cascades::new();
+    unshared.seed_memo("12");
+    assert!(!unshared.shares_subtrees());
+}
+
+#[test]
+fn test_optimize_with_hint() {
+    let scan = |n: usize, rows: usize| test_utils::build_table_scan(&format!("t{}", n), &[&format!("a{}", n)], Some(rows));
+    let mut builder = LogicalPlanBuilder::from(scan(1, 10));
+    for (n, rows) in [(2, 100), (3, 1000), (4, 10000)] {
+        let keys = (vec![col(format!("t{}.a{}", n - 1, n - 1))], vec![col(format!("t{}.a{}", n, n))]);
+        builder = builder.join_with_expr_keys(scan(n, rows), JoinType::Inner, keys, None).unwrap();
+    }
+    let plan = builder.build().unwrap();
+    let scan_order = |plan: &LogicalPlan| {
+        let mut tables = Vec::new();
+        plan.apply(|node| {
+            if let LogicalPlan::TableScan(scan) = node {
+                tables.push(scan.table_name.to_string());
+            }
+            Ok(TreeNodeRecursion::Continue)
+        })
+        .unwrap();
+        tables
+    };
+
+    let mut free = Cascades::new();
+    let free_plan = free.optimize_plan(&plan).unwrap();
+    let free_cost = free.cost_of_plan(&free_plan);
+
+    // With t3 between them, the two smallest tables cannot be joined first
+    let order: Vec<String> = ["t4", "t1", "t3", "t2"].iter().map(|table| table.to_string()).collect();
+    let mut hinted = Cascades::new();
+    let hinted_plan = hinted.optimize_with_hint(&plan, order.clone()).unwrap();
+    println!("{}", hinted_plan.display_indent());
+    assert_eq!(scan_order(&hinted_plan), order);
+    let hinted_cost = hinted.cost_of_plan(&hinted_plan);
+    assert!(hinted_cost > free_cost);
+}
+
+#[test]
+fn test_warm_start_reuses_unchanged_groups() {
+    // The distinct values of a 5 table chain of joins, joined with t6. A filter is added to t6 in the new query, the
+    // join tree below the aggregate is reordered on its own and untouched by the change
+    let query = |t6_filter: Option<datafusion_expr::Expr>| {
+        let mut builder = LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(1000)));
+        for i in 2..=5 {
+            let columns = [format!("a{}", i), format!("b{}", i)];
+            let scan = test_utils::build_table_scan(&format!("t{}", i), &[&columns[0], &columns[1]], Some(100 * i));
+            builder = builder
+                .join(scan, JoinType::Inner, (vec![format!("a{}", i - 1)], vec![format!("b{}", i)]), None)
+                .unwrap();
+        }
+        let mut t6 = LogicalPlanBuilder::from(test_utils::build_table_scan("t6", &["a6", "b6"], Some(5000)));
+        if let Some(predicate) = t6_filter {
+            t6 = t6.filter(predicate).unwrap();
+        }
+        builder
+            .aggregate(vec![col("t5.a5")], Vec::<datafusion_expr::Expr>::new())
+            .unwrap()
+            .join(t6.build().unwrap(), JoinType::Inner, (vec!["a5"], vec!["b6"]), None)
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+    let unique_groups = |cascades: &Cascades| {
+        cascades.get_unique_groups_in_memo().iter().map(Rc::as_ptr).collect::<HashSet<_>>().len()
+    };
+
+    let mut prev = Cascades::new();
+    let prev_root = prev.gen_group_logical_plan(Rc::new(RefCell::new(query(None))));
+    prev.optimize(prev_root);
+
+    let new_plan = query(Some(col("t6.a6").gt(lit(5))));
+    let (warm, warm_root) = Cascades::reoptimize_with_memo(prev, &new_plan);
+    let warm_groups = unique_groups(&warm);
+    println!("Reused {} of {} groups", warm.reused_groups(), warm_groups);
+
+    // Only the filter and the groups above it are new, the replaced join with t6 is dropped from the memo
+    assert!(warm.reused_groups() * 2 > warm_groups);
+    assert_eq!(warm_groups - warm.reused_groups(), 2);
+    assert!(warm.validate_memo().is_ok());
+
+    let mut cold = Cascades::new();
+    let cold_root = cold.gen_group_logical_plan(Rc::new(RefCell::new(new_plan)));
+    cold.optimize(cold_root.clone());
+    assert_eq!(unique_groups(&cold), warm_groups);
+    assert_eq!(warm_root.borrow().get_group_cost(), cold_root.borrow().get_group_cost());
+}
+
+#[test]
+fn test_registered_selectivity_fn() {
+    let join = || {
+        LogicalPlanBuilder::from(test_utils::build_table_scan("t1", &["a1"], Some(1000)))
+            .join(
+                test_utils::build_table_scan("t2", &["a2"], Some(100)),
+                JoinType::Inner,
+                (vec!["a1"], vec!["a2"]),
+                None,
+            )
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+
+    let seen_ndvs = Rc::new(RefCell::new(Vec::new()));
+    let collected = Rc::clone(&seen_ndvs);
+    let mut cascades = Cascades::new();
+    cascades.set_column_stats("t1", "a1", ColumnStats::with_ndv(10));
+    cascades.register_selectivity_fn(Box::new(move |predicate, left, right| {
+        assert!(matches!(predicate, datafusion_expr::Expr::BinaryExpr(_)));
+        collected.borrow_mut().push((left.ndv, right.ndv));
+        0.25
+    }));
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(join())));
+    cascades.optimize(root.clone());
+
+    // Both orientations of the key are estimated by the closure, ahead of the canned 0.001 for t1 ⋈ t2
+    assert!(!seen_ndvs.borrow().is_empty());
+    assert!(seen_ndvs.borrow().iter().all(|ndvs| *ndvs == (Some(10), None) || *ndvs == (None, Some(10))));
+    assert_eq!(root.borrow().get_group_row_count(), 25_000);
+    let input_costs = 1000.0 + 100.0;
+    let expected_cost = JOIN_COST_PER_ROW * 25_000.0 + input_costs;
+    assert!((root.borrow().get_group_cost() - expected_cost).abs() < 1e-9);
+
+    let mut default = Cascades::new();
+    let default_root = default.gen_group_logical_plan(Rc::new(RefCell::new(join())));
+    default.optimize(default_root.clone());
+    assert_eq!(default_root.borrow().get_group_row_count(), 100);
+}
+
+/// (t1 ⟕ t2) ◦ t3 over tables with data, joined on t1.a1 = t2.a2 and t2.a2 = t3.a3
+async fn build_outer_join_plan(top_join_type: JoinType) -> LogicalPlan {
+    let ctx = test_utils::setup_tables(3).unwrap();
+    let scan = async |name: &str| ctx.table(name).await.unwrap().logical_plan().clone();
+    LogicalPlanBuilder::from(scan("t1").await)
+        .join_on(scan("t2").await, JoinType::Left, vec![col("t1.a1").eq(col("t2.a2"))])
+        .unwrap()
+        .join_on(scan("t3").await, top_join_type, vec![col("t2.a2").eq(col("t3.a3"))])
+        .unwrap()
+        .build()
+        .unwrap()
+}
+
+fn count_joins(plan: &LogicalPlan, join_type: JoinType) -> usize {
+    let mut count = 0;
+    plan.apply(|node| {
+        if matches!(node, LogicalPlan::Join(join) if join.join_type == join_type) {
+            count += 1;
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })
+    .unwrap();
+    count
+}
+
+#[tokio::test]
+async fn test_legal_outer_join_reorder() {
+    // (t1 ⟕ t2) ⟕ t3 ≡ t1 ⟕ (t2 ⟕ t3), the equi join on t2 rejects the rows padded for t1 either way
+    let plan = build_outer_join_plan(JoinType::Left).await;
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+    cascades.optimize(root.clone());
+
+    let t2_t3: BTreeSet<String> = ["t2", "t3"].iter().map(|t| t.to_string()).collect();
+    assert!(cascades.materialized_subsets().contains(&t2_t3));
+
+    // Both joins stay left joins whichever order is picked
+    let reordered = util::get_cheapest_logical_plan(&root).unwrap();
+    println!("{}", reordered.display_indent());
+    assert_eq!(count_joins(&reordered, JoinType::Left), 2);
+    test_utils::assert_same_results(&plan, &reordered).await;
+}
+
+#[tokio::test]
+async fn test_illegal_outer_join_reorder() {
+    // (t1 ⟕ t2) ⋈ t3 is not t1 ⟕ (t2 ⋈ t3), the inner join drops the rows padded for t1 only in the original order
+    let plan = build_outer_join_plan(JoinType::Inner).await;
+    let mut cascades = Cascades::new();
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+    cascades.optimize(root.clone());
+
+    let t2_t3: BTreeSet<String> = ["t2", "t3"].iter().map(|t| t.to_string()).collect();
+    assert!(!cascades.materialized_subsets().contains(&t2_t3));
+
+    let reordered = util::get_cheapest_logical_plan(&root).unwrap();
+    assert_eq!(count_joins(&reordered, JoinType::Left), 1);
+    test_utils::assert_same_results(&plan, &reordered).await;
+}
+
+#[tokio::test]
+async fn test_reorder_keeps_range_join_predicate() {
+    // (t1 ⋈ t2) ⋈ t3 joined on t1.a1 = t2.a2 and t1.a1 < t3.a3, reordered to join t1 and t3 first
+    let ctx = test_utils::setup_tables(3).unwrap();
+    let scan = async |name: &str| ctx.table(name).await.unwrap().logical_plan().clone();
+    let plan = LogicalPlanBuilder::from(scan("t1").await)
+        .join_on(scan("t2").await, JoinType::Inner, vec![col("t1.a1").eq(col("t2.a2"))])
+        .unwrap()
+        .join_on(scan("t3").await, JoinType::Inner, vec![col("t1.a1").lt(col("t3.a3"))])
+        .unwrap()
+        .build()
+        .unwrap();
+    let t1_t3: BTreeSet<String> = ["t1".to_string(), "t3".to_string()].into();
+    let mut cascades = Cascades::new();
+    cascades.set_cardinality_override(t1_t3.clone(), 1);
+    let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+    cascades.optimize(root.clone());
+
+    let cheapest = root.borrow().cheapest_logical_expression.clone().unwrap();
+    assert!(cheapest.operands().iter().any(|operand| util::get_group_sources(operand) == t1_t3));
+
+    // The range predicate becomes the filter of the t1 ⋈ t3 join
+    let reordered = util::get_cheapest_logical_plan(&root).unwrap();
+    println!("{}", reordered.display_indent());
+    test_utils::assert_same_results(&plan, &reordered).await;
+}
+
+#[tokio::test]
+async fn test_outer_join_rules_do_not_outlive_their_plan() {
+    // (t1 ⋈ t2) ⋈ t3 is seeded after (t1 ⟕ t2) ⋈ t3, its joins are reordered as freely as on an optimizer of its own
+    let ctx = test_utils::setup_tables(3).unwrap();
+    let scan = async |name: &str| ctx.table(name).await.unwrap().logical_plan().clone();
+    let inner_plan = LogicalPlanBuilder::from(scan("t1").await)
+        .join_on(scan("t2").await, JoinType::Inner, vec![col("t1.a1").eq(col("t2.a2"))])
+        .unwrap()
+        .join_on(scan("t3").await, JoinType::Inner, vec![col("t2.a2").eq(col("t3.a3"))])
+        .unwrap()
+        .build()
+        .unwrap();
+    let optimize = |cascades: &mut Cascades, plan: &LogicalPlan| {
+        let root = cascades.gen_group_logical_plan(Rc::new(RefCell::new(plan.clone())));
+        cascades.optimize(root.clone());
+        let logical_mexprs = root.borrow().equivalent_logical_mexprs.borrow().len();
+        (logical_mexprs, cascades.materialized_subsets().len())
+    };
+
+    let mut reused = Cascades::new();
+    optimize(&mut reused, &build_outer_join_plan(JoinType::Inner).await);
+    let reused_counts = optimize(&mut reused, &inner_plan);
+    let fresh_counts = optimize(&mut Cascades::new(), &inner_plan);
+    assert_eq!(reused_counts, fresh_counts);
+    assert_eq!(reused_counts, (8, 7));
+}
+
+#[test]
+fn test_memo_size_regression() {
+    // Baseline for the 5-table clique, exhaustive search with the default configuration : 185 memo entries, keyed on
+    // the mexprs they hold, over 31 groups with 185 logical mexprs between them. Raise the thresholds only for a change
+    // that is meant to grow the search space
+    const MAX_MEMO_ENTRIES: usize = 185;
+    const MAX_GROUPS: usize = 31;
+    const MAX_LOGICAL_MEXPRS: usize = 185;
+
+    let mut cascades = Cascades::new();
+    let root = cascades.seed_memo("12345");
+    cascades.optimize(root);
+
+    let memo_entries = cascades.get_memo().len();
+    let groups: Vec<_> = cascades
+        .get_unique_groups_in_memo()
+        .into_iter()
+        .filter({
+            let mut seen = HashSet::new();
+            move |group| seen.insert(Rc::as_ptr(group))
+        })
+        .collect();
+    let mexprs: usize = groups
+        .iter()
+        .map(|group| group.borrow().equivalent_logical_mexprs.borrow().len())
+        .sum();
+    println!("{} memo entries, {} groups, {} logical mexprs", memo_entries, groups.len(), mexprs);
+    assert!(memo_entries <= MAX_MEMO_ENTRIES, "{} memo entries, at most {} expected", memo_entries, MAX_MEMO_ENTRIES);
+    assert!(groups.len() <= MAX_GROUPS, "{} groups, at most {} expected", groups.len(), MAX_GROUPS);
+    assert!(mexprs <= MAX_LOGICAL_MEXPRS, "{} logical mexprs, at most {} expected", mexprs, MAX_LOGICAL_MEXPRS);
+}
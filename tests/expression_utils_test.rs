@@ -1,4 +1,7 @@
-use disagg_optimizer::cascades::expression_utils::{flip_equality, infer_equalities};
+use datafusion_common::Column;
+use disagg_optimizer::cascades::expression_utils::{
+    flip_comparison, flip_equality, get_unique_equalities, infer_equalities,
+};
 use datafusion_expr::Operator;
 use datafusion_expr::{BinaryExpr, Expr};
 
@@ -69,3 +72,83 @@ fn test_infer_equalities() {
         );
     }
 }
+
+#[test]
+fn test_flip_comparison() {
+    let a = Expr::Column("a".into());
+    let b = Expr::Column("b".into());
+    let binary = |left: &Expr, op: Operator, right: &Expr| {
+        Expr::BinaryExpr(BinaryExpr {
+            left: Box::new(left.clone()),
+            op,
+            right: Box::new(right.clone()),
+        })
+    };
+
+    let expected_flips = vec![
+        (Operator::Lt, Operator::Gt),
+        (Operator::Gt, Operator::Lt),
+        (Operator::LtEq, Operator::GtEq),
+        (Operator::GtEq, Operator::LtEq),
+        (Operator::Eq, Operator::Eq),
+        (Operator::NotEq, Operator::NotEq),
+    ];
+
+    for (op, flipped_op) in expected_flips {
+        // a <op> b flips to b <flipped_op> a
+        let flipped = flip_comparison(&binary(&a, op, &b));
+        assert_eq!(flipped, binary(&b, flipped_op, &a), "Unexpected flip for {:?}", op);
+        // Flipping twice gets us back the original expression
+        assert_eq!(flip_comparison(&flipped), binary(&a, op, &b));
+    }
+
+    // Non comparison expressions are returned as is
+    let sum = binary(&a, Operator::Plus, &b);
+    assert_eq!(flip_comparison(&sum), sum);
+}
+
+#[test]
+fn test_unique_equalities_prefer_cross_table_pair() {
+    let column = |table: &str, name: &str| Expr::Column(Column::new(Some(table), name));
+    let t1_a = column("t1", "a");
+    let t1_b = column("t1", "b");
+    let t2_b = column("t2", "b");
+    let t3_c = column("t3", "c");
+
+    // A single equivalence class spanning t1, t2 and t3, with two members from t1
+    let equalities = vec![
+        (t1_a.clone(), t1_b.clone()),
+        (t1_b.clone(), t2_b.clone()),
+        (t2_b.clone(), t3_c.clone()),
+    ];
+
+    let unique = get_unique_equalities(&equalities);
+    assert_eq!(unique.len(), 1);
+
+    let (left, right) = unique.into_iter().next().unwrap();
+    match (left, right) {
+        (Expr::Column(left), Expr::Column(right)) => {
+            assert_ne!(left.relation, right.relation, "Picked a same-table pair");
+        }
+        other => panic!("Expected a pair of columns, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_infer_equalities_drops_self_equalities() {
+    let a = Expr::Column("a".into());
+    let b = Expr::Column("b".into());
+    let c = Expr::Column("c".into());
+
+    let equalities = vec![a.clone().eq(a.clone()), a.clone().eq(b.clone()), b.clone().eq(c.clone())];
+    let inferred = infer_equalities(&equalities);
+
+    for equality in &inferred {
+        let Expr::BinaryExpr(BinaryExpr { left, right, .. }) = equality else {
+            panic!("Expected an equality, got {equality}");
+        };
+        assert_ne!(left, right, "Inferred a self equality {equality}");
+    }
+    assert!(!inferred.contains(&a.clone().eq(a.clone())));
+    assert!(inferred.contains(&a.clone().eq(c.clone())) || inferred.contains(&c.eq(a)));
+}